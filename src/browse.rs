@@ -0,0 +1,269 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use log::warn;
+
+use crate::json::GameSummary;
+use crate::json::Level;
+use crate::save::SaveState;
+
+const GAME_SUFFIX: &str = ".parsed.json";
+
+/// A game in the games directory along with how far the player got in it
+#[derive(Debug)]
+pub struct GameEntry {
+    pub path: PathBuf,
+    /// The file name without `.parsed.json` (e.g. `pot-wash-panic`)
+    pub name: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub homepage: Option<String>,
+    /// Size of the game file in bytes
+    pub size: u64,
+    // Indexes of the levels that are maps (messages do not count as levels)
+    maps: Vec<usize>,
+    completed: usize,
+}
+
+impl GameEntry {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let name = file_name
+            .strip_suffix(GAME_SUFFIX)
+            .unwrap_or(&file_name)
+            .to_string();
+        let size = fs::metadata(path)?.len();
+        // Only read what the list shows. Building every game would take too long
+        let game: GameSummary = serde_json::from_slice(&fs::read(path)?)?;
+        let maps = game
+            .levels
+            .iter()
+            .enumerate()
+            .filter(|(_, level)| matches!(level, Level::Map { .. }))
+            .map(|(index, _)| index)
+            .collect();
+        let mut entry = Self {
+            path: path.to_path_buf(),
+            name,
+            title: game.title,
+            author: game.metadata.author,
+            homepage: game.metadata.homepage,
+            size,
+            maps,
+            completed: 0,
+        };
+        entry.refresh_progress();
+        Ok(entry)
+    }
+
+    /// Where `play_game()` keeps the progress of this game
+    pub fn save_path(&self) -> String {
+        format!("{}.save.json", self.path.to_string_lossy())
+    }
+
+    /// Reads the save file again (after the game was played)
+    pub fn refresh_progress(&mut self) {
+        self.completed = match SaveState::read_from_file(&self.save_path()) {
+            Err(_) => 0,
+            Ok(save) => self
+                .maps
+                .iter()
+                .filter(|index| save.levels.get(**index).is_some_and(|l| l.completed))
+                .count(),
+        }
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.maps.len()
+    }
+
+    pub fn completed_count(&self) -> usize {
+        self.completed
+    }
+
+    fn progress(&self) -> f32 {
+        if self.maps.is_empty() {
+            0.0
+        } else {
+            self.completed as f32 / self.maps.len() as f32
+        }
+    }
+
+    // The best fuzzy match of the query in the title, file name or author
+    fn score(&self, query: &str) -> Option<usize> {
+        let author = self.author.as_deref().unwrap_or("");
+        [self.title.as_str(), self.name.as_str(), author]
+            .iter()
+            .filter_map(|text| fuzzy_score(query, text))
+            .min()
+    }
+}
+
+/// Loads every `*.parsed.json` game in `dir`. Games that cannot be parsed are skipped
+pub fn scan(dir: &Path) -> Result<Vec<GameEntry>, Box<dyn Error>> {
+    let mut games = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_game = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().ends_with(GAME_SUFFIX));
+        if !is_game {
+            continue;
+        }
+        match GameEntry::load(&path) {
+            Ok(game) => games.push(game),
+            Err(e) => warn!("Skipping {:?}: {}", path, e),
+        }
+    }
+    Ok(games)
+}
+
+/// How the game browser orders the games
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortBy {
+    Title,
+    Author,
+    /// Smallest file first (the games that are quickest to load and usually to play)
+    Size,
+    /// Fewest levels first
+    Levels,
+    /// Most completed first
+    Progress,
+}
+
+impl SortBy {
+    pub fn next(self) -> Self {
+        match self {
+            SortBy::Title => SortBy::Author,
+            SortBy::Author => SortBy::Size,
+            SortBy::Size => SortBy::Levels,
+            SortBy::Levels => SortBy::Progress,
+            SortBy::Progress => SortBy::Title,
+        }
+    }
+
+    fn compare(self, a: &GameEntry, b: &GameEntry) -> Ordering {
+        let by_title = || a.title.to_lowercase().cmp(&b.title.to_lowercase());
+        match self {
+            SortBy::Title => by_title(),
+            SortBy::Author => {
+                let author = |g: &GameEntry| g.author.as_deref().unwrap_or("").to_lowercase();
+                author(a).cmp(&author(b)).then_with(by_title)
+            }
+            SortBy::Size => a.size.cmp(&b.size),
+            SortBy::Levels => a.level_count().cmp(&b.level_count()).then_with(by_title),
+            SortBy::Progress => b
+                .progress()
+                .partial_cmp(&a.progress())
+                .unwrap_or(Ordering::Equal)
+                .then_with(by_title),
+        }
+    }
+}
+
+impl fmt::Display for SortBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            SortBy::Title => "title",
+            SortBy::Author => "author",
+            SortBy::Size => "size",
+            SortBy::Levels => "levels",
+            SortBy::Progress => "progress",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How well `query` matches `text` (lower is better). The characters of the query have to
+/// appear in order but may have other characters between them. None when it does not match
+pub fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let query: Vec<char> = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (first, rest) = match query.split_first() {
+        None => return Some(0),
+        Some(split) => split,
+    };
+    // Try every place the query could start and keep the one where the rest is closest together
+    (0..text.len())
+        .filter(|start| text[*start] == *first)
+        .filter_map(|start| {
+            // Matching at the start of the text is better than in the middle
+            let mut score = start.min(1);
+            let mut pos = start + 1;
+            for q in rest {
+                let found = pos + text[pos..].iter().position(|c| c == q)?;
+                score += found - pos;
+                pos = found + 1;
+            }
+            Some(score)
+        })
+        .min()
+}
+
+/// Indexes of the games that match `query`, best matches first and then ordered by `sort`
+pub fn filter(games: &[GameEntry], query: &str, sort: SortBy) -> Vec<usize> {
+    let mut matches: Vec<(usize, usize)> = games
+        .iter()
+        .enumerate()
+        .filter_map(|(index, game)| game.score(query).map(|score| (score, index)))
+        .collect();
+    matches.sort_by(|(a_score, a), (b_score, b)| {
+        a_score
+            .cmp(b_score)
+            .then_with(|| sort.compare(&games[*a], &games[*b]))
+    });
+    matches.into_iter().map(|(_, index)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(name: &str, title: &str, size: u64, maps: usize, completed: usize) -> GameEntry {
+        GameEntry {
+            path: PathBuf::from(format!("{}{}", name, GAME_SUFFIX)),
+            name: name.to_string(),
+            title: title.to_string(),
+            author: None,
+            homepage: None,
+            size,
+            maps: (0..maps).collect(),
+            completed,
+        }
+    }
+
+    #[test]
+    fn scores_fuzzy_matches() {
+        assert_eq!(fuzzy_score("", "Mirror Isles"), Some(0));
+        assert_eq!(fuzzy_score("mirror", "Mirror Isles"), Some(0));
+        assert_eq!(fuzzy_score("isles", "Mirror Isles"), Some(1));
+        assert_eq!(fuzzy_score("mi", "Mirror Isles"), Some(0));
+        assert!(fuzzy_score("mis", "Mirror Isles") > fuzzy_score("mir", "Mirror Isles"));
+        assert_eq!(fuzzy_score("sim", "Mirror Isles"), None);
+    }
+
+    #[test]
+    fn filters_and_sorts() {
+        let games = vec![
+            game("pot-wash-panic", "Pot Wash Panic!", 300, 10, 0),
+            game("swapbot", "SwapBot", 100, 20, 20),
+            game("push", "Push", 200, 5, 1),
+        ];
+        assert_eq!(filter(&games, "", SortBy::Title), vec![0, 2, 1]);
+        assert_eq!(filter(&games, "", SortBy::Size), vec![1, 2, 0]);
+        assert_eq!(filter(&games, "", SortBy::Levels), vec![2, 0, 1]);
+        assert_eq!(filter(&games, "", SortBy::Progress), vec![1, 2, 0]);
+        // The closest match comes first no matter how they are sorted
+        assert_eq!(filter(&games, "pus", SortBy::Size), vec![2]);
+        assert_eq!(filter(&games, "p", SortBy::Size), vec![2, 0, 1]);
+        assert_eq!(filter(&games, "wp", SortBy::Size), vec![1, 0]);
+    }
+}
@@ -0,0 +1,110 @@
+use std::io;
+use std::io::Write;
+use std::time;
+
+use serde_json::json;
+
+/// Passes everything through to `inner` and also records it into an asciicast v2 file
+/// (see https://github.com/asciinema/asciinema/blob/develop/doc/asciicast-v2.md).
+/// Each `flush()` (the tui Terminal flushes once per frame) becomes one output event.
+pub struct CastWriter<W: Write, C: Write> {
+    inner: W,
+    cast: C,
+    start: time::Instant,
+    // When set, frames are this far apart instead of using the wall clock (for scripted replays)
+    frame_interval: Option<time::Duration>,
+    frames: u32,
+    pending: Vec<u8>,
+}
+
+impl<W: Write, C: Write> CastWriter<W, C> {
+    pub fn new(
+        inner: W,
+        mut cast: C,
+        width: u16,
+        height: u16,
+        frame_interval: Option<time::Duration>,
+    ) -> io::Result<Self> {
+        let timestamp = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": timestamp,
+        });
+        writeln!(cast, "{}", header)?;
+
+        Ok(Self {
+            inner,
+            cast,
+            start: time::Instant::now(),
+            frame_interval,
+            frames: 0,
+            pending: vec![],
+        })
+    }
+
+    fn elapsed(&self) -> f64 {
+        match self.frame_interval {
+            None => self.start.elapsed().as_secs_f64(),
+            Some(interval) => (interval * self.frames).as_secs_f64(),
+        }
+    }
+}
+
+impl<W: Write, C: Write> Write for CastWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.pending.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        if !self.pending.is_empty() {
+            let data = String::from_utf8_lossy(&self.pending).to_string();
+            let event = json!([self.elapsed(), "o", data]);
+            writeln!(self.cast, "{}", event)?;
+            self.cast.flush()?;
+            self.pending.clear();
+            self.frames += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_a_frame_per_flush() {
+        let mut screen = vec![];
+        let mut cast = vec![];
+        {
+            let interval = Some(time::Duration::from_millis(500));
+            let mut w = CastWriter::new(&mut screen, &mut cast, 80, 24, interval).unwrap();
+            write!(w, "hello ").unwrap();
+            write!(w, "world").unwrap();
+            w.flush().unwrap();
+            w.flush().unwrap(); // nothing new to record
+            write!(w, "\u{1b}[2J").unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(screen, b"hello world\x1b[2J");
+
+        let cast = String::from_utf8(cast).unwrap();
+        let lines: Vec<serde_json::Value> = cast
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 80);
+        assert_eq!(lines[1], json!([0.0, "o", "hello world"]));
+        assert_eq!(lines[2], json!([0.5, "o", "\u{1b}[2J"]));
+    }
+}
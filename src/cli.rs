@@ -1,40 +1,79 @@
 extern crate env_logger;
-extern crate hex;
-extern crate rand;
-extern crate rand_xorshift;
-extern crate serde;
-extern crate serde_json;
 extern crate termion;
 
 use log::{debug, info};
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::stdin;
 use std::io::stdout;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process;
+use std::rc::Rc;
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::TryRecvError;
 use std::thread;
 use std::time;
 
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+use clap::ArgMatches;
 use termion::event::Key;
 use termion::input::TermRead;
 
-use crate::color::ColorSpace;
-use crate::debugger::ScreenDumper;
-use crate::engine::Engine;
-use crate::engine::EngineInput;
-use crate::model::board::Board;
-use crate::model::game::GameData;
-use crate::model::game::SpriteLookup;
-use crate::parser;
-use crate::save::SaveState;
-use crate::terminal::Attribution;
-use crate::terminal::Help;
-use crate::terminal::PlayPause;
-use crate::terminal::RecordingInfo;
-use crate::terminal::Spinner;
+use puzzlescript::browse;
+use puzzlescript::browse::SortBy;
+use puzzlescript::cast::CastWriter;
+use puzzlescript::color::ColorSpace;
+use puzzlescript::debugger::ScreenDumper;
+use puzzlescript::engine::BoardOrMessage;
+use puzzlescript::engine::Engine;
+use puzzlescript::engine::EngineInput;
+use puzzlescript::lint;
+use puzzlescript::model::board::Board;
+use puzzlescript::model::board::BoardDelta;
+use puzzlescript::model::game::GameData;
+use puzzlescript::model::game::Level;
+use puzzlescript::model::game::SpriteLookup;
+use puzzlescript::model::legend::Legend;
+use puzzlescript::model::util::CardinalDirection;
+use puzzlescript::model::util::Dimension;
+use puzzlescript::model::util::SpriteState;
+use puzzlescript::observer::EngineObserver;
+use puzzlescript::parser;
+use puzzlescript::profile::Profiler;
+use puzzlescript::render;
+use puzzlescript::save;
+use puzzlescript::save::LevelProgress;
+use puzzlescript::save::SaveState;
+use puzzlescript::sfxr;
+use puzzlescript::solver;
+use puzzlescript::solver::Limits;
+use puzzlescript::solver::SolveResult;
+use puzzlescript::solver::Strategy;
+use puzzlescript::stepper::Breakpoint;
+use puzzlescript::stepper::Session;
+use puzzlescript::terminal::Attribution;
+use puzzlescript::terminal::FixedSizeBackend;
+use puzzlescript::terminal::GameBrowser;
+use puzzlescript::terminal::Help;
+use puzzlescript::terminal::LevelSelect;
+use puzzlescript::terminal::PlayPause;
+use puzzlescript::terminal::RecordingInfo;
+use puzzlescript::terminal::Spinner;
+use puzzlescript::terminal::StepView;
+use puzzlescript::timing::Timing;
+use puzzlescript::trace::Tracer;
+use puzzlescript::verify;
+use puzzlescript::verify::Outcome;
 
 use termion::screen::AlternateScreen;
 use tui::backend::Backend;
@@ -44,29 +83,105 @@ use tui::widgets::Widget;
 use tui::Terminal;
 
 const IDLE_SECS: u64 = 60;
+// How often the screen is redrawn while waiting for a key
+const IDLE_POLL_MILLIS: u64 = 100;
 
 pub fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
     let matches = clap_app!(myapp =>
         (about: "Play Puzzle Games in Rust")
+        (@setting SubcommandsNegateReqs)
         (@arg INPUT: +required "Game file to play")
         (@arg START_LEVEL: --level -l +takes_value "Which level to start playing")
+        (@arg BOARD: --board +takes_value "Start the level from the board in this text file (e.g. one written by render -o level.txt)")
         (@arg IS_SCRIPTED: --scripted "Play 1 level using stdin. Used for running tests")
         (@arg SOUND: --sound "Play sound effects (via the BEL character)")
+        (@arg SOUND_DIR: --("sound-dir") +takes_value "Write every sound effect that plays into this directory as a WAV file")
+        (@arg SOUND_PLAYER: --("sound-player") +takes_value "Pipe every sound effect (as a WAV file) into this command, e.g. \"aplay -q\"")
         (@arg FORCE_PRIMARY_SCREEN: --primary "Do not use the alternate screen (useful for debugging)")
         (@arg NO_FLICK_SCREEN: --noflick "Show the WHOLE level not just the current screen (for finding easter-eggs)")
         (@arg NO_SAVE: --nosave "Do not save")
         (@arg USE_TEST_REPLAY_FILE: --test "Use the {game}.test-replay.json file to replay the games and to save to instead of the default {game}.save.json file")
-        (@arg TICK_SPEED: --speed +takes_value "How long the game waits between each tick")
+        (@arg TICK_SPEED: --speed +takes_value "How long the game waits between each tick (instead of the game's again and realtime intervals)")
+        (@arg SEED: --seed +takes_value "Seed the random number generator (instead of resuming the one in the save file)")
+        (@arg RECORD: --record +takes_value "Record the game into an asciicast v2 file (e.g. game.cast)")
+        (@arg PROFILE: --profile "Time every rule and print the slowest ones (by source line) when the game exits")
+        (@arg PROFILE_JSON: --("profile-json") +takes_value "Like --profile but also write the report into this JSON file")
+        (@arg TRACE: --trace +takes_value "Write every tick, rule, movement and command into this file as JSON lines")
+        (@subcommand solve =>
+            (about: "Search for a solution to a level and print the keys to press")
+            (@arg INPUT: +required "Game file to solve")
+            (@arg LEVEL: --level -l +takes_value +required "Which level to solve")
+            (@arg MAX_DEPTH: --depth +takes_value "Give up on solutions longer than this many inputs")
+            (@arg MAX_NODES: --nodes +takes_value "Give up after visiting this many boards")
+            (@arg TIMEOUT: --timeout +takes_value "Give up after this many seconds")
+            (@arg ASTAR: --astar "Try the moves that get closer to the win conditions first. Usually faster, but the solution may not be the shortest")
+        )
+        (@subcommand render =>
+            (about: "Save a PNG (or text) of a level, or an animated GIF (or asciicast) of a solution being played")
+            (@arg INPUT: +required "Game file to render")
+            (@arg LEVEL: --level -l +takes_value +required "Which level to render")
+            (@arg OUTPUT: --output -o +takes_value +required "The *.png, *.gif, *.cast or *.txt file to write")
+            (@arg SOLUTION: --solution +takes_value "Keys to play (same format as the *.test-replay.json files). Use - to read them from stdin")
+            (@arg SCALE: --scale +takes_value "Size of each sprite pixel (default: 4)")
+            (@arg DELAY: --delay +takes_value "Milliseconds between GIF or asciicast frames (default: 100)")
+        )
+        (@subcommand verify =>
+            (about: "Replay the solutions in the *.test-replay.json files and report which levels still pass")
+            (@arg GAMES: ... "Only verify these games (e.g. pot-wash-panic)")
+            (@arg GAMES_DIR: --dir +takes_value "Directory that contains the *.parsed.json games (default: ./games)")
+            (@arg JOBS: --jobs -j +takes_value "How many games to replay in parallel (default: the number of CPUs)")
+            (@arg OUTPUT: --output -o +takes_value "Where to write the report (default: stats.txt)")
+            (@arg UPDATE_SNAPSHOTS: --("update-snapshots") "Write the boards of the solved levels into the *.test-snapshots.txt files instead of comparing them")
+            (@arg TIMING: --timing "Add how long each level took to the lines of the report")
+        )
+        (@subcommand lint =>
+            (about: "Check a game for common authoring mistakes and print them as JSON")
+            (@arg INPUT: +required "Game file to check")
+        )
+        (@subcommand browse =>
+            (about: "Search the games in a directory and pick one to play")
+            (@arg GAMES_DIR: --dir +takes_value "Directory that contains the *.parsed.json games (default: ./games)")
+        )
     ).get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("solve") {
+        return solve_level(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("render") {
+        return render_level(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        return verify_solutions(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("lint") {
+        return lint_game(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("browse") {
+        return browse_games(matches);
+    }
+
     let game_path = matches.value_of("INPUT").unwrap();
     let start_level = matches
         .value_of("START_LEVEL")
         .map(|s| s.parse().expect("Enter a valid number"));
+    let start_board = match matches.value_of("BOARD") {
+        None => None,
+        Some(path) => Some(fs::read_to_string(path)?),
+    };
     let scripted = matches.is_present("IS_SCRIPTED");
-    let enable_sound = matches.is_present("SOUND");
+    let sound_dir = matches.value_of("SOUND_DIR").map(PathBuf::from);
+    if let Some(dir) = &sound_dir {
+        fs::create_dir_all(dir)?;
+    }
+    let player = matches.value_of("SOUND_PLAYER").map(String::from);
+    let sound = SoundOutput {
+        bell: matches.is_present("SOUND") && player.is_none(),
+        wav_dir: sound_dir,
+        player,
+        ..SoundOutput::default()
+    };
     let force_primary_screen = matches.is_present("FORCE_PRIMARY_SCREEN");
     let no_flick_screen = matches.is_present("NO_FLICK_SCREEN");
     let no_save = matches.is_present("NO_SAVE");
@@ -74,29 +189,68 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let tick_speed = matches
         .value_of("TICK_SPEED")
         .map(|s| s.parse().expect("Enter a valid number"));
+    let seed = matches
+        .value_of("SEED")
+        .map(|s| s.parse().expect("Enter a valid number"));
 
-    let is_stdin_tty = is_tty("/dev/stdin");
+    let profile_json = matches.value_of("PROFILE_JSON");
+    let mut profiler = if matches.is_present("PROFILE") || profile_json.is_some() {
+        Some(Profiler::default())
+    } else {
+        None
+    };
+    let mut tracer = match matches.value_of("TRACE") {
+        None => None,
+        Some(path) => Some(Tracer::new(BufWriter::new(File::create(path)?))),
+    };
+    let mut observers: Vec<&mut dyn EngineObserver> = vec![];
+    if let Some(profiler) = &mut profiler {
+        observers.push(profiler);
+    }
+    if let Some(tracer) = &mut tracer {
+        observers.push(tracer);
+    }
 
-    if scripted || force_primary_screen {
+    let out: Box<dyn Write> = match matches.value_of("RECORD") {
+        None => Box::new(stdout()),
+        Some(cast_path) => {
+            let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+            // Scripted games run as fast as they can so space the frames out like a player would see them
+            let frame_interval = if scripted {
+                Some(time::Duration::from_millis(tick_speed.unwrap_or(100)))
+            } else {
+                None
+            };
+            Box::new(CastWriter::new(
+                stdout(),
+                File::create(cast_path)?,
+                width,
+                height,
+                frame_interval,
+            )?)
+        }
+    };
+
+    let result = if scripted || force_primary_screen {
         // Terminal initialization
-        let out = stdout();
         let backend = TermionBackend::new(out);
         let mut t = Terminal::new(backend)?;
         play_game(
             &mut t,
             &game_path,
             start_level,
+            start_board.as_deref(),
             scripted,
-            enable_sound,
+            sound,
             no_flick_screen,
             tick_speed,
             no_save,
-            is_stdin_tty,
+            &mut observers,
             is_use_test_replay_file,
+            seed,
         )
     } else {
         // Terminal initialization
-        let out = stdout();
         let out = AlternateScreen::from(out);
         let backend = TermionBackend::new(out);
         let mut t = Terminal::new(backend)?;
@@ -106,29 +260,47 @@ pub fn main() -> Result<(), Box<dyn Error>> {
             &mut t,
             &game_path,
             start_level,
+            start_board.as_deref(),
             scripted,
-            enable_sound,
+            sound,
             no_flick_screen,
             tick_speed,
             no_save,
-            is_stdin_tty,
+            &mut observers,
             is_use_test_replay_file,
+            seed,
         )
+    };
+
+    // The terminal is back to normal so the report stays on the screen
+    if let Some(tracer) = tracer {
+        tracer.finish()?;
+    }
+    if let Some(profiler) = &mut profiler {
+        let report = profiler.take();
+        println!("{}", report);
+        if let Some(path) = profile_json {
+            serde_json::to_writer_pretty(File::create(path)?, &report)?;
+        }
     }
+    result
 }
 
 fn play_game<B: Backend>(
     terminal: &mut Terminal<B>,
     path: &str,
     start_level: Option<u8>,
+    start_board: Option<&str>,
     scripted: bool,
-    enable_sound: bool,
+    mut sound: SoundOutput,
     no_flick_screen: bool,
     tick_speed: Option<u64>,
     no_save: bool,
-    is_stdin_tty: bool,
+    observer: &mut dyn EngineObserver,
     is_use_test_replay_file: bool,
+    seed: Option<u64>,
 ) -> Result<(), Box<dyn Error>> {
+    let is_stdin_tty = is_tty("/dev/stdin");
     let save_path = if is_use_test_replay_file {
         format!("{}.test-replay.json", path)
     } else {
@@ -156,40 +328,52 @@ fn play_game<B: Backend>(
     let mut recording_info = RecordingInfo::default();
     let mut debug_keypresses = String::from("");
 
-    let (start_level, checkpoint, mut inputs) = SaveState::read_from_file(&save_path)
-        .map(|save_data| {
+    // Names that no longer match the game are handled like a save file that cannot be read
+    let save_data = read_save_file(&save_path, |save_data| {
+        let saved_level = match start_level {
+            Some(_) => SavedLevel::default(),
+            None => restore_level(&save_data, &game, &sprite_lookup)?,
+        };
+        Ok((save_data, saved_level))
+    });
+    let mut levels = save_data
+        .as_ref()
+        .map(|(save_data, _)| save_data.levels.clone())
+        .unwrap_or_default();
+    let (start_level, saved_level, rng, mut inputs) = save_data
+        .map(|(save_data, saved_level)| {
             match start_level {
-                None => {
-                    let level = &game.levels[save_data.level as usize];
-                    let checkpoint = save_data.checkpoint.map(|checkpoint| {
-                        let (width, height) = level.size();
-                        Board::from_checkpoint(
-                            width,
-                            height,
-                            checkpoint
-                                .iter()
-                                .map(|names| {
-                                    names
-                                        .iter()
-                                        .map(|name| sprite_lookup.to_id(name).unwrap().clone())
-                                        .collect()
-                                })
-                                .collect(),
-                        )
-                    });
-                    (save_data.level, checkpoint, save_data.inputs)
-                }
+                None => (
+                    save_data.level,
+                    saved_level,
+                    save_data.rng,
+                    save_data.inputs,
+                ),
                 Some(level_num) => {
                     // clear the current level since we will be playing it
                     let mut inputs = save_data.inputs.clone();
                     if inputs.len() >= level_num as usize {
                         inputs[level_num as usize] = String::from("");
                     }
-                    (level_num, None, inputs)
+                    (level_num, SavedLevel::default(), None, inputs)
                 }
             }
         })
-        .unwrap_or_else(|_| (start_level.unwrap_or(0), None, vec![]));
+        .unwrap_or_else(|| {
+            (
+                start_level.unwrap_or(0),
+                SavedLevel::default(),
+                None,
+                vec![],
+            )
+        });
+    let saved_level = match start_board {
+        None => saved_level,
+        Some(text) => SavedLevel {
+            checkpoint: Some(Board::from_text(text, &Legend::new(&game))?),
+            ..SavedLevel::default()
+        },
+    };
 
     fn add_input(
         inputs: &mut Vec<String>,
@@ -204,10 +388,20 @@ fn play_game<B: Backend>(
         debug_keypresses.push(input);
     }
 
-    let mut engine = match checkpoint {
+    let mut engine = match saved_level.checkpoint {
         None => Engine::new(game, start_level),
         Some(checkpoint) => Engine::from_checkpoint(game, start_level, checkpoint),
     };
+    if let Some(board) = saved_level.board {
+        engine.set_board(board);
+    }
+    match (seed, rng) {
+        (Some(seed), _) => engine.set_rng(XorShiftRng::seed_from_u64(seed)),
+        (None, Some(rng)) => engine.set_rng(rng),
+        (None, None) => {}
+    }
+    engine.set_undo_history(saved_level.undo);
+    sound.play(&engine.take_sounds())?;
 
     // Enable raw mode so we get keys
     if !scripted && is_stdin_tty {
@@ -216,45 +410,52 @@ fn play_game<B: Backend>(
 
     clear_screen();
 
-    let mut sleep_time = match tick_speed {
-        None => {
-            if scripted {
-                0
-            } else {
-                match &engine.game_data.metadata.realtime_interval {
-                    None => 100,
-                    Some(sec) => (sec * 1000.0) as u64,
-                }
-            }
-        }
-        Some(s) => s,
+    let mut timing = if scripted {
+        Timing::fixed(time::Duration::from_millis(tick_speed.unwrap_or(0)))
+    } else {
+        Timing::new(
+            &engine.game_data.metadata,
+            tick_speed.map(time::Duration::from_millis),
+        )
     };
 
-    let save_game = |current_level_num: u8,
-                     inputs: Vec<String>,
-                     board: Option<Board>|
+    let save_game = |inputs: &[String],
+                     levels: &[LevelProgress],
+                     board: Option<Board>,
+                     engine: &Engine|
      -> Result<(), Box<dyn Error>> {
         if no_save {
             return Ok(());
         }
-        let checkpoint = board.map(|board| {
-            board
-                .positions_iter()
-                .iter()
-                .map(|p| {
-                    board
-                        .get_sprite_states(&p)
-                        .iter()
-                        .map(|s| sprite_lookup.to_name(&s.index).unwrap().clone())
-                        .collect()
-                })
-                .collect()
-        });
+        // The board is only saved when the level does not continue from the checkpoint or its start
+        let current = match &engine.current_level {
+            BoardOrMessage::Board(current) if board.as_ref() != Some(current) => {
+                let level = &engine.game_data.levels[engine.current_level_num as usize];
+                if *current == engine.game_data.to_board(level) {
+                    None
+                } else {
+                    Some(board_to_names(current, &sprite_lookup))
+                }
+            }
+            _ => None,
+        };
+        let checkpoint = board.map(|board| board_to_names(&board, &sprite_lookup));
+        let mut levels = levels.to_vec();
+        if levels.len() < inputs.len() {
+            levels.resize(inputs.len(), LevelProgress::default());
+        }
+        for (level, inputs) in levels.iter_mut().zip(inputs) {
+            level.moves = save::count_moves(inputs);
+        }
         let save = SaveState {
-            version: 1,
-            inputs,
-            level: current_level_num,
+            version: save::VERSION,
+            inputs: inputs.to_vec(),
+            level: engine.current_level_num,
             checkpoint: checkpoint,
+            rng: Some(engine.rng().clone()),
+            levels,
+            board: current,
+            undo: undo_to_names(engine.undo_history(), &sprite_lookup),
         };
         save.write_to_file(&save_path)
     };
@@ -267,16 +468,82 @@ fn play_game<B: Backend>(
         .run_rules_on_level_start
         .unwrap_or(false);
     let mut last_input = time::Instant::now();
-    let (stdin_channel, _handle) = spawn_stdin_channel();
+    let mut last_realtime_tick = time::Instant::now();
+    // A key that arrived while waiting for the next realtime tick
+    let mut pending_key = None;
+    // The highlighted level while the level select screen is open
+    let mut level_select: Option<usize> = None;
+    // The step debugger (toggled with ~). Breakpoints are kept when it is closed
+    let mut session = Session::default();
+    let stdin_channel = stdin_channel();
     sleep(100); // wait for thread to look into stdin
     loop {
         let start_tick = time::Instant::now();
         let mut should_tick;
         let mut input = None;
 
-        let key = stdin_channel.try_recv();
+        let key = match pending_key.take() {
+            Some(key) => Ok(key),
+            None => stdin_channel.try_recv(),
+        };
         debug!("Input: Received {:?}", key);
         match key {
+            Ok(key) if level_select.is_some() => {
+                last_input = time::Instant::now();
+                should_tick = false;
+                let selected = level_select.unwrap_or(0);
+                let last = engine.game_data.levels.len() - 1;
+                match key {
+                    Key::Ctrl('c') => break,
+                    Key::Esc | Key::Char('q') | Key::Char('l') | Key::Char('L') => {
+                        level_select = None
+                    }
+                    Key::Up | Key::Char('w') | Key::Char('W') => {
+                        level_select = Some(selected.saturating_sub(1))
+                    }
+                    Key::Down | Key::Char('s') | Key::Char('S') => {
+                        level_select = Some((selected + 1).min(last))
+                    }
+                    Key::Char('\n') | Key::Char(' ') | Key::Char('x') | Key::Char('X') => {
+                        level_select = None;
+                        engine.goto_level(selected as u8);
+                        // the level is played from the start
+                        if let Some(level_inputs) = inputs.get_mut(selected) {
+                            level_inputs.clear();
+                        }
+                        tick_without_input = engine
+                            .game_data
+                            .metadata
+                            .run_rules_on_level_start
+                            .unwrap_or(false);
+                        save_game(&inputs, &levels, None, &engine)?;
+                        sound.play(&engine.take_sounds())?;
+                    }
+                    _ => {}
+                }
+            }
+            Ok(key) if session.prompt.is_some() => {
+                last_input = time::Instant::now();
+                should_tick = false;
+                let mut prompt = session.prompt.take().unwrap_or_default();
+                match key {
+                    Key::Ctrl('c') => break,
+                    Key::Esc => {}
+                    Key::Char('\n') => match Breakpoint::parse(&prompt, &engine.game_data) {
+                        Ok(breakpoint) => session.toggle_breakpoint(breakpoint),
+                        Err(message) => session.status = Some(message),
+                    },
+                    Key::Backspace => {
+                        prompt.pop();
+                        session.prompt = Some(prompt);
+                    }
+                    Key::Char(c) => {
+                        prompt.push(c);
+                        session.prompt = Some(prompt);
+                    }
+                    _ => session.prompt = Some(prompt),
+                }
+            }
             Ok(key) => {
                 last_input = time::Instant::now();
                 let was_paused = play_pause.paused;
@@ -287,6 +554,23 @@ fn play_game<B: Backend>(
                         help.toggle();
                         false
                     }
+                    // The step debugger's cursor
+                    Key::Up | Key::Char('w') | Key::Char('W') if session.inspecting => {
+                        move_cursor(&mut session, &engine, CardinalDirection::Up);
+                        false
+                    }
+                    Key::Down | Key::Char('s') | Key::Char('S') if session.inspecting => {
+                        move_cursor(&mut session, &engine, CardinalDirection::Down);
+                        false
+                    }
+                    Key::Left | Key::Char('a') | Key::Char('A') if session.inspecting => {
+                        move_cursor(&mut session, &engine, CardinalDirection::Left);
+                        false
+                    }
+                    Key::Right | Key::Char('d') | Key::Char('D') if session.inspecting => {
+                        move_cursor(&mut session, &engine, CardinalDirection::Right);
+                        false
+                    }
                     Key::Up | Key::Char('w') | Key::Char('W') => {
                         input = input.or(Some(EngineInput::Up));
                         keys += 1;
@@ -326,11 +610,20 @@ fn play_game<B: Backend>(
                         keys += 1;
                         true
                     }
+                    Key::Char('y') | Key::Char('Y') => {
+                        input = input.or(Some(EngineInput::Redo));
+                        keys += 1;
+                        true
+                    }
                     Key::Char('R') | Key::Char('r') => {
                         input = input.or(Some(EngineInput::Restart));
                         keys += 1;
                         true
                     }
+                    Key::Char('l') | Key::Char('L') => {
+                        level_select = Some(engine.current_level_num as usize);
+                        false
+                    }
                     Key::Char('p') => {
                         if !was_paused {
                             play_pause.pause()
@@ -354,36 +647,67 @@ fn play_game<B: Backend>(
                         process::exit(0)
                     }
                     Key::Char('~') | Key::Char('`') | Key::Char('\\') => {
-                        if is_stdin_tty {
-                            // ensure the dumper can enable/disable raw mode
-                            ScreenDumper::set_term();
+                        session.enabled = !session.enabled;
+                        session.load(None);
+                        session.inspecting = false;
+                        false
+                    }
+                    // Step debugger. At the end of the tick `n` runs the next one
+                    Key::Char('n') => session.enabled && !session.next_step(),
+                    Key::Char('b') => {
+                        session.prev_step();
+                        false
+                    }
+                    Key::Char(']') => {
+                        session.next_phase();
+                        false
+                    }
+                    Key::Char('[') => {
+                        session.prev_phase();
+                        false
+                    }
+                    Key::Char('g') => {
+                        session.resume();
+                        false
+                    }
+                    Key::Char('B') => {
+                        if let Some(line) = session.current().and_then(|step| step.line) {
+                            session.toggle_breakpoint(Breakpoint::Line(line));
                         }
-                        engine.debug_rules = !engine.debug_rules;
-                        if engine.debug_rules {
-                            terminal.draw(|_|{})?/*repaint*/;
-                            true
-                        } else {
-                            clear_screen();
-                            false
+                        false
+                    }
+                    Key::Char(':') => {
+                        if session.enabled {
+                            session.prompt = Some(String::new());
                         }
+                        false
+                    }
+                    Key::Char('i') => {
+                        session.inspecting = session.enabled && !session.inspecting;
+                        false
                     }
-                    Key::Char('n') => engine.debug_rules,
                     Key::Char('-') | Key::Char('_') => {
-                        if sleep_time >= 50 {
-                            sleep_time -= 50;
+                        if timing.faster() {
                             play_bell()
                         };
                         false
                     }
                     Key::Char('=') | Key::Char('+') => {
-                        if sleep_time < 1000 {
-                            sleep_time += 50;
+                        if timing.slower() {
                             play_bell()
                         };
                         false
                     }
                     _ => true,
                 };
+
+                if let Some(i) = input {
+                    if !timing.accept(i, last_input) {
+                        debug!("Input: Dropped repeated {:?}", i);
+                        input = None;
+                        should_tick = false;
+                    }
+                }
             }
             Err(TryRecvError::Empty) => {
                 if scripted {
@@ -402,7 +726,7 @@ fn play_game<B: Backend>(
                 if !is_stdin_tty {
                     return Ok(());
                 }
-                should_tick = !engine.debug_rules; // Do not tick when debugger is on.
+                should_tick = !session.enabled; // Do not tick when debugger is on.
 
                 // Pause the game when idle (only for realtime games)
                 if !play_pause.paused
@@ -413,43 +737,63 @@ fn play_game<B: Backend>(
                     should_tick = false;
                 }
 
-                if engine.game_data.metadata.realtime_interval.is_none() {
-                    should_tick = false
+                match timing.realtime_interval() {
+                    None => should_tick = false,
+                    Some(interval) => {
+                        if last_realtime_tick.elapsed() < interval {
+                            should_tick = false
+                        }
+                    }
                 }
             }
             Err(TryRecvError::Disconnected) => panic!("Channel disconnected"),
         }
 
-        if tick_without_input {
+        // The step debugger runs AGAIN ticks one at a time with `n`
+        if tick_without_input && !session.enabled {
             should_tick = true;
         }
+        if level_select.is_some() {
+            should_tick = false;
+        }
 
         if !should_tick {
-            if !engine.debug_rules {
-                // *******************************************
-                //   This is Copy/Pasta'd in multiple places
-                // *******************************************
-                terminal.draw(|mut f| {
-                    let size = f.size();
-                    let top = Rect::new(size.x, size.y, size.width, 1);
-                    let main = Rect::new(size.x, size.y + 1, size.width, size.height - 2);
-                    let bottom = Rect::new(size.x, main.bottom(), size.width, 1);
-
-                    engine.render(&mut f, main);
-                    play_pause.render(&mut f, main);
-                    attribution.render(&mut f, top);
-                    help.render(&mut f, bottom);
-                    spinner.render(&mut f, bottom);
-                    if !is_stdin_tty {
-                        recording_info.render(&mut f, bottom);
-                    }
-                })?;
-            }
+            let completed: Vec<bool> = levels.iter().map(|level| level.completed).collect();
+            // *******************************************
+            //   This is Copy/Pasta'd in multiple places
+            // *******************************************
+            terminal.draw(|mut f| {
+                let size = f.size();
+                let top = Rect::new(size.x, size.y, size.width, 1);
+                let main = Rect::new(size.x, size.y + 1, size.width, size.height - 2);
+                let bottom = Rect::new(size.x, main.bottom(), size.width, 1);
 
-            // Copy/Pasta
-            let elapsed_time = start_tick.elapsed().as_millis();
-            if elapsed_time < sleep_time as u128 {
-                sleep(sleep_time - (elapsed_time as u64));
+                match level_select {
+                    Some(selected) => LevelSelect::new(&engine.game_data, &completed, selected)
+                        .render(&mut f, main),
+                    None if session.enabled => {
+                        StepView::new(&engine, &session).render(&mut f, main)
+                    }
+                    None => engine.render(&mut f, main),
+                }
+                play_pause.render(&mut f, main);
+                attribution.render(&mut f, top);
+                help.render(&mut f, bottom);
+                spinner.render(&mut f, bottom);
+                if !is_stdin_tty {
+                    recording_info.render(&mut f, bottom);
+                }
+            })?;
+
+            // Wait for a key (or until the next realtime tick)
+            let wait = match timing.realtime_interval() {
+                None => time::Duration::from_millis(IDLE_POLL_MILLIS),
+                Some(interval) => interval
+                    .checked_sub(last_realtime_tick.elapsed())
+                    .unwrap_or_default(),
+            };
+            if let Ok(key) = stdin_channel.recv_timeout(wait) {
+                pending_key = Some(key);
             }
 
             continue;
@@ -461,11 +805,21 @@ fn play_game<B: Backend>(
         }
 
         // Tick!
-        let tr = engine.tick(input);
+        let mut observers: Vec<&mut dyn EngineObserver> = vec![];
+        if session.enabled {
+            // A tick that does not run the rules (e.g. UNDO) leaves nothing to step through
+            session.load(None);
+            observers.push(&mut session);
+        }
+        observers.push(observer);
+        let tr = engine.tick_with_observer(input, &mut observers);
 
         tick_without_input = !tr.accepting_input;
+        if input.is_none() {
+            last_realtime_tick = time::Instant::now();
+        }
 
-        if !engine.debug_rules {
+        if !session.enabled {
             // *******************************************
             //   This is Copy/Pasta'd in multiple places
             // *******************************************
@@ -496,17 +850,21 @@ fn play_game<B: Backend>(
             );
         }
 
-        if tr.sfx && enable_sound {
-            play_bell();
-        }
+        sound.play(&tr.sfx)?;
 
-        if tr.completed_level.is_some() {
+        if let Some(level) = tr.completed_level {
             scripted_did_win = true;
+            if levels.len() <= level as usize {
+                levels.resize(level as usize + 1, LevelProgress::default());
+            }
+            let solution = inputs.get(level as usize).map(String::as_str);
+            levels[level as usize].record_win(solution.unwrap_or(""));
             if !engine.next_level() {
-                save_game(engine.current_level_num, inputs.clone(), None)?;
+                save_game(&inputs, &levels, None, &engine)?;
                 println!("You beat all the levels in the game!");
-                break;
+                return Ok(());
             }
+            sound.play(&engine.take_sounds())?;
         }
 
         if tr.checkpoint.is_some() {
@@ -520,17 +878,326 @@ fn play_game<B: Backend>(
                 engine.current_level_num,
                 '#',
             );
-            save_game(engine.current_level_num, inputs.clone(), tr.checkpoint)?;
+            save_game(&inputs, &levels, tr.checkpoint, &engine)?;
         }
 
         if tr.completed_level.is_some() {
-            save_game(engine.current_level_num, inputs.clone(), None)?;
+            save_game(&inputs, &levels, None, &engine)?;
+        }
+
+        if tr.restarted {
+            save_game(&inputs, &levels, engine.checkpoint().cloned(), &engine)?;
+        }
+
+        // AGAIN ticks are animated at the game's again_interval
+        let wait = if tick_without_input {
+            timing.again_interval()
+        } else {
+            timing.after_input()
+        };
+        if let Some(wait) = wait.checked_sub(start_tick.elapsed()) {
+            thread::sleep(wait);
+        }
+    }
+    // Quitting keeps the board and its undo history so the level continues from where it was left
+    save_game(&inputs, &levels, engine.checkpoint().cloned(), &engine)?;
+    Ok(())
+}
+
+fn solve_level(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let game_path = matches.value_of("INPUT").unwrap();
+    let level: u8 = matches
+        .value_of("LEVEL")
+        .map(|s| s.parse().expect("Enter a valid number"))
+        .unwrap();
+    let limits = Limits {
+        max_depth: matches
+            .value_of("MAX_DEPTH")
+            .map(|s| s.parse().expect("Enter a valid number")),
+        max_nodes: matches
+            .value_of("MAX_NODES")
+            .map(|s| s.parse().expect("Enter a valid number")),
+        timeout: matches
+            .value_of("TIMEOUT")
+            .map(|s| time::Duration::from_secs(s.parse().expect("Enter a valid number"))),
+    };
+
+    let game = read_game_from_file(game_path)?;
+    let strategy = if matches.is_present("ASTAR") {
+        Strategy::AStar
+    } else {
+        Strategy::BreadthFirst
+    };
+    let solution = solver::solve(&game, level, strategy, &limits);
+    eprintln!(
+        "Visited {} boards in {:.1}s",
+        solution.nodes,
+        solution.elapsed.as_secs_f32()
+    );
+    match solution.result {
+        SolveResult::Solved(keys) => {
+            println!("{}", keys);
+            Ok(())
+        }
+        SolveResult::Unsolvable => Err("No solution exists for this level".into()),
+        SolveResult::GaveUp => Err("Gave up before finding a solution".into()),
+    }
+}
+
+fn render_level(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let game_path = matches.value_of("INPUT").unwrap();
+    let output = matches.value_of("OUTPUT").unwrap();
+    let level: u8 = matches
+        .value_of("LEVEL")
+        .map(|s| s.parse().expect("Enter a valid number"))
+        .unwrap();
+    let scale = matches
+        .value_of("SCALE")
+        .map(|s| s.parse().expect("Enter a valid number"))
+        .unwrap_or(4);
+    let delay: u16 = matches
+        .value_of("DELAY")
+        .map(|s| s.parse().expect("Enter a valid number"))
+        .unwrap_or(100);
+    let solution = match matches.value_of("SOLUTION") {
+        None => String::from(""),
+        Some("-") => {
+            let mut keys = String::new();
+            stdin().read_to_string(&mut keys)?;
+            keys
+        }
+        Some(keys) => String::from(keys),
+    };
+
+    let game = read_game_from_file(game_path)?;
+    let mut engine = Engine::new(game, level);
+    if output.ends_with(".cast") {
+        let interval = time::Duration::from_millis(delay as u64);
+        return record_replay(
+            &mut engine,
+            solution.trim(),
+            interval,
+            File::create(output)?,
+        );
+    }
+    if output.ends_with(".txt") {
+        // The last board that was played, like the PNG (not the next level after a win)
+        let legend = Legend::new(&engine.game_data);
+        let text = |engine: &Engine| match &engine.current_level {
+            BoardOrMessage::Board(board) => Some(board.to_text(&legend)),
+            BoardOrMessage::Message(_) => None,
+        };
+        let mut last = text(&engine);
+        verify::replay_with(&mut engine, solution.trim(), |engine, _| {
+            if let Some(t) = text(engine) {
+                last = Some(t);
+            }
+        });
+        return match last {
+            None => Err(format!("Level {} is a message. There is nothing to draw", level).into()),
+            Some(text) => Ok(fs::write(output, text)?),
+        };
+    }
+    let frames = render::render_replay(&mut engine, solution.trim(), scale);
+    let f = File::create(output)?;
+    if output.ends_with(".gif") {
+        render::write_gif(&frames, delay / 10, f)
+    } else {
+        match frames.last() {
+            None => Err(format!("Level {} is a message. There is nothing to draw", level).into()),
+            Some(image) => render::write_png(image, f),
+        }
+    }
+}
+
+// Draws the game the same way play_game() does, without needing a real terminal
+fn record_replay<W: Write>(
+    engine: &mut Engine,
+    keys: &str,
+    interval: time::Duration,
+    cast: W,
+) -> Result<(), Box<dyn Error>> {
+    let size = Rect::new(0, 0, 80, 25);
+    let out = CastWriter::new(io::sink(), cast, size.width, size.height, Some(interval))?;
+    let backend = FixedSizeBackend::new(TermionBackend::new(out), size);
+    let mut terminal = Terminal::new(backend)?;
+    let mut attribution = Attribution::new(
+        engine.game_data.title.clone(),
+        engine.game_data.metadata.author.clone(),
+        engine.game_data.metadata.homepage.clone(),
+    );
+
+    let mut draw = |terminal: &mut Terminal<_>, engine: &mut Engine| {
+        terminal.draw(|mut f| {
+            let size = f.size();
+            let top = Rect::new(size.x, size.y, size.width, 1);
+            let main = Rect::new(size.x, size.y + 1, size.width, size.height - 2);
+
+            engine.render(&mut f, main);
+            attribution.render(&mut f, top);
+        })
+    };
+
+    draw(&mut terminal, engine)?;
+    let mut result = Ok(());
+    verify::replay_with(engine, keys, |engine, _| {
+        if result.is_ok() {
+            result = draw(&mut terminal, engine);
+        }
+    });
+    Ok(result?)
+}
+
+fn verify_solutions(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let games_dir = matches.value_of("GAMES_DIR").unwrap_or("./games");
+    let output = matches.value_of("OUTPUT").unwrap_or("stats.txt");
+    let jobs = matches
+        .value_of("JOBS")
+        .map(|s| s.parse().expect("Enter a valid number"))
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let only: Option<Vec<&str>> = matches.values_of("GAMES").map(|games| games.collect());
+
+    let mut paths = vec![];
+    for entry in fs::read_dir(games_dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if let Some(game) = name.strip_suffix(".parsed.json") {
+            if only.as_ref().is_none_or(|only| only.contains(&game)) {
+                paths.push(path);
+            }
+        }
+    }
+
+    let start = time::Instant::now();
+    let update_snapshots = matches.is_present("UPDATE_SNAPSHOTS");
+    let reports = verify::verify_games(paths, jobs, update_snapshots);
+
+    let timing = matches.is_present("TIMING");
+    let mut stats = File::create(output)?;
+    let (mut solved, mut failed, mut skipped) = (0, 0, 0);
+    for report in &reports {
+        if timing {
+            writeln!(stats, "{}", report.with_time())?;
+        } else {
+            writeln!(stats, "{}", report)?;
+        }
+        match &report.outcome {
+            Outcome::Solved => solved += 1,
+            Outcome::Failed(reason) => {
+                failed += 1;
+                match report.index {
+                    Some(_) => println!("{} ({})", report, reason),
+                    None => println!("{}", report),
+                }
+            }
+            Outcome::Skipped(_) => skipped += 1,
         }
+    }
+    println!(
+        "{} solved, {} failed, {} skipped in {:.1}s. See {}",
+        solved,
+        failed,
+        skipped,
+        start.elapsed().as_secs_f32(),
+        output
+    );
 
-        // Copy/Pasta
-        let elapsed_time = start_tick.elapsed().as_millis();
-        if elapsed_time < sleep_time as u128 {
-            sleep(sleep_time - (elapsed_time as u64));
+    if failed > 0 {
+        Err(format!("{} levels failed", failed).into())
+    } else {
+        Ok(())
+    }
+}
+
+fn lint_game(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let game_path = matches.value_of("INPUT").unwrap();
+    let game = read_game_from_file(game_path)?;
+    let diagnostics = lint::lint(&game);
+    println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} problems found", diagnostics.len()).into())
+    }
+}
+
+fn browse_games(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let games_dir = matches.value_of("GAMES_DIR").unwrap_or("./games");
+    if !is_tty("/dev/stdin") {
+        return Err("The game browser needs a terminal".into());
+    }
+    println!("Loading the games in {}...", games_dir);
+    let mut games = browse::scan(Path::new(games_dir))?;
+    if games.is_empty() {
+        return Err(format!("There are no *.parsed.json games in {}", games_dir).into());
+    }
+
+    let backend = TermionBackend::new(AlternateScreen::from(stdout()));
+    let mut terminal = Terminal::new(backend)?;
+    terminal.hide_cursor()?;
+    ScreenDumper::set_term();
+    clear_screen();
+
+    let stdin_channel = stdin_channel();
+    let mut query = String::new();
+    let mut sort = SortBy::Title;
+    let mut selected = 0;
+    let mut status = None;
+    loop {
+        let visible = browse::filter(&games, &query, sort);
+        selected = selected.min(visible.len().saturating_sub(1));
+        terminal.draw(|mut f| {
+            let size = f.size();
+            GameBrowser::new(&games, &visible, selected, &query, sort, status.as_deref())
+                .render(&mut f, size);
+        })?;
+
+        let key = stdin_channel.recv()?;
+        status = None;
+        match key {
+            Key::Esc if !query.is_empty() => query.clear(),
+            Key::Esc | Key::Ctrl('c') => break,
+            Key::Up => selected = selected.saturating_sub(1),
+            Key::Down => selected += 1,
+            Key::PageUp => selected = selected.saturating_sub(10),
+            Key::PageDown => selected += 10,
+            Key::Char('\t') => sort = sort.next(),
+            Key::Char('\n') => {
+                if let Some(index) = visible.get(selected) {
+                    let game = &mut games[*index];
+                    let path = game.path.to_string_lossy().to_string();
+                    let result = play_game(
+                        &mut terminal,
+                        &path,
+                        None,
+                        None,
+                        false,
+                        SoundOutput::default(),
+                        false,
+                        None,
+                        false,
+                        &mut (),
+                        false,
+                        None,
+                    );
+                    if let Err(e) = result {
+                        status = Some(format!("Could not play {}: {}", game.name, e));
+                    }
+                    game.refresh_progress();
+                    // The game printed outside of the terminal's buffers so redraw everything
+                    let size = terminal.size()?;
+                    terminal.resize(size)?;
+                }
+            }
+            Key::Backspace => {
+                query.pop();
+                selected = 0;
+            }
+            Key::Char(c) if !c.is_control() => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => {}
         }
     }
     Ok(())
@@ -539,9 +1206,26 @@ fn play_game<B: Backend>(
 fn read_game_from_file(path: &str) -> Result<GameData, Box<dyn Error>> {
     info!("Reading {:?}", path);
 
+    // Games are either the compiled JSON or the original PuzzleScript source.
+    // Use the extension and fall back to peeking at the contents.
+    let is_json = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("json") => true,
+        Some("txt") => false,
+        _ => {
+            let mut contents = String::new();
+            File::open(path)?.read_to_string(&mut contents)?;
+            contents.trim_start().starts_with('{')
+        }
+    };
+
     // Open the file in read-only mode with buffer.
     let file = File::open(path)?;
-    let game = parser::parse(file)?;
+    let game = if is_json {
+        parser::parse(file)
+    } else {
+        parser::parse_source(file)
+    }
+    .map_err(|e| format!("Could not load {}: {}", path, e))?;
 
     info!("Parsed {:?}", path);
 
@@ -549,16 +1233,25 @@ fn read_game_from_file(path: &str) -> Result<GameData, Box<dyn Error>> {
 }
 
 // https://stackoverflow.com/a/55201400
-fn spawn_stdin_channel() -> (Receiver<Key>, thread::JoinHandle<()>) {
+fn spawn_stdin_channel() -> Receiver<Key> {
     let (tx, rx) = mpsc::channel::<Key>();
-    let handle = thread::spawn(move || loop {
+    thread::spawn(move || loop {
         for key in stdin().keys() {
             let key = key.unwrap();
             debug!("Sending {:?}", key);
             tx.send(key).unwrap();
         }
     });
-    (rx, handle)
+    rx
+}
+
+thread_local! {
+    // Only one thread may read stdin, so the games that the browser starts share it
+    static STDIN_CHANNEL: Rc<Receiver<Key>> = Rc::new(spawn_stdin_channel());
+}
+
+fn stdin_channel() -> Rc<Receiver<Key>> {
+    STDIN_CHANNEL.with(Rc::clone)
 }
 
 fn sleep(millis: u64) {
@@ -566,6 +1259,16 @@ fn sleep(millis: u64) {
     thread::sleep(duration);
 }
 
+// Moves the step debugger's cursor around the board that it shows
+fn move_cursor(session: &mut Session, engine: &Engine, dir: CardinalDirection) {
+    let size = match (session.current(), &engine.current_level) {
+        (Some(step), _) => step.board.size(),
+        (None, BoardOrMessage::Board(board)) => board.size(),
+        (None, BoardOrMessage::Message(_)) => return,
+    };
+    session.move_cursor(dir, size);
+}
+
 fn clear_screen() {
     print!(
         "{}{}{}",
@@ -578,6 +1281,227 @@ fn play_bell() {
     print!("\x07")
 }
 
+// Where sound effects go: the terminal bell, WAV files and/or a command that plays them
+#[derive(Default)]
+struct SoundOutput {
+    bell: bool,
+    wav_dir: Option<PathBuf>,
+    player: Option<String>,
+    played: usize,
+    // Synthesizing takes a while so only do it once per sound
+    wavs: HashMap<u32, Vec<u8>>,
+}
+
+impl SoundOutput {
+    fn play(&mut self, seeds: &[u32]) -> Result<(), Box<dyn Error>> {
+        if self.bell && !seeds.is_empty() {
+            play_bell();
+        }
+        if self.wav_dir.is_none() && self.player.is_none() {
+            return Ok(());
+        }
+
+        for seed in seeds {
+            let wav = self.wavs.entry(*seed).or_insert_with(|| {
+                let mut wav = vec![];
+                let samples = sfxr::Params::from_seed(*seed).synthesize();
+                sfxr::write_wav(&samples, &mut wav).expect("Writing to memory cannot fail");
+                wav
+            });
+            self.played += 1;
+            debug!("Sound {}: {}", self.played, seed);
+
+            if let Some(dir) = &self.wav_dir {
+                fs::write(dir.join(format!("{:04}-{}.wav", self.played, seed)), &wav)?;
+            }
+            if let Some(command) = &self.player {
+                let mut child = process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .stdin(process::Stdio::piped())
+                    .stdout(process::Stdio::null())
+                    .spawn()?;
+                let mut stdin = child.stdin.take().unwrap();
+                let wav = wav.clone();
+                // Do not make the game wait for the sound to finish
+                thread::spawn(move || {
+                    let _ = stdin.write_all(&wav);
+                    drop(stdin);
+                    let _ = child.wait();
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// A save file that cannot be read (or restored with `restore`) is moved out of the way (instead of
+// being overwritten) and the game starts over
+fn read_save_file<T, F>(path: &str, restore: F) -> Option<T>
+where
+    F: FnOnce(SaveState) -> Result<T, Box<dyn Error>>,
+{
+    if !Path::new(path).exists() {
+        return None;
+    }
+    match SaveState::read_from_file(path).and_then(restore) {
+        Ok(restored) => Some(restored),
+        Err(e) => {
+            let backup = format!("{}.corrupt", path);
+            let moved = match fs::rename(path, &backup) {
+                Ok(_) => format!(" The old file was moved to {}", backup),
+                Err(_) => String::from(""),
+            };
+            println!(
+                "\n\n\n\n{}WARNING:{} {}. Starting from the beginning.{}",
+                termion::color::Fg(termion::color::Yellow),
+                termion::style::Reset,
+                e,
+                moved
+            );
+            sleep(5000);
+            None
+        }
+    }
+}
+
+// Where the level that the save file was on continues from
+#[derive(Default)]
+struct SavedLevel {
+    checkpoint: Option<Board>,
+    // The board when the game was quit (the undo steps apply to it)
+    board: Option<Board>,
+    undo: Vec<BoardDelta>,
+}
+
+fn restore_level(
+    save_data: &SaveState,
+    game: &GameData,
+    lookup: &SpriteLookup,
+) -> Result<SavedLevel, Box<dyn Error>> {
+    let level = game
+        .levels
+        .get(save_data.level as usize)
+        .ok_or_else(|| format!("the game does not have a level {}", save_data.level))?;
+    if let Level::Message(_) = level {
+        let has_board = save_data.checkpoint.is_some() || save_data.board.is_some();
+        return match (has_board, save_data.undo.is_empty()) {
+            (false, true) => Ok(SavedLevel::default()),
+            _ => Err(format!("level {} is a message but has a board", save_data.level).into()),
+        };
+    }
+    let (width, height) = level.size();
+    let size = Dimension { width, height };
+    let restore_board = |cells: &Option<Vec<Vec<String>>>, what: &str| match cells {
+        None => Ok(None),
+        Some(cells) => {
+            if cells.len() != width as usize * height as usize {
+                return Err(format!(
+                    "the {} has {} cells but level {} has {}",
+                    what,
+                    cells.len(),
+                    save_data.level,
+                    width as usize * height as usize
+                )
+                .into());
+            }
+            board_from_names(cells, size, lookup).map(Some)
+        }
+    };
+    Ok(SavedLevel {
+        checkpoint: restore_board(&save_data.checkpoint, "checkpoint")?,
+        board: restore_board(&save_data.board, "board")?,
+        undo: undo_from_names(&save_data.undo, size, lookup)?,
+    })
+}
+
+// Save files use sprite names so they still work if the sprites are reordered
+fn board_to_names(board: &Board, lookup: &SpriteLookup) -> Vec<Vec<String>> {
+    board
+        .positions_iter()
+        .iter()
+        .map(|p| {
+            board
+                .get_sprite_states(p)
+                .iter()
+                .map(|s| lookup.to_name(&s.index).unwrap().clone())
+                .collect()
+        })
+        .collect()
+}
+
+fn board_from_names(
+    cells: &[Vec<String>],
+    size: Dimension,
+    lookup: &SpriteLookup,
+) -> Result<Board, Box<dyn Error>> {
+    let grid = cells
+        .iter()
+        .map(|names| {
+            names
+                .iter()
+                .map(|name| sprite_from_name(name, lookup).copied())
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Board::from_checkpoint(size.width, size.height, grid))
+}
+
+fn sprite_from_name<'a>(
+    name: &str,
+    lookup: &'a SpriteLookup,
+) -> Result<&'a SpriteState, Box<dyn Error>> {
+    lookup
+        .to_id(&name.to_string())
+        .ok_or_else(|| format!("the game does not have a sprite named '{}'", name).into())
+}
+
+// Save files use sprite names (like checkpoints) so they still work if the sprites are reordered
+fn undo_to_names(steps: Vec<BoardDelta>, lookup: &SpriteLookup) -> Vec<Vec<(usize, Vec<String>)>> {
+    steps
+        .iter()
+        .map(|delta| {
+            delta
+                .cells()
+                .iter()
+                .map(|(index, sprites)| {
+                    let names = sprites
+                        .iter()
+                        .map(|(_, sprite_index)| lookup.to_name(sprite_index).unwrap().clone())
+                        .collect();
+                    (*index, names)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn undo_from_names(
+    steps: &[Vec<(usize, Vec<String>)>],
+    size: Dimension,
+    lookup: &SpriteLookup,
+) -> Result<Vec<BoardDelta>, Box<dyn Error>> {
+    steps
+        .iter()
+        .map(|cells| {
+            let cells = cells
+                .iter()
+                .map(|(index, names)| {
+                    let sprites = names
+                        .iter()
+                        .map(|name| {
+                            let sprite = sprite_from_name(name, lookup)?;
+                            Ok((sprite.collision_layer, sprite.index))
+                        })
+                        .collect::<Result<_, Box<dyn Error>>>()?;
+                    Ok((*index, sprites))
+                })
+                .collect::<Result<_, Box<dyn Error>>>()?;
+            BoardDelta::new(cells, size)
+        })
+        .collect()
+}
+
 fn warn_if_alpha_transparency(game: &GameData) {
     if !ColorSpace::get_colorspace().is_true_color() {
         for sprite in game.sprites.values() {
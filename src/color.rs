@@ -47,12 +47,20 @@ impl Rgb {
     }
 
     pub fn parse(hex: &String) -> Rgb {
-        let bytes = hex::decode(&hex[1..]).unwrap(); // Skip the "#"
+        Self::try_parse(hex).unwrap()
+    }
+
+    /// Like `parse()` but returns None when `hex` is not a `#rrggbb` (or `#rrggbbaa`) color
+    pub fn try_parse(hex: &str) -> Option<Rgb> {
+        let bytes = hex::decode(hex.strip_prefix('#')?).ok()?;
+        if bytes.len() < 3 {
+            return None;
+        }
         let r = bytes[0];
         let g = bytes[1];
         let b = bytes[2];
         let a = if bytes.len() > 3 { bytes[3] } else { 0 };
-        Rgb { r, g, b, a }
+        Some(Rgb { r, g, b, a })
     }
 
     pub fn distance(&self, other: &Rgb) -> u8 {
@@ -224,6 +232,7 @@ impl ColorSpace {
         }
     }
 
+    #[cfg(feature = "terminal")]
     pub fn print_bg_color(&self, r: u8, g: u8, b: u8) {
         match self {
             ColorSpace::TrueColor => print!("{}", termion::color::Bg(termion::color::Rgb(r, g, b))),
@@ -238,6 +247,7 @@ impl ColorSpace {
         };
     }
 
+    #[cfg(feature = "terminal")]
     pub fn print_fg_color(&self, r: u8, g: u8, b: u8) {
         match self {
             ColorSpace::TrueColor => print!("{}", termion::color::Fg(termion::color::Rgb(r, g, b))),
@@ -1,28 +1,40 @@
+use std::cmp;
 use std::fmt;
+use std::mem;
 
 use log::debug;
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 
+use crate::history::History;
+use crate::history::DEFAULT_UNDO_BUDGET;
 use crate::model::board::Board;
+use crate::model::board::BoardDelta;
 use crate::model::game::GameData;
+use crate::model::game::GameEvent;
 use crate::model::game::Input;
 use crate::model::game::Level;
+use crate::model::util::Dimension;
 use crate::model::util::Position;
+use crate::observer::EngineObserver;
 
+/// Plays one level of a game at a time. See `tick()`
 #[derive(Debug)]
 pub struct Engine {
     rng: XorShiftRng,
     pub game_data: GameData,
     pub current_level: BoardOrMessage,
-    undo_stack: Vec<Board>,
+    history: History,
+    checkpoint: Option<Board>,
     pub current_level_num: u8,
-    pub debug_rules: bool,
     pub pending_message: Option<String>,
+    // Sounds for events that happened outside of a tick (e.g. starting a level)
+    sounds: Vec<u32>,
 }
 
 // The main enchilada. Pass in a game and a level and then just call engine.tick(Some(EngineInput::Right))
 impl Engine {
+    /// Starts the game at the beginning of `current_level_num`
     pub fn new(game_data: GameData, current_level_num: u8) -> Self {
         let current = &game_data.levels[current_level_num as usize];
         let current_level = match current {
@@ -30,32 +42,107 @@ impl Engine {
             Level::Map(_) => BoardOrMessage::Board(game_data.to_board(&current)),
         };
 
-        Self {
+        let mut engine = Self {
             rng: new_rng(),
             game_data,
             current_level,
             current_level_num,
-            undo_stack: vec![],
-            debug_rules: false,
+            history: History::new(DEFAULT_UNDO_BUDGET),
+            checkpoint: None,
             pending_message: None,
+            sounds: vec![],
+        };
+        if current_level_num == 0 {
+            queue_sound(&engine.game_data, &mut engine.sounds, GameEvent::StartGame);
         }
+        engine.queue_level_sound();
+        engine
+    }
+
+    /// Same as `new()` but random rules draw from a generator seeded with `seed`
+    /// instead of the fixed default one
+    pub fn new_with_seed(game_data: GameData, current_level_num: u8, seed: u64) -> Self {
+        let mut engine = Self::new(game_data, current_level_num);
+        engine.rng = XorShiftRng::seed_from_u64(seed);
+        engine
     }
 
+    /// Resumes a level from a previously saved checkpoint board
     pub fn from_checkpoint(game_data: GameData, current_level_num: u8, checkpoint: Board) -> Self {
-        let current_level = BoardOrMessage::Board(checkpoint);
+        let current_level = BoardOrMessage::Board(checkpoint.clone());
 
-        Self {
+        let mut engine = Self {
             rng: new_rng(),
             game_data,
             current_level,
             current_level_num,
-            undo_stack: vec![],
-            debug_rules: false,
+            history: History::new(DEFAULT_UNDO_BUDGET),
+            checkpoint: Some(checkpoint),
             pending_message: None,
-        }
+            sounds: vec![],
+        };
+        engine.queue_level_sound();
+        engine
     }
 
+    /// Advances the game by one frame. Pass `None` when there is no input
+    /// (e.g. to let `AGAIN` rules keep running)
     pub fn tick(&mut self, input: Option<EngineInput>) -> TickResult {
+        self.tick_with_observer(input, &mut ())
+    }
+
+    /// Same as `tick()` but tells `observer` what the rules and movements did along the way
+    pub fn tick_with_observer(
+        &mut self,
+        input: Option<EngineInput>,
+        observer: &mut dyn EngineObserver,
+    ) -> TickResult {
+        let mut tr = self.tick_level(input, observer);
+        if tr.restarted {
+            queue_sound(&self.game_data, &mut self.sounds, GameEvent::Restart);
+        }
+        if tr.completed_level.is_some() {
+            if let BoardOrMessage::Board(_) = self.current_level {
+                queue_sound(&self.game_data, &mut self.sounds, GameEvent::EndLevel);
+            }
+            if self.current_level_num as usize + 1 == self.game_data.levels.len() {
+                queue_sound(&self.game_data, &mut self.sounds, GameEvent::EndGame);
+            }
+        }
+
+        // The sounds of the events come first, then the ones from the rules
+        let mut sfx = mem::take(&mut self.sounds);
+        for seed in tr.sfx {
+            if !sfx.contains(&seed) {
+                sfx.push(seed);
+            }
+        }
+        tr.sfx = sfx;
+        tr
+    }
+
+    /// The sounds for events that happened since the last tick (e.g. `startlevel` after `next_level()`).
+    /// Otherwise they are played with the next tick
+    pub fn take_sounds(&mut self) -> Vec<u32> {
+        mem::take(&mut self.sounds)
+    }
+
+    fn queue_level_sound(&mut self) {
+        match self.current_level {
+            BoardOrMessage::Board(_) => {
+                queue_sound(&self.game_data, &mut self.sounds, GameEvent::StartLevel)
+            }
+            BoardOrMessage::Message(_) => {
+                queue_sound(&self.game_data, &mut self.sounds, GameEvent::ShowMessage)
+            }
+        }
+    }
+
+    fn tick_level(
+        &mut self,
+        input: Option<EngineInput>,
+        observer: &mut dyn EngineObserver,
+    ) -> TickResult {
         let mut changed = false;
         match input {
             None => debug!("Tick start"),
@@ -66,6 +153,7 @@ impl Engine {
             match input {
                 Some(EngineInput::Action) => {
                     self.pending_message = None;
+                    queue_sound(&self.game_data, &mut self.sounds, GameEvent::CloseMessage);
                     return TickResult::empty().affected();
                 }
                 _ => return TickResult::empty(),
@@ -74,68 +162,134 @@ impl Engine {
 
         match &self.current_level {
             BoardOrMessage::Board(board) => {
+                let metadata = &self.game_data.metadata;
+                match input {
+                    Some(EngineInput::Undo) | Some(EngineInput::Redo) if metadata.no_undo => {
+                        debug!("Ignoring UNDO/REDO because the game has noundo set");
+                        return TickResult::empty();
+                    }
+                    Some(EngineInput::Restart) if metadata.no_restart => {
+                        debug!("Ignoring RESTART because the game has norestart set");
+                        return TickResult::empty();
+                    }
+                    Some(EngineInput::Action) if metadata.no_action => {
+                        debug!("Ignoring ACTION because the game has noaction set");
+                        return TickResult::empty();
+                    }
+                    Some(EngineInput::Redo) => {
+                        // The board goes back to exactly how it was before the undo so the rules do not run
+                        return match self.history.redo(board) {
+                            None => TickResult::empty(),
+                            Some(b) => {
+                                self.current_level = BoardOrMessage::Board(b);
+                                TickResult::empty().affected()
+                            }
+                        };
+                    }
+                    _ => {}
+                }
+
+                observer.tick_started(&self.game_data, input, board);
                 let mut pressed = false;
+                let mut restarted = false;
                 let mut new = board.clone();
                 match input {
                     None => {}
                     Some(EngineInput::Up) => {
                         pressed = true;
-                        self.game_data
-                            .evaluate_player_input(&mut self.rng, &mut new, Input::Up)
+                        self.game_data.evaluate_player_input(
+                            &mut self.rng,
+                            &mut new,
+                            Input::Up,
+                            observer,
+                        )
                     }
                     Some(EngineInput::Down) => {
                         pressed = true;
-                        self.game_data
-                            .evaluate_player_input(&mut self.rng, &mut new, Input::Down)
+                        self.game_data.evaluate_player_input(
+                            &mut self.rng,
+                            &mut new,
+                            Input::Down,
+                            observer,
+                        )
                     }
                     Some(EngineInput::Left) => {
                         pressed = true;
-                        self.game_data
-                            .evaluate_player_input(&mut self.rng, &mut new, Input::Left)
+                        self.game_data.evaluate_player_input(
+                            &mut self.rng,
+                            &mut new,
+                            Input::Left,
+                            observer,
+                        )
                     }
                     Some(EngineInput::Right) => {
                         pressed = true;
-                        self.game_data
-                            .evaluate_player_input(&mut self.rng, &mut new, Input::Right)
+                        self.game_data.evaluate_player_input(
+                            &mut self.rng,
+                            &mut new,
+                            Input::Right,
+                            observer,
+                        )
                     }
                     Some(EngineInput::Action) => {
                         pressed = true;
-                        self.game_data
-                            .evaluate_player_input(&mut self.rng, &mut new, Input::Action)
+                        self.game_data.evaluate_player_input(
+                            &mut self.rng,
+                            &mut new,
+                            Input::Action,
+                            observer,
+                        )
                     }
-                    Some(EngineInput::Restart) => match self.undo_stack.first() {
-                        None => {}
-                        Some(b) => new = b.clone(),
-                    },
-                    Some(EngineInput::Undo) => match self.undo_stack.pop() {
+                    Some(EngineInput::Restart) => {
+                        restarted = true;
+                        new = self.restart_board();
+                    }
+                    Some(EngineInput::Undo) => match self.history.undo(board) {
                         None => {}
-                        Some(b) => new = b,
+                        Some(b) => {
+                            queue_sound(&self.game_data, &mut self.sounds, GameEvent::Undo);
+                            new = b
+                        }
                     },
+                    Some(EngineInput::Redo) => unreachable!("REDO returns early"),
+                }
+                let mut t = self.game_data.evaluate(&mut self.rng, &mut new, observer);
+
+                // The turn only counts if at least one of the players moved
+                if input.map(|i| i.is_direction()).unwrap_or(false)
+                    && self.game_data.player_failed_to_move(board, &new)
+                {
+                    debug!("Cancelling the turn because the player did not move (require_player_movement)");
+                    t.cancel = true;
+                    t.win = false;
+                    t.sfx.clear();
                 }
-                let t = self
-                    .game_data
-                    .evaluate(&mut self.rng, &mut new, self.debug_rules);
 
                 let mut new_board = None;
                 if !t.cancel {
-                    if t.message.is_some() {
-                        self.pending_message = Some(t.message.unwrap().clone());
-                    }
+                    if t.restart {
+                        // Throw away this turn and go back to the start of the level (or the last checkpoint)
+                        debug!("RESTART command found. Restarting the level");
+                        restarted = true;
+                        new = self.restart_board();
+                    } else {
+                        if t.message.is_some() {
+                            self.pending_message = Some(t.message.clone().unwrap());
+                            queue_sound(&self.game_data, &mut self.sounds, GameEvent::ShowMessage);
+                        }
 
-                    if t.checkpoint {
-                        debug!("Checkpoint reached. Clearing Undo Stack");
-                        self.undo_stack.clear();
+                        if t.checkpoint {
+                            debug!("Checkpoint reached. Clearing the undo history");
+                            self.history.clear();
+                            self.checkpoint = Some(new.clone());
+                        }
                     }
 
                     changed = new != *board;
 
-                    if pressed && changed {
-                        debug!("Pushing to the Undo Stack");
-                        // Keep the undo stack at a manageable size
-                        if self.undo_stack.len() > 100 {
-                            self.undo_stack.drain(1..50);
-                        }
-                        self.undo_stack.push(board.clone());
+                    if (pressed || restarted) && changed {
+                        debug!("Adding the move to the undo history");
+                        self.history.push(board, &new);
                     }
 
                     new_board = Some(BoardOrMessage::Board(new));
@@ -144,24 +298,29 @@ impl Engine {
                     None => {}
                     Some(n) => self.current_level = n,
                 }
-                TickResult {
+                let restarted = restarted && !t.cancel;
+                let tr = TickResult {
                     changed: changed,
-                    completed_level: if t.win {
+                    completed_level: if t.win && !restarted {
                         Some(self.current_level_num)
                     } else {
                         None
                     },
-                    checkpoint: if t.checkpoint {
+                    restarted,
+                    checkpoint: if t.checkpoint && !restarted {
                         Some(self.current_level.unwrap_board().clone())
                     } else {
                         None
                     },
-                    accepting_input: !t.again,
+                    accepting_input: !t.again || restarted,
                     sfx: t.sfx,
-                }
+                };
+                observer.tick_finished(self.current_level.unwrap_board(), &tr);
+                tr
             }
             BoardOrMessage::Message(_) => match input {
                 Some(EngineInput::Action) => {
+                    queue_sound(&self.game_data, &mut self.sounds, GameEvent::CloseMessage);
                     TickResult::empty().affected().win(self.current_level_num)
                 }
                 _ => TickResult::empty(),
@@ -169,16 +328,91 @@ impl Engine {
         }
     }
 
+    /// How many bytes the undo history may use. The oldest moves are forgotten first
+    pub fn set_undo_budget(&mut self, bytes: usize) {
+        self.history.set_budget(bytes);
+    }
+
+    /// The undo steps of the current level (oldest first) so they can be saved
+    pub fn undo_history(&self) -> Vec<BoardDelta> {
+        match &self.current_level {
+            BoardOrMessage::Board(board) => self.history.steps(board),
+            BoardOrMessage::Message(_) => vec![],
+        }
+    }
+
+    /// Restores the undo steps that `undo_history()` returned for the same board
+    pub fn set_undo_history(&mut self, steps: Vec<BoardDelta>) {
+        if let BoardOrMessage::Board(board) = &self.current_level {
+            self.history.set_steps(steps, board);
+        }
+    }
+
+    /// Continues the current level from `board` (e.g. where a saved game was quit).
+    /// RESTART still goes back to the checkpoint or the start of the level
+    pub fn set_board(&mut self, board: Board) {
+        if let BoardOrMessage::Board(_) = &self.current_level {
+            self.current_level = BoardOrMessage::Board(board);
+        }
+    }
+
+    pub fn checkpoint(&self) -> Option<&Board> {
+        self.checkpoint.as_ref()
+    }
+
+    /// The state of the random number generator. Save it alongside the board to resume a random game exactly
+    pub fn rng(&self) -> &XorShiftRng {
+        &self.rng
+    }
+
+    pub fn set_rng(&mut self, rng: XorShiftRng) {
+        self.rng = rng;
+    }
+
+    // The board that RESTART goes back to
+    fn restart_board(&self) -> Board {
+        match &self.checkpoint {
+            Some(checkpoint) => checkpoint.clone(),
+            None => self
+                .game_data
+                .to_board(&self.game_data.levels[self.current_level_num as usize]),
+        }
+    }
+
+    /// The part of the board that is shown. Games with `flickscreen` or `zoomscreen`
+    /// only show the screen around the player. Returns the top-left cell and the size
+    pub fn screen_window(&self, board_size: Dimension) -> (Position, Dimension) {
+        let is_flickscreen = self.game_data.metadata.flickscreen.is_some();
+        let screen_size = self
+            .game_data
+            .metadata
+            .flickscreen
+            .or(self.game_data.metadata.zoomscreen);
+        match screen_size {
+            None => (Position::new(0, 0), board_size),
+            Some(flick) => {
+                let width = cmp::min(flick.width, board_size.width); // see atlas-shrank
+                let height = cmp::min(flick.height, board_size.height);
+                let player = self.player_position().unwrap_or_default();
+                let top_left = if is_flickscreen {
+                    Position::new(player.x / width * width, player.y / height * height)
+                } else {
+                    // zoomscreen
+                    Position::new(
+                        player.x.saturating_sub(width / 2),
+                        player.y.saturating_sub(height / 2),
+                    )
+                };
+                (top_left, Dimension { width, height })
+            }
+        }
+    }
+
     pub fn player_position(&self) -> Option<Position> {
         match &self.current_level {
             BoardOrMessage::Message(_) => None,
             BoardOrMessage::Board(board) => {
-                let matches: Vec<Position> = board
-                    .positions_iter()
-                    .iter()
-                    .filter(|&p| board.matches(p, &self.game_data.player_tile, &None))
-                    .map(|&p| p)
-                    .collect();
+                let matches = self.game_data.player_positions(board);
 
                 if matches.len() != 1 {
                     None
@@ -193,7 +427,12 @@ impl Engine {
     }
 
     pub fn next_level(&mut self) -> bool {
-        self.current_level_num = self.current_level_num + 1;
+        self.goto_level(self.current_level_num + 1)
+    }
+
+    /// Starts the level (map or message) at `level_num`. Returns false when there is no such level
+    pub fn goto_level(&mut self, level_num: u8) -> bool {
+        self.current_level_num = level_num;
         self.pending_message = None;
 
         if self.current_level_num as usize >= self.game_data.levels.len() {
@@ -204,12 +443,15 @@ impl Engine {
             Level::Message(message) => BoardOrMessage::Message(message.clone()),
             Level::Map(_) => BoardOrMessage::Board(self.game_data.to_board(&current)),
         };
-        self.undo_stack.clear();
+        self.history.clear();
+        self.checkpoint = None;
+        self.queue_level_sound();
         true
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// A key press that is sent to `Engine::tick()`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum EngineInput {
     Up,
     Down,
@@ -217,10 +459,20 @@ pub enum EngineInput {
     Right,
     Action,
     Undo,
+    Redo,
     Restart,
 }
 
 impl EngineInput {
+    pub fn is_direction(&self) -> bool {
+        match self {
+            EngineInput::Up | EngineInput::Down | EngineInput::Left | EngineInput::Right => true,
+            EngineInput::Action | EngineInput::Undo | EngineInput::Redo | EngineInput::Restart => {
+                false
+            }
+        }
+    }
+
     pub fn to_key(&self) -> char {
         match self {
             EngineInput::Up => 'W',
@@ -229,6 +481,7 @@ impl EngineInput {
             EngineInput::Right => 'D',
             EngineInput::Action => 'X',
             EngineInput::Undo => 'Z',
+            EngineInput::Redo => 'Y',
             EngineInput::Restart => 'R',
         }
     }
@@ -243,13 +496,24 @@ impl fmt::Display for EngineInput {
             EngineInput::Right => "RIGHT",
             EngineInput::Action => "ACTION",
             EngineInput::Undo => "UNDO",
+            EngineInput::Redo => "REDO",
             EngineInput::Restart => "RESTART",
         };
         write!(f, "{}", msg)
     }
 }
 
-fn new_rng() -> XorShiftRng {
+// Adds the sound that the SOUNDS section plays for `event` (it is a free function so it can be
+// called while the board is borrowed)
+fn queue_sound(game_data: &GameData, sounds: &mut Vec<u32>, event: GameEvent) {
+    if let Some(seed) = game_data.sound_for(event) {
+        if !sounds.contains(&seed) {
+            sounds.push(seed);
+        }
+    }
+}
+
+pub(crate) fn new_rng() -> XorShiftRng {
     XorShiftRng::from_seed([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
 }
 
@@ -268,12 +532,15 @@ impl BoardOrMessage {
     }
 }
 
+/// What happened during one `Engine::tick()`
 pub struct TickResult {
     pub changed: bool,
     pub completed_level: Option<u8>,
+    pub restarted: bool,
     pub checkpoint: Option<Board>,
     pub accepting_input: bool,
-    pub sfx: bool,
+    /// The sfxr seeds of the sound effects to play (see `sfxr::Params::from_seed()`)
+    pub sfx: Vec<u32>,
 }
 
 impl TickResult {
@@ -281,9 +548,10 @@ impl TickResult {
         Self {
             changed: false,
             completed_level: None,
+            restarted: false,
             checkpoint: None,
             accepting_input: true,
-            sfx: false,
+            sfx: vec![],
         }
     }
 
@@ -297,3 +565,286 @@ impl TickResult {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture;
+    use crate::model::util::Position;
+    use crate::profile::Profiler;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    // A 1-row level where the player walks to the right: `P..O`
+    fn build_engine(prelude: &str, rules: &str) -> Engine {
+        Engine::new(build_game(prelude, rules), 0)
+    }
+
+    fn build_game(prelude: &str, rules: &str) -> GameData {
+        fixture::game_with_prelude(prelude, rules, "P..O")
+    }
+
+    #[test]
+    fn restart_command_resets_the_level() {
+        init();
+        let mut engine = build_engine("", "late [ Player Hole ] -> [ Player Hole ] RESTART");
+        let start = engine.current_level.unwrap_board().clone();
+
+        engine.tick(Some(EngineInput::Right));
+        engine.tick(Some(EngineInput::Right));
+        let before_restart = engine.current_level.unwrap_board().clone();
+        let t = engine.tick(Some(EngineInput::Right));
+
+        assert!(t.restarted);
+        assert_eq!(t.completed_level, None);
+        assert!(*engine.current_level.unwrap_board() == start);
+
+        // Undo goes back to the board just before the restart
+        engine.tick(Some(EngineInput::Undo));
+        assert!(*engine.current_level.unwrap_board() == before_restart);
+    }
+
+    #[test]
+    fn restart_command_goes_back_to_the_checkpoint() {
+        init();
+        let mut engine = build_engine(
+            "",
+            "late [ Player | Hole ] -> [ Player | Hole ] CHECKPOINT\nlate [ Player Hole ] -> [ Player Hole ] RESTART",
+        );
+
+        engine.tick(Some(EngineInput::Right));
+        let t = engine.tick(Some(EngineInput::Right));
+        assert!(t.checkpoint.is_some());
+        let checkpoint = engine.current_level.unwrap_board().clone();
+
+        let t = engine.tick(Some(EngineInput::Right));
+        assert!(t.restarted);
+        assert!(*engine.current_level.unwrap_board() == checkpoint);
+    }
+
+    #[test]
+    fn restart_input_resets_the_level() {
+        init();
+        let mut engine = build_engine("", "");
+        let start = engine.current_level.unwrap_board().clone();
+
+        engine.tick(Some(EngineInput::Right));
+        let moved = engine.current_level.unwrap_board().clone();
+        let t = engine.tick(Some(EngineInput::Restart));
+        assert!(t.restarted);
+        assert!(*engine.current_level.unwrap_board() == start);
+
+        engine.tick(Some(EngineInput::Undo));
+        assert!(*engine.current_level.unwrap_board() == moved);
+    }
+
+    #[test]
+    fn redo_replays_the_undone_moves() {
+        init();
+        let mut engine = build_engine("", "");
+        let start = engine.current_level.unwrap_board().clone();
+        engine.tick(Some(EngineInput::Right));
+        let moved = engine.current_level.unwrap_board().clone();
+        engine.tick(Some(EngineInput::Right));
+        let end = engine.current_level.unwrap_board().clone();
+
+        engine.tick(Some(EngineInput::Undo));
+        engine.tick(Some(EngineInput::Undo));
+        assert!(*engine.current_level.unwrap_board() == start);
+        assert!(engine.tick(Some(EngineInput::Redo)).changed);
+        assert!(*engine.current_level.unwrap_board() == moved);
+        engine.tick(Some(EngineInput::Redo));
+        assert!(*engine.current_level.unwrap_board() == end);
+        assert!(!engine.tick(Some(EngineInput::Redo)).changed);
+
+        // A new move replaces the moves that could be redone
+        engine.tick(Some(EngineInput::Undo));
+        engine.tick(Some(EngineInput::Left));
+        assert!(!engine.tick(Some(EngineInput::Redo)).changed);
+    }
+
+    #[test]
+    fn resumes_a_level_with_its_undo_history() {
+        init();
+        let mut engine = build_engine("", "");
+        let start = engine.current_level.unwrap_board().clone();
+        engine.tick(Some(EngineInput::Right));
+        engine.tick(Some(EngineInput::Right));
+        let quit = engine.current_level.unwrap_board().clone();
+        let undo = engine.undo_history();
+
+        let mut engine = build_engine("", "");
+        engine.set_board(quit.clone());
+        engine.set_undo_history(undo);
+        assert!(*engine.current_level.unwrap_board() == quit);
+        engine.tick(Some(EngineInput::Undo));
+        engine.tick(Some(EngineInput::Undo));
+        assert!(*engine.current_level.unwrap_board() == start);
+
+        engine.set_board(quit);
+        engine.tick(Some(EngineInput::Restart));
+        assert!(*engine.current_level.unwrap_board() == start);
+    }
+
+    #[test]
+    fn goes_to_a_level() {
+        init();
+        let mut engine = build_engine("", "");
+        let start = engine.current_level.unwrap_board().clone();
+        engine.tick(Some(EngineInput::Right));
+
+        assert!(engine.goto_level(0));
+        assert!(*engine.current_level.unwrap_board() == start);
+        assert!(!engine.tick(Some(EngineInput::Undo)).changed);
+        assert!(!engine.goto_level(1));
+    }
+
+    #[test]
+    fn noundo_and_norestart_ignore_the_input() {
+        init();
+        let mut engine = build_engine("noundo\nnorestart", "");
+        engine.tick(Some(EngineInput::Right));
+        let moved = engine.current_level.unwrap_board().clone();
+
+        let t = engine.tick(Some(EngineInput::Undo));
+        assert!(!t.changed);
+        let t = engine.tick(Some(EngineInput::Redo));
+        assert!(!t.changed);
+        let t = engine.tick(Some(EngineInput::Restart));
+        assert!(!t.changed);
+        assert!(!t.restarted);
+        assert!(*engine.current_level.unwrap_board() == moved);
+    }
+
+    #[test]
+    fn noaction_ignores_the_action_input() {
+        init();
+        let mut engine = build_engine("noaction", "[ action Player ] -> [ Player Hole ]");
+        let t = engine.tick(Some(EngineInput::Action));
+        assert!(!t.changed);
+
+        let mut engine = build_engine("", "[ action Player ] -> [ Player Hole ]");
+        let t = engine.tick(Some(EngineInput::Action));
+        assert!(t.changed);
+    }
+
+    #[test]
+    fn require_player_movement_cancels_the_turn() {
+        init();
+        // Every move leaves a hole behind but the player cannot move left off the board
+        let rules = "[ > Player no Hole ] -> [ > Player Hole ]";
+        let mut engine = build_engine("", rules);
+        let t = engine.tick(Some(EngineInput::Left));
+        assert!(t.changed);
+
+        let mut engine = build_engine("require_player_movement", rules);
+        let start = engine.current_level.unwrap_board().clone();
+        let t = engine.tick(Some(EngineInput::Left));
+        assert!(!t.changed);
+        assert!(*engine.current_level.unwrap_board() == start);
+
+        let t = engine.tick(Some(EngineInput::Right));
+        assert!(t.changed);
+    }
+
+    #[test]
+    fn require_player_movement_needs_a_player() {
+        init();
+        let game = build_game("require_player_movement", "");
+        let mut board = game.to_board(&game.levels[0]);
+        assert!(game.player_failed_to_move(&board, &board));
+
+        // Without a player there is nothing that could have moved so the turn is not cancelled
+        let player_layer = 2;
+        board.remove_collision_layer(&Position::new(0, 0), player_layer);
+        assert!(!game.player_failed_to_move(&board, &board));
+    }
+
+    #[test]
+    fn profiles_the_rules() {
+        init();
+        let mut profiler = Profiler::default();
+        let mut engine = build_engine("", "[ action Player ] -> [ Player Hole ]");
+        engine.tick_with_observer(Some(EngineInput::Action), &mut profiler);
+        engine.tick_with_observer(Some(EngineInput::Action), &mut profiler);
+        engine.tick_with_observer(Some(EngineInput::Right), &mut profiler);
+
+        // The engine's rules that move the player have no source line
+        let profile = profiler.take();
+        assert_eq!(profile.rules.len(), 2);
+        let rule = profile.rules.iter().find(|r| r.line.is_some()).unwrap();
+        assert_eq!(rule.matches, 2);
+        assert!(rule.attempts > rule.matches);
+        assert_eq!(rule.permutations, 2);
+    }
+
+    #[test]
+    fn same_seed_plays_the_same_random_game() {
+        init();
+        let rules = "[ action Player ] -> [ randomdir Player ]";
+        let boards = |seed| {
+            let mut engine = Engine::new_with_seed(build_game("", rules), 0, seed);
+            (0..2)
+                .map(|_| {
+                    engine.tick(Some(EngineInput::Action));
+                    engine.current_level.unwrap_board().clone()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(boards(7), boards(7));
+        assert!((0..10).any(|seed| boards(seed) != boards(7)));
+    }
+
+    #[test]
+    fn restored_rng_resumes_the_random_game() {
+        init();
+        let rules = "[ action Player ] -> [ randomdir Player ]";
+        let mut engine = Engine::new_with_seed(build_game("", rules), 0, 7);
+        engine.tick(Some(EngineInput::Action));
+
+        // Resume from the board and the (serialized) RNG like a save file would
+        let saved = serde_json::to_string(engine.rng()).unwrap();
+        let mut resumed = Engine::from_checkpoint(
+            build_game("", rules),
+            0,
+            engine.current_level.unwrap_board().clone(),
+        );
+        resumed.set_rng(serde_json::from_str(&saved).unwrap());
+
+        engine.tick(Some(EngineInput::Action));
+        resumed.tick(Some(EngineInput::Action));
+        assert_eq!(
+            engine.current_level.unwrap_board(),
+            resumed.current_level.unwrap_board()
+        );
+    }
+
+    #[test]
+    fn plays_the_sounds_of_events() {
+        init();
+        let sounds = "SOUNDS
+startlevel 1
+Player move 2
+Player cantmove 3
+Hole destroy 4
+endlevel 5
+undo 6";
+        let mut engine = build_engine(sounds, "late [ Player Hole ] -> [ Player ] WIN");
+        assert_eq!(engine.take_sounds(), vec![1]);
+
+        assert_eq!(engine.tick(Some(EngineInput::Left)).sfx, vec![3]);
+        assert_eq!(engine.tick(Some(EngineInput::Right)).sfx, vec![2]);
+        assert_eq!(engine.tick(Some(EngineInput::Undo)).sfx, vec![6]);
+        assert_eq!(engine.tick(None).sfx, Vec::<u32>::new());
+
+        engine.tick(Some(EngineInput::Right));
+        engine.tick(Some(EngineInput::Right));
+        let t = engine.tick(Some(EngineInput::Right));
+        assert_eq!(t.completed_level, Some(0));
+        assert_eq!(t.sfx, vec![5, 4, 2]);
+    }
+}
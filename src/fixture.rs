@@ -0,0 +1,82 @@
+//! The game that the unit tests play. Each test only brings its own rules and levels
+
+use crate::engine::Engine;
+use crate::model::game::GameData;
+use crate::parser;
+
+// `_` is another name for Background (for the legend tests)
+const SOURCE: &str = "
+{prelude}
+
+OBJECTS
+Background
+black
+Player
+white
+Target
+red
+Wall
+gray
+Crate
+orange
+Flag
+yellow
+Hole
+purple
+Ice
+blue
+Sign
+brown
+
+LEGEND
+. = Background
+_ = Background
+P = Player
+T = Target
+# = Wall
+C = Crate
+F = Flag
+O = Hole
+I = Ice
+S = Sign
+@ = Crate and Target
+
+COLLISIONLAYERS
+Background
+Target, Flag, Hole, Ice, Sign
+Player, Wall, Crate
+
+RULES
+{rules}
+
+WINCONDITIONS
+All Player on Target
+
+LEVELS
+{levels}
+";
+
+/// The source line of the first rule (when the prelude is one line). Lines count from 1
+pub const FIRST_RULE_LINE: usize = 43;
+
+/// The text of the game, for tests that work on the parsed source instead of `GameData`
+pub fn source(prelude: &str, rules: &str, levels: &str) -> String {
+    SOURCE
+        .replace("{prelude}", prelude)
+        .replace("{rules}", rules)
+        .replace("{levels}", levels)
+}
+
+/// Same as `game()` with metadata lines (e.g. `flickscreen 2x1`) at the top of the source
+pub fn game_with_prelude(prelude: &str, rules: &str, levels: &str) -> GameData {
+    parser::parse_source(source(prelude, rules, levels).as_bytes()).unwrap()
+}
+
+pub fn game(rules: &str, levels: &str) -> GameData {
+    game_with_prelude("", rules, levels)
+}
+
+/// An engine at the first level
+pub fn engine(rules: &str, levels: &str) -> Engine {
+    Engine::new(game(rules, levels), 0)
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+
+use crate::model::board::Board;
+use crate::model::board::BoardDelta;
+
+/// How much memory the undo history may use unless `Engine::set_undo_budget()` says otherwise
+pub const DEFAULT_UNDO_BUDGET: usize = 16 * 1024 * 1024;
+
+/// The undo/redo timeline of a level. Each step only keeps the cells that changed.
+/// When the undo steps use more than `budget` bytes the oldest ones are forgotten
+#[derive(Debug)]
+pub struct History {
+    undo: VecDeque<BoardDelta>,
+    redo: Vec<BoardDelta>,
+    // The board that the newest undo and redo steps apply to
+    tip: Option<Board>,
+    bytes: usize,
+    budget: usize,
+}
+
+impl History {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            tip: None,
+            bytes: 0,
+            budget,
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.shrink();
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Remembers a move from `before` to `after`. Making a new move forgets everything that could be redone
+    pub fn push(&mut self, before: &Board, after: &Board) {
+        self.rebase(before);
+        self.redo.clear();
+        self.push_undo(before.delta_from(after));
+        self.tip = Some(after.clone());
+        self.shrink();
+    }
+
+    /// The board before the last move (None when there is nothing to undo)
+    pub fn undo(&mut self, current: &Board) -> Option<Board> {
+        self.rebase(current);
+        let delta = self.undo.pop_back()?;
+        self.bytes -= delta.size_in_bytes();
+        let mut board = current.clone();
+        board.apply(&delta);
+        self.redo.push(current.delta_from(&board));
+        self.tip = Some(board.clone());
+        Some(board)
+    }
+
+    /// The board after the last undone move (None when there is nothing to redo)
+    pub fn redo(&mut self, current: &Board) -> Option<Board> {
+        self.rebase(current);
+        let delta = self.redo.pop()?;
+        let mut board = current.clone();
+        board.apply(&delta);
+        self.push_undo(current.delta_from(&board));
+        self.tip = Some(board.clone());
+        self.shrink();
+        Some(board)
+    }
+
+    /// The undo steps (oldest first) so they can be saved. The newest one applies to `current`
+    pub fn steps(&self, current: &Board) -> Vec<BoardDelta> {
+        let mut steps: Vec<BoardDelta> = self.undo.iter().cloned().collect();
+        if let (Some(tip), Some(newest)) = (&self.tip, steps.pop()) {
+            steps.push(newest.rebase(tip, current));
+        }
+        steps
+    }
+
+    /// Replaces the history with saved undo steps. The newest one must apply to `current`
+    pub fn set_steps(&mut self, steps: Vec<BoardDelta>, current: &Board) {
+        self.clear();
+        for delta in steps {
+            self.push_undo(delta);
+        }
+        self.tip = Some(current.clone());
+        self.shrink();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.tip = None;
+        self.bytes = 0;
+    }
+
+    fn push_undo(&mut self, delta: BoardDelta) {
+        self.bytes += delta.size_in_bytes();
+        self.undo.push_back(delta);
+    }
+
+    // Ticks that are not moves (realtime or AGAIN ticks) change the board without adding a step.
+    // Make the newest steps apply to `current` so undo still goes back to the board before the move
+    fn rebase(&mut self, current: &Board) {
+        let tip = match self.tip.take() {
+            Some(tip) => tip,
+            None => return,
+        };
+        if let Some(delta) = self.undo.pop_back() {
+            self.bytes -= delta.size_in_bytes();
+            let delta = delta.rebase(&tip, current);
+            self.push_undo(delta);
+        }
+        if let Some(delta) = self.redo.pop() {
+            self.redo.push(delta.rebase(&tip, current));
+        }
+    }
+
+    fn shrink(&mut self) {
+        while self.bytes > self.budget {
+            match self.undo.pop_front() {
+                None => break,
+                Some(delta) => self.bytes -= delta.size_in_bytes(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::util::Position;
+    use crate::model::util::SpriteState;
+    use crate::model::util::WantsToMove;
+
+    fn board_with_player_at(x: u16) -> Board {
+        let player = SpriteState::new(&String::from("Player"), 0, 0);
+        let mut board = Board::new(4, 1);
+        board.add_sprite(&Position::new(x, 0), &player, WantsToMove::Stationary);
+        board
+    }
+
+    #[test]
+    fn undoes_and_redoes() {
+        let boards: Vec<Board> = (0..4).map(board_with_player_at).collect();
+        let mut history = History::new(DEFAULT_UNDO_BUDGET);
+        history.push(&boards[0], &boards[1]);
+        history.push(&boards[1], &boards[2]);
+
+        assert_eq!(history.undo(&boards[2]), Some(boards[1].clone()));
+        assert_eq!(history.undo(&boards[1]), Some(boards[0].clone()));
+        assert_eq!(history.undo(&boards[0]), None);
+        assert_eq!(history.redo(&boards[0]), Some(boards[1].clone()));
+        assert_eq!((history.undo_len(), history.redo_len()), (1, 1));
+
+        // A new move cannot be redone past
+        history.push(&boards[1], &boards[3]);
+        assert_eq!(history.redo(&boards[3]), None);
+        assert_eq!(history.undo(&boards[3]), Some(boards[1].clone()));
+    }
+
+    #[test]
+    fn undoes_ticks_that_were_not_moves() {
+        let boards: Vec<Board> = (0..4).map(board_with_player_at).collect();
+        let mut history = History::new(DEFAULT_UNDO_BUDGET);
+        history.push(&boards[0], &boards[1]);
+        // Something else moved the player from 1 to 2 before the next move
+        history.push(&boards[2], &boards[3]);
+
+        assert_eq!(history.undo(&boards[3]), Some(boards[2].clone()));
+        assert_eq!(history.undo(&boards[2]), Some(boards[0].clone()));
+        assert_eq!(history.redo(&boards[1]), Some(boards[2].clone()));
+    }
+
+    #[test]
+    fn restores_saved_steps() {
+        let boards: Vec<Board> = (0..4).map(board_with_player_at).collect();
+        let mut history = History::new(DEFAULT_UNDO_BUDGET);
+        history.push(&boards[0], &boards[1]);
+        history.push(&boards[1], &boards[2]);
+        // The game was saved after something else moved the player
+        let steps = history.steps(&boards[3]);
+
+        let mut history = History::new(DEFAULT_UNDO_BUDGET);
+        history.set_steps(steps, &boards[3]);
+        assert_eq!(history.undo(&boards[3]), Some(boards[1].clone()));
+        assert_eq!(history.undo(&boards[1]), Some(boards[0].clone()));
+    }
+
+    #[test]
+    fn forgets_the_oldest_moves() {
+        let boards: Vec<Board> = (0..4).map(board_with_player_at).collect();
+        let size = boards[0].delta_from(&boards[1]).size_in_bytes();
+        let mut history = History::new(size * 2);
+        history.push(&boards[0], &boards[1]);
+        history.push(&boards[1], &boards[2]);
+        history.push(&boards[2], &boards[3]);
+        assert_eq!(history.undo_len(), 2);
+
+        assert_eq!(history.undo(&boards[3]), Some(boards[2].clone()));
+        assert_eq!(history.undo(&boards[2]), Some(boards[1].clone()));
+        assert_eq!(history.undo(&boards[1]), None);
+
+        history.set_budget(0);
+        assert_eq!(history.undo_len(), 0);
+    }
+}
@@ -15,6 +15,8 @@ pub struct GameMap {
     pub title: String,
     pub metadata: Metadata,
     pub colors: FnvHashMap<String, String>,
+    #[serde(default)]
+    pub sounds: FnvHashMap<String, Sound<String>>,
     pub collision_layers: Vec<CollisionLayer>,
     pub commands: FnvHashMap<String, Command<String>>,
     pub sprites: FnvHashMap<String, Sprite<u16>>,
@@ -28,6 +30,14 @@ pub struct GameMap {
     pub win_conditions: Vec<WinCondition<String>>,
 }
 
+/// Just enough of a game to list it (the game browser reads every game in a directory)
+#[derive(Deserialize, Debug)]
+pub struct GameSummary {
+    pub title: String,
+    pub metadata: Metadata,
+    pub levels: Vec<Level<serde::de::IgnoredAny>>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Dimension {
     pub width: u16,
@@ -60,7 +70,7 @@ pub struct Metadata {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CollisionLayer {
-    id: u16,
+    pub id: u16,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -74,6 +84,34 @@ pub enum Command<Sound> {
     Sfx { sound: Sound },
 }
 
+// The number is the sfxr seed that makes the sound. The compiler writes these fields in camelCase
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Sound<Tile> {
+    Sfx {
+        #[serde(rename = "soundEffect")]
+        sound_effect: String,
+        #[serde(rename = "soundCode")]
+        sound_code: u32,
+    },
+    SpriteMove {
+        sprite: Tile,
+        #[serde(rename = "soundCode")]
+        sound_code: u32,
+    },
+    SpriteEvent {
+        sprite: Tile,
+        #[serde(rename = "eventEnum")]
+        event: String,
+        #[serde(rename = "soundCode")]
+        sound_code: u32,
+    },
+    When {
+        when: String,
+        #[serde(rename = "soundCode")]
+        sound_code: u32,
+    },
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Sprite<CollisionLayer> {
     pub name: String,
@@ -0,0 +1,74 @@
+//! A [PuzzleScript](https://www.puzzlescript.net) interpreter.
+//!
+//! Games are loaded with the [`parser`] (either the original source or the
+//! `*.parsed.json` files in the `games/` directory) and then played by
+//! sending input to an [`Engine`](engine::Engine) one tick at a time:
+//!
+//! ```
+//! use puzzlescript::engine::{Engine, EngineInput};
+//! use puzzlescript::parser;
+//!
+//! let source = "
+//! OBJECTS
+//! Background
+//! black
+//! Player
+//! white
+//! Target
+//! red
+//!
+//! LEGEND
+//! . = Background
+//! P = Player
+//! T = Target
+//!
+//! COLLISIONLAYERS
+//! Background
+//! Target
+//! Player
+//!
+//! WINCONDITIONS
+//! All Player on Target
+//!
+//! LEVELS
+//! P.T
+//! ";
+//!
+//! let game = parser::parse_source(source.as_bytes()).unwrap();
+//! let mut engine = Engine::new(game, 0);
+//! engine.tick(Some(EngineInput::Right));
+//! let result = engine.tick(Some(EngineInput::Right));
+//! assert_eq!(result.completed_level, Some(0));
+//! ```
+//!
+//! The terminal front end (the `puzzlescript` binary and the [`terminal`] widgets)
+//! is behind the default `terminal` feature.
+
+mod bitset;
+pub mod browse;
+pub mod cast;
+pub mod color;
+#[cfg(feature = "terminal")]
+pub mod debugger;
+pub mod engine;
+#[cfg(test)]
+mod fixture;
+pub mod history;
+mod json;
+pub mod lint;
+pub mod model;
+pub mod observer;
+pub mod parser;
+pub mod profile;
+pub mod render;
+pub mod save;
+pub mod sfxr;
+pub mod snapshot;
+pub mod solver;
+mod source;
+pub mod stepper;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+pub mod timing;
+pub mod trace;
+pub mod verify;
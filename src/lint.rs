@@ -0,0 +1,402 @@
+use std::fmt;
+
+use fnv::FnvHashSet;
+use serde::Serialize;
+
+use crate::model::bracket::Bracket;
+use crate::model::game::GameData;
+use crate::model::game::Level;
+use crate::model::game::WinCondition;
+use crate::model::neighbor::Neighbor;
+use crate::model::rule::Rule;
+use crate::model::tile::Tile;
+use crate::model::util::SpriteState;
+
+/// The kinds of authoring mistakes that `lint()` finds
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// The sprite is not in any level and no rule adds it
+    UnusedSprite,
+    /// A cell in the rule needs two sprites that are on the same collision layer
+    ImpossibleRule,
+    /// The win condition names a tile that no level contains and no rule adds
+    MissingWinTile,
+    /// Flickscreen and zoomscreen follow the player so a level needs exactly one
+    PlayerCount,
+    /// The sprite has semi-transparent pixels which only truecolor terminals can show
+    AlphaSprite,
+}
+
+/// One problem in a game. Depending on the check it points at a rule (`line`), a level or a sprite
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub check: Check,
+    pub message: String,
+    /// The line of the rule in the game source (starting at 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The index of the level (the same number that `--level` takes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<String>,
+}
+
+impl Diagnostic {
+    fn new(check: Check, message: String) -> Self {
+        Self {
+            check,
+            message,
+            line: None,
+            level: None,
+            sprite: None,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(level) = self.level {
+            write!(f, "level {}: ", level)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Looks for common authoring mistakes without playing the game
+pub fn lint(game: &GameData) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    unused_sprites(game, &mut diagnostics);
+    impossible_rules(game, &mut diagnostics);
+    missing_win_tiles(game, &mut diagnostics);
+    player_counts(game, &mut diagnostics);
+    alpha_sprites(game, &mut diagnostics);
+    diagnostics
+}
+
+fn sprite_name(game: &GameData, sprite: &SpriteState) -> String {
+    game.sprites
+        .get(sprite)
+        .map(|s| s.name.clone())
+        .unwrap_or_else(|| sprite.to_string())
+}
+
+// Sorted by name so the output does not change from run to run
+fn sorted_sprites(game: &GameData) -> Vec<(&SpriteState, String)> {
+    let mut sprites: Vec<_> = game
+        .sprites
+        .iter()
+        .map(|(state, sprite)| (state, sprite.name.clone()))
+        .collect();
+    sprites.sort_by(|a, b| a.1.cmp(&b.1));
+    sprites
+}
+
+fn all_rules(game: &GameData) -> impl Iterator<Item = &Rule> {
+    game.rules
+        .iter()
+        .flat_map(|l| &l.rules)
+        .flat_map(|g| &g.rules)
+}
+
+// Conditions and actions can have an ellipsis which keeps the cells after it separately
+fn cells(brackets: &[Bracket]) -> impl Iterator<Item = &Neighbor> {
+    brackets
+        .iter()
+        .flat_map(|b| b.before_neighbors.iter().chain(b.after_neighbors.iter()))
+}
+
+fn level_sprites(game: &GameData) -> FnvHashSet<u16> {
+    let mut sprites: FnvHashSet<u16> = game
+        .background_tile
+        .sprites
+        .iter()
+        .map(|s| s.index)
+        .collect();
+    for level in &game.levels {
+        if let Level::Map(rows) = level {
+            for tile in rows.iter().flatten() {
+                sprites.extend(tile.sprites.iter().map(|s| s.index));
+            }
+        }
+    }
+    sprites
+}
+
+// The sprites that can be on the board: the ones in the levels and the ones that rules add
+fn placed_sprites(game: &GameData) -> FnvHashSet<u16> {
+    let mut placed = level_sprites(game);
+    for rule in all_rules(game) {
+        for cell in cells(&rule.actions) {
+            for t in cell.tiles_with_modifier.iter().filter(|t| !t.negated) {
+                placed.extend(t.tile.sprites.iter().map(|s| s.index));
+            }
+        }
+    }
+    placed
+}
+
+fn unused_sprites(game: &GameData, diagnostics: &mut Vec<Diagnostic>) {
+    let placed = placed_sprites(game);
+    for (state, name) in sorted_sprites(game) {
+        if !placed.contains(&state.index) {
+            let mut d = Diagnostic::new(
+                Check::UnusedSprite,
+                format!("{} is not in any level and no rule adds it", name),
+            );
+            d.sprite = Some(name);
+            diagnostics.push(d);
+        }
+    }
+}
+
+// Two different sprites that the cell needs but that can never be in the same cell
+fn collision_layer_conflict(cell: &Neighbor) -> Option<(SpriteState, SpriteState)> {
+    let needed: Vec<&Tile> = cell
+        .tiles_with_modifier
+        .iter()
+        .filter(|t| !t.negated)
+        .map(|t| &t.tile)
+        .collect();
+    let required: Vec<SpriteState> = needed
+        .iter()
+        .filter(|t| !t.is_or() || t.sprites.len() == 1)
+        .flat_map(|t| t.sprites.iter().cloned())
+        .collect();
+    let conflicts_with = |sprite: &SpriteState| {
+        required
+            .iter()
+            .find(|r| r.collision_layer == sprite.collision_layer && r.index != sprite.index)
+            .cloned()
+    };
+    for sprite in &required {
+        if let Some(other) = conflicts_with(sprite) {
+            return Some((*sprite, other));
+        }
+    }
+    // An OR tile is impossible when every one of its sprites conflicts
+    for tile in needed.iter().filter(|t| t.is_or() && t.sprites.len() > 1) {
+        let conflicts: Option<Vec<SpriteState>> =
+            tile.sprites.iter().map(&conflicts_with).collect();
+        if let Some(conflicts) = conflicts {
+            return Some((tile.sprites[0], conflicts[0]));
+        }
+    }
+    None
+}
+
+fn impossible_rules(game: &GameData, diagnostics: &mut Vec<Diagnostic>) {
+    // The compiler expands one line into several rules (e.g. one per direction) so report each line once
+    let mut reported = FnvHashSet::default();
+    for rule in all_rules(game) {
+        if reported.contains(&rule.source_line_num) {
+            continue;
+        }
+        if let Some((a, b)) = cells(&rule.conditions).find_map(collision_layer_conflict) {
+            reported.insert(rule.source_line_num);
+            let mut d = Diagnostic::new(
+                Check::ImpossibleRule,
+                format!(
+                    "the rule never matches because {} and {} are on the same collision layer",
+                    sprite_name(game, &a),
+                    sprite_name(game, &b)
+                ),
+            );
+            d.line = rule.source_line_num.map(|line| line + 1);
+            diagnostics.push(d);
+        }
+    }
+}
+
+fn missing_win_tiles(game: &GameData, diagnostics: &mut Vec<Diagnostic>) {
+    let placed = placed_sprites(game);
+    for condition in &game.win_conditions {
+        let tiles = match condition {
+            WinCondition::Simple(_, tile) => vec![tile],
+            WinCondition::On(_, tile, on_tile) => vec![tile, on_tile],
+        };
+        for tile in tiles {
+            if !tile.sprites.iter().any(|s| placed.contains(&s.index)) {
+                diagnostics.push(Diagnostic::new(
+                    Check::MissingWinTile,
+                    format!(
+                        "a win condition needs {} but no level contains it and no rule adds it",
+                        tile.name
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn player_counts(game: &GameData, diagnostics: &mut Vec<Diagnostic>) {
+    let screen = if game.metadata.flickscreen.is_some() {
+        "flickscreen"
+    } else if game.metadata.zoomscreen.is_some() {
+        "zoomscreen"
+    } else {
+        return;
+    };
+    for (index, level) in game.levels.iter().enumerate() {
+        if let Level::Map(_) = level {
+            let players = game.player_positions(&game.to_board(level)).len();
+            if players != 1 {
+                let mut d = Diagnostic::new(
+                    Check::PlayerCount,
+                    format!(
+                        "the level has {} players but {} needs exactly one",
+                        players, screen
+                    ),
+                );
+                d.level = Some(index);
+                diagnostics.push(d);
+            }
+        }
+    }
+}
+
+fn alpha_sprites(game: &GameData, diagnostics: &mut Vec<Diagnostic>) {
+    for (state, name) in sorted_sprites(game) {
+        if game.sprites[state].contains_alpha_pixel() {
+            let mut d = Diagnostic::new(
+                Check::AlphaSprite,
+                format!(
+                    "{} has semi-transparent pixels which need a truecolor terminal",
+                    name
+                ),
+            );
+            d.sprite = Some(name);
+            diagnostics.push(d);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::parser;
+
+    fn lint_source(
+        prelude: &str,
+        rules: &str,
+        win_conditions: &str,
+        levels: &str,
+    ) -> Vec<Diagnostic> {
+        let source = format!(
+            "{}
+
+OBJECTS
+Background
+black
+Player
+white
+Crate
+orange
+Wall
+#ff000080
+Hole
+red
+
+LEGEND
+. = Background
+P = Player
+C = Crate
+# = Wall
+H = Hole
+Solid = Wall or Crate
+
+COLLISIONLAYERS
+Background
+Hole
+Player, Wall, Crate
+
+RULES
+{}
+
+WINCONDITIONS
+{}
+
+LEVELS
+{}
+",
+            prelude, rules, win_conditions, levels
+        );
+        lint(&parser::parse_source(source.as_bytes()).unwrap())
+    }
+
+    fn checks(diagnostics: &[Diagnostic]) -> Vec<(Check, Option<String>)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.check, d.sprite.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_unused_and_alpha_sprites() {
+        let diagnostics = lint_source("", "", "", "P.C#");
+        assert_eq!(
+            checks(&diagnostics),
+            vec![
+                (Check::UnusedSprite, Some(String::from("Hole"))),
+                (Check::AlphaSprite, Some(String::from("Wall"))),
+            ]
+        );
+
+        // A rule can add the sprite
+        let diagnostics = lint_source("", "[ Crate ] -> [ Hole ]", "", "P.C#");
+        assert_eq!(
+            checks(&diagnostics)[0],
+            (Check::AlphaSprite, Some(String::from("Wall")))
+        );
+    }
+
+    #[test]
+    fn finds_impossible_rules() {
+        let rules = "[ Player ] -> [ Player ]
+[ > Player Crate ] -> [ Player ]
+[ Player Solid ] -> [ Player ]
+[ Player no Crate ] -> [ Player ]";
+        let diagnostics = lint_source("", rules, "", "P.C#\n...H");
+        let impossible: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.check == Check::ImpossibleRule)
+            .collect();
+        assert_eq!(impossible.len(), 2);
+        assert_eq!(
+            impossible[0].line.map(|l| impossible[1].line.unwrap() - l),
+            Some(1)
+        );
+        assert!(impossible[0].message.contains("Player and Crate"));
+    }
+
+    #[test]
+    fn finds_win_conditions_and_players() {
+        let diagnostics = lint_source(
+            "flickscreen 3x1",
+            "",
+            "all Crate on Hole\nsome Player",
+            "P.C#\n\nmessage hi\n\nPP..",
+        );
+        let found: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.check != Check::AlphaSprite)
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                "Hole is not in any level and no rule adds it",
+                "a win condition needs Hole but no level contains it and no rule adds it",
+                "level 2: the level has 2 players but flickscreen needs exactly one",
+            ]
+        );
+        let json = serde_json::to_string(&diagnostics[2]).unwrap();
+        assert!(json.contains("\"check\":\"player_count\""));
+        assert!(json.contains("\"level\":2"));
+    }
+}
@@ -4,6 +4,7 @@ pub mod board;
 pub mod bracket;
 pub mod cell;
 pub mod game;
+pub mod legend;
 pub mod neighbor;
 pub mod rule;
 pub mod tile;
@@ -71,7 +72,7 @@ impl fmt::Display for Command {
             Command::Checkpoint => "CHECKPOINT",
             Command::Restart => "RESTART",
             Command::Win => "WIN",
-            Command::Sfx => "SFX",
+            Command::Sfx(_) => "SFX",
             Command::Message(_) => "MESSAGE",
         };
         write!(f, "{}", msg)
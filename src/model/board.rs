@@ -1,8 +1,13 @@
+use std::error::Error;
+use std::hash;
+use std::mem;
+
 use fnv::FnvHashMap;
 use fnv::FnvHashSet;
 
 use crate::bitset::BitSet;
 use crate::model::cell::Cell;
+use crate::model::legend::Legend;
 use crate::model::tile::Tile;
 use crate::model::tile::TileKind;
 
@@ -86,6 +91,9 @@ impl Neighbors {
         };
         ret as usize
     }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     pub fn nth(&self, n: usize) -> Option<Position> {
         self.iter().nth(n)
     }
@@ -98,6 +106,12 @@ pub struct StripeCache {
     // pub staleness: u16,
 }
 
+impl Default for StripeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StripeCache {
     pub fn new() -> Self {
         Self {
@@ -225,6 +239,88 @@ impl Board {
         }
     }
 
+    /// Draws the board with the characters of the game's levels, one line per row. A cell
+    /// that no single character draws is written as its sprite names, e.g. `[Background Player]`.
+    /// The directions the sprites want to move in are not written
+    pub fn to_text(&self, legend: &Legend) -> String {
+        let mut text = String::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sprites = self.get_sprite_states(&Position::new(x, y));
+                sprites.sort();
+                match legend.glyph(&sprites) {
+                    Some(glyph) => text.push(glyph),
+                    None => {
+                        let names: Vec<String> = sprites
+                            .iter()
+                            .map(|s| match legend.sprite_name(s) {
+                                Some(name) => name.clone(),
+                                None => s.to_string(),
+                            })
+                            .collect();
+                        text.push_str(&format!("[{}]", names.join(" ")));
+                    }
+                }
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Reads a board that `to_text()` wrote (or that was typed in the same way)
+    pub fn from_text(text: &str, legend: &Legend) -> Result<Self, Box<dyn Error>> {
+        let mut rows: Vec<Vec<Vec<SpriteState>>> = vec![];
+        for (line_num, line) in text.lines().enumerate() {
+            let err = |msg: String| format!("line {}: {}", line_num + 1, msg);
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let mut row = vec![];
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                if c == '[' {
+                    let list: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                    let sprites = list
+                        .split_whitespace()
+                        .map(|name| {
+                            legend
+                                .sprite(name)
+                                .ok_or_else(|| err(format!("unknown sprite '{}'", name)))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    row.push(sprites);
+                } else {
+                    match legend.sprites(c) {
+                        Some(sprites) => row.push(sprites.clone()),
+                        None => return Err(err(format!("unknown character '{}'", c)).into()),
+                    }
+                }
+            }
+            if let Some(first) = rows.first() {
+                if first.len() != row.len() {
+                    return Err(err(format!(
+                        "expected {} cells but found {}",
+                        first.len(),
+                        row.len()
+                    ))
+                    .into());
+                }
+            }
+            rows.push(row);
+        }
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 {
+            return Err("the board is empty".into());
+        }
+        let height = rows.len();
+        Ok(Self::from_checkpoint(
+            width as u16,
+            height as u16,
+            rows.into_iter().flatten().collect(),
+        ))
+    }
+
     fn pos(&self, pos: &Position) -> usize {
         let x = pos.x;
         let y = pos.y;
@@ -380,6 +476,131 @@ impl Board {
     pub fn col_cache(&self, x: u16) -> &StripeCache {
         &self.col_cache[x as usize]
     }
+
+    /// The cells of this board that are different in `other`
+    pub fn delta_from(&self, other: &Board) -> BoardDelta {
+        assert_eq!(
+            self.size(),
+            other.size(),
+            "BUG: Boards must be the same size"
+        );
+        let cells = self
+            .grid
+            .iter()
+            .zip(&other.grid)
+            .enumerate()
+            // Only the sprites matter. Every sprite is Stationary between ticks
+            .filter(|(_, (mine, theirs))| mine.sprite_bits != theirs.sprite_bits)
+            .map(|(index, (mine, _))| (index, cell_sprites(mine)))
+            .collect();
+        BoardDelta { cells }
+    }
+
+    /// Replaces the sprites of every cell in the delta
+    pub fn apply(&mut self, delta: &BoardDelta) {
+        for (index, sprites) in &delta.cells {
+            let pos = self.index_to_pos(*index);
+            let layers: Vec<u16> = self.get(&pos).as_map().keys().cloned().collect();
+            for collision_layer in layers {
+                self.remove_collision_layer(&pos, collision_layer);
+            }
+            for (collision_layer, sprite_index) in sprites {
+                self.add_sprite_index(
+                    &pos,
+                    *collision_layer,
+                    *sprite_index,
+                    WantsToMove::Stationary,
+                );
+            }
+        }
+    }
+}
+
+/// The cells of a board that are different from another board of the same size.
+/// Applying it to the other board turns it back into the board it was made from
+#[derive(Clone, PartialEq, Debug)]
+pub struct BoardDelta {
+    // (grid index, [(collision layer, sprite index)])
+    cells: Vec<(usize, Vec<(u16, u16)>)>,
+}
+
+impl BoardDelta {
+    /// `cells` has the grid index of each changed cell and its (collision layer, sprite index) pairs.
+    /// Fails when a cell is not inside a board of `size` (e.g. the save file is for an older version of the game)
+    pub fn new(
+        cells: Vec<(usize, Vec<(u16, u16)>)>,
+        size: Dimension,
+    ) -> Result<Self, Box<dyn Error>> {
+        let len = size.width as usize * size.height as usize;
+        if let Some((index, _)) = cells.iter().find(|(index, _)| *index >= len) {
+            return Err(format!(
+                "cell {} is outside of the {}x{} board",
+                index, size.width, size.height
+            )
+            .into());
+        }
+        Ok(Self { cells })
+    }
+
+    pub fn cells(&self) -> &[(usize, Vec<(u16, u16)>)] {
+        &self.cells
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Roughly how much memory the delta uses
+    pub fn size_in_bytes(&self) -> usize {
+        mem::size_of::<Self>()
+            + self
+                .cells
+                .iter()
+                .map(|(_, sprites)| {
+                    mem::size_of::<(usize, Vec<(u16, u16)>)>()
+                        + sprites.len() * mem::size_of::<(u16, u16)>()
+                })
+                .sum::<usize>()
+    }
+
+    /// The same change but made to `to` instead of `from`. Applying it to `to` gives the same board
+    /// as applying this delta to `from`
+    pub fn rebase(&self, from: &Board, to: &Board) -> BoardDelta {
+        let mut changed: FnvHashMap<usize, &Vec<(u16, u16)>> = self
+            .cells
+            .iter()
+            .map(|(index, sprites)| (*index, sprites))
+            .collect();
+        let mut cells = vec![];
+        for (index, (before, after)) in from.grid.iter().zip(&to.grid).enumerate() {
+            let sprites = match changed.remove(&index) {
+                Some(sprites) => {
+                    let mut bits = BitSet::new();
+                    for (_, sprite_index) in sprites {
+                        bits.insert(*sprite_index);
+                    }
+                    if bits == after.sprite_bits {
+                        continue;
+                    }
+                    sprites.clone()
+                }
+                None if before.sprite_bits == after.sprite_bits => continue,
+                None => cell_sprites(before),
+            };
+            cells.push((index, sprites));
+        }
+        BoardDelta { cells }
+    }
+}
+
+fn cell_sprites(cell: &Cell) -> Vec<(u16, u16)> {
+    let mut sprites: Vec<_> = cell
+        .as_map()
+        .iter()
+        .map(|(c, w)| (*c, w.sprite_index))
+        .collect();
+    sprites.sort();
+    sprites
 }
 
 impl PartialEq for Board {
@@ -388,6 +609,19 @@ impl PartialEq for Board {
     }
 }
 
+impl Eq for Board {}
+
+// Only the sprites are hashed. Boards that are equal have the same sprites so this agrees with PartialEq
+impl hash::Hash for Board {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.height.hash(state);
+        for cell in &self.grid {
+            cell.sprite_bits.hash(state);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -412,6 +646,41 @@ mod tests {
         assert_eq!(n.len(), 3);
     }
 
+    #[test]
+    fn applies_a_delta() {
+        let wall = SpriteState::new(&String::from("Wall"), 1, 1);
+        let player = SpriteState::new(&String::from("Player"), 2, 2);
+        let mut before = Board::new(3, 2);
+        before.add_sprite(&Position::new(0, 0), &player, WantsToMove::Stationary);
+        before.add_sprite(&Position::new(2, 1), &wall, WantsToMove::Stationary);
+
+        let mut after = before.clone();
+        after.remove_collision_layer(&Position::new(0, 0), 2);
+        after.add_sprite(&Position::new(1, 0), &player, WantsToMove::Stationary);
+
+        let delta = before.delta_from(&after);
+        assert_eq!(delta.cells.len(), 2);
+        assert!(before.delta_from(&before).is_empty());
+
+        let mut other = after.clone();
+        other.add_sprite(&Position::new(2, 0), &wall, WantsToMove::Stationary);
+        let rebased = delta.rebase(&after, &other);
+        other.apply(&rebased);
+        assert_eq!(other, before);
+
+        after.apply(&delta);
+        assert_eq!(after, before);
+        assert!(after.has_sprite(&Position::new(0, 0), &player));
+        assert!(!after.has_sprite(&Position::new(1, 0), &player));
+    }
+
+    #[test]
+    fn rejects_a_delta_outside_the_board() {
+        let size = Board::new(3, 2).size();
+        assert!(BoardDelta::new(vec![(5, vec![(1, 1)])], size).is_ok());
+        assert!(BoardDelta::new(vec![(0, vec![]), (6, vec![(1, 1)])], size).is_err());
+    }
+
     #[test]
     fn stripe_cache() {
         let mut row = StripeCache::new();
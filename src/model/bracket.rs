@@ -8,6 +8,7 @@ use crate::model::board::Board;
 use crate::model::board::Neighbors;
 use crate::model::board::StripeCache;
 use crate::model::neighbor::Neighbor;
+use crate::model::rule::RigidMovements;
 use crate::model::tile::Tile;
 use crate::model::util::CardinalDirection;
 use crate::model::util::Position;
@@ -249,6 +250,26 @@ impl Bracket {
         something_changed
     }
 
+    /// See `Neighbor::record_rigid_movements()`
+    pub fn record_rigid_movements(
+        &self,
+        rigid: &mut RigidMovements,
+        m: &BracketMatch,
+        rigid_group: usize,
+    ) {
+        self.before_neighbors
+            .iter()
+            .zip(m.before_positions.iter())
+            .for_each(|(n, pos)| n.record_rigid_movements(rigid, &pos, rigid_group));
+
+        if let Some(after_positions) = &m.after_positions {
+            self.after_neighbors
+                .iter()
+                .zip(after_positions.iter())
+                .for_each(|(n, pos)| n.record_rigid_movements(rigid, &pos, rigid_group));
+        }
+    }
+
     pub fn populate_magic_or_tiles(
         &self,
         board: &Board,
@@ -609,6 +630,7 @@ mod tests {
             &mut board,
             &mut TriggeredCommands::default(),
             false,
+            &mut (),
         );
 
         assert!(!board.has_sprite(&origin, &player));
@@ -23,6 +23,12 @@ pub struct Cell {
 // type Cache = FnvHashMap<(usize /*Cell*/, usize/*Tile*/, Option<WantsToMove>), bool>;
 // type CellKeys = FnvHashMap<String /*Cell*/, usize>;
 
+impl Default for Cell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Cell {
     pub fn new() -> Self {
         Self {
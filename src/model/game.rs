@@ -3,15 +3,18 @@ extern crate rand_core;
 extern crate rand_xorshift;
 
 use fnv::FnvHashMap;
+use fnv::FnvHashSet;
 use std::fmt;
 use std::time;
 
 use rand::Rng;
 
+use crate::bitset::BitSet;
 use crate::color::Rgb;
 use crate::model::board::Board;
 use crate::model::bracket::Bracket;
 use crate::model::neighbor::Neighbor;
+use crate::model::rule::RigidMovements;
 use crate::model::rule::Rule;
 use crate::model::rule::RuleGroup;
 use crate::model::rule::RuleLoop;
@@ -24,7 +27,8 @@ use crate::model::util::SpriteState;
 use crate::model::util::TriggeredCommands;
 use crate::model::util::WantsToMove;
 
-use crate::debugger::ScreenDumper;
+use crate::observer::EngineObserver;
+use crate::observer::Phase;
 
 #[derive(Clone, Debug)]
 pub enum Level {
@@ -123,7 +127,7 @@ impl Sprite {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Input {
     Up,
     Down,
@@ -137,6 +141,7 @@ fn build_input_rule(player_tile: &Tile, wants_to_move: WantsToMove) -> RuleLoop
         is_loop: false,
         rules: vec![RuleGroup {
             random: false,
+            rigid_group: None,
             rules: vec![Rule {
                 source_line_num: None,
                 causes_board_changes: None,
@@ -194,6 +199,50 @@ pub struct Metadata {
     pub verbose_logging: bool,
 }
 
+/// Something that happens to the whole game that the SOUNDS section can play a sound for
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GameEvent {
+    StartGame,
+    EndGame,
+    StartLevel,
+    EndLevel,
+    Restart,
+    Undo,
+    ShowMessage,
+    CloseMessage,
+    // There is no title screen so this one never plays
+    TitleScreen,
+}
+
+/// Something that happens to a sprite that the SOUNDS section can play a sound for
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SpriteEvent {
+    Move,
+    CantMove,
+    Create,
+    Destroy,
+    Action,
+}
+
+/// The sfxr seeds that the SOUNDS section binds to events.
+/// `sfx0`..`sfx10` are not here; rules play those with the SFX command
+#[derive(Clone, Default, Debug)]
+pub struct Sounds {
+    pub game: FnvHashMap<GameEvent, u32>,
+    /// Keyed by sprite index
+    pub sprites: FnvHashMap<(u16, SpriteEvent), u32>,
+}
+
+// What happened to the sprites while the movements were resolved (for the SOUNDS section)
+#[derive(Default)]
+struct Movements {
+    // from, to, sprite index
+    moved: Vec<(Position, Position, u16)>,
+    blocked: Vec<u16>,
+    actions: Vec<u16>,
+}
+
+/// A compiled game. Build one with `parser::parse()` or `parser::parse_source()`
 #[derive(Debug)]
 pub struct GameData {
     pub title: String,
@@ -210,6 +259,8 @@ pub struct GameData {
     pub input_rule_left: RuleLoop,
     pub input_rule_right: RuleLoop,
     pub input_rule_action: RuleLoop,
+    pub sounds: Sounds,
+    has_rigid_rules: bool,
 }
 
 impl GameData {
@@ -219,7 +270,7 @@ impl GameData {
         sprites: FnvHashMap<SpriteState, Sprite>,
         player_tile: Tile,
         background_tile: Tile,
-        rules: Vec<RuleLoop>,
+        mut rules: Vec<RuleLoop>,
         levels: Vec<Level>,
         win_conditions: Vec<WinCondition>,
     ) -> Self {
@@ -228,6 +279,16 @@ impl GameData {
             Some((_, sprite)) => (sprite.pixels[0].len() as u16, sprite.pixels.len() as u16),
         };
 
+        // Number the RIGID rule groups so a blocked movement can be traced back to its group
+        let mut rigid_groups = 0;
+        for group in rules.iter_mut().flat_map(|l| &mut l.rules) {
+            if group.is_rigid() {
+                group.rigid_group = Some(rigid_groups);
+                rigid_groups += 1;
+            }
+        }
+        let has_rigid_rules = rigid_groups > 0;
+
         Self {
             input_rule_up: build_input_rule(&player_tile, WantsToMove::Up),
             input_rule_down: build_input_rule(&player_tile, WantsToMove::Down),
@@ -244,9 +305,16 @@ impl GameData {
             rules,
             levels,
             win_conditions,
+            sounds: Sounds::default(),
+            has_rigid_rules,
         }
     }
 
+    /// The sfxr seed of the sound the SOUNDS section plays for `event` (if any)
+    pub fn sound_for(&self, event: GameEvent) -> Option<u32> {
+        self.sounds.game.get(&event).copied()
+    }
+
     pub fn sprite_size(&self) -> (u16, u16) {
         self._sprite_size
     }
@@ -256,18 +324,27 @@ impl GameData {
         rng: &mut R,
         board: &mut Board,
         late: bool,
+        rigid: &mut RigidMovements,
+        observer: &mut dyn EngineObserver,
     ) -> TriggeredCommands {
         let start_time = time::Instant::now();
         let mut t = TriggeredCommands::default();
         self.rules
             .iter()
-            .map(|r| r.evaluate(rng, board, late))
+            .map(|r| r.evaluate(rng, board, late, rigid, observer))
             .for_each(|c| t.merge(&c));
         trace!("Rule Evaluation took {}sec", start_time.elapsed().as_secs());
         t
     }
 
-    fn evaluate_post(&self, board: &mut Board, triggered: &TriggeredCommands) {
+    // Returns the RIGID rule groups that had a movement blocked and what moved
+    fn evaluate_post(
+        &self,
+        board: &mut Board,
+        rigid: &RigidMovements,
+        observer: &mut dyn EngineObserver,
+    ) -> (FnvHashSet<usize>, Movements) {
+        let mut movements = Movements::default();
         // Move all the sprites in cells that want to move
         let mut did_change;
         loop {
@@ -281,12 +358,20 @@ impl GameData {
                 for (c, sw) in board.as_map(&pos) {
                     match sw.wants_to_move.to_cardinal_direction() {
                         None => {
+                            if sw.wants_to_move == WantsToMove::Action {
+                                movements.actions.push(sw.sprite_index);
+                            }
                             if sw.wants_to_move != WantsToMove::Stationary {
                                 to_stationary.push((pos, c.clone()))
                             }
                         }
                         Some(dir) => match board.neighbor_position(&pos, dir) {
-                            None => to_stationary.push((pos, c.clone())),
+                            None => {
+                                // Moving off the edge of the level
+                                movements.blocked.push(sw.sprite_index);
+                                observer.movement_blocked(sw.sprite_index, pos);
+                                to_stationary.push((pos, c.clone()))
+                            }
                             Some(neighbor_pos) => {
                                 if !board.has_collision_layer(&neighbor_pos, *c) {
                                     to_move.push((pos, c.clone(), neighbor_pos, sw.sprite_index));
@@ -314,20 +399,14 @@ impl GameData {
                         c,
                         sprite_index,
                         WantsToMove::Stationary,
-                    )
+                    );
+                    movements.moved.push((pos, neighbor_pos, sprite_index));
+                    observer.sprite_moved(sprite_index, pos, neighbor_pos);
                 } else {
                     debug!("POST: Tried to move sprite {} from {} to {} but something became in-the-way", sprite_index, pos, neighbor_pos);
                 }
             }
 
-            if ScreenDumper::is_enabled() {
-                ScreenDumper::dump(
-                    board,
-                    triggered,
-                    &String::from("post-action WantsToMoves. iteration done"),
-                );
-            }
-
             if !did_change {
                 break;
             }
@@ -335,11 +414,20 @@ impl GameData {
 
         // Finally, clear all the WantsToMove because the elements were not able to move (they were blocked)
         let mut to_stationary = vec![];
+        let mut blocked_rigid_groups = FnvHashSet::default();
         for pos in board.positions_iter() {
             for (c, sw) in board.as_map(&pos) {
                 if sw.wants_to_move != WantsToMove::Stationary {
                     to_stationary.push((pos, c.clone()));
                 }
+                if sw.wants_to_move.to_cardinal_direction().is_some() {
+                    movements.blocked.push(sw.sprite_index);
+                    observer.movement_blocked(sw.sprite_index, pos);
+                    if let Some(rigid_group) = rigid.group_at(&pos, *c) {
+                        debug!("POST: RIGID movement was blocked @ {}", pos);
+                        blocked_rigid_groups.insert(rigid_group);
+                    }
+                }
             }
         }
         for (pos, c) in to_stationary {
@@ -350,6 +438,65 @@ impl GameData {
             );
             board.set_wants_to_move(&pos, c, WantsToMove::Stationary);
         }
+        (blocked_rigid_groups, movements)
+    }
+
+    // Adds the sounds of sprites that moved, could not move, or were created or destroyed during the turn
+    fn add_sprite_sounds(
+        &self,
+        before: &[BitSet],
+        after: &Board,
+        movements: &Movements,
+        t: &mut TriggeredCommands,
+    ) {
+        // A sprite that moved is not in the same cell as before but it was not created or destroyed
+        let mut created = FnvHashSet::default();
+        let mut destroyed = FnvHashSet::default();
+        for (pos, was) in after.positions_iter().into_iter().zip(before) {
+            let is = after.as_sprites(&pos);
+            if was == is {
+                continue;
+            }
+            for index in is.into_vec() {
+                if !was.contains(index) {
+                    created.insert((pos, index));
+                }
+            }
+            for index in was.into_vec() {
+                if !is.contains(index) {
+                    destroyed.insert((pos, index));
+                }
+            }
+        }
+        for (from, to, sprite_index) in &movements.moved {
+            created.remove(&(*to, *sprite_index));
+            destroyed.remove(&(*from, *sprite_index));
+        }
+
+        let events = created
+            .iter()
+            .map(|(_, s)| (*s, SpriteEvent::Create))
+            .chain(destroyed.iter().map(|(_, s)| (*s, SpriteEvent::Destroy)))
+            .chain(
+                movements
+                    .moved
+                    .iter()
+                    .map(|(_, _, s)| (*s, SpriteEvent::Move)),
+            )
+            .chain(
+                movements
+                    .blocked
+                    .iter()
+                    .map(|s| (*s, SpriteEvent::CantMove)),
+            )
+            .chain(movements.actions.iter().map(|s| (*s, SpriteEvent::Action)));
+        for key in events {
+            if let Some(seed) = self.sounds.sprites.get(&key) {
+                if !t.sfx.contains(seed) {
+                    t.sfx.push(*seed);
+                }
+            }
+        }
     }
 
     fn check_win_conditions(&self, board: &Board) -> bool {
@@ -377,35 +524,67 @@ impl GameData {
         &self,
         rng: &mut R,
         board: &mut Board,
-        debug_rules: bool,
+        observer: &mut dyn EngineObserver,
     ) -> TriggeredCommands {
-        // enable/disable screen dumping for each rule
-        let has_sprites = ScreenDumper::is_enabled();
-        if debug_rules && !has_sprites {
-            ScreenDumper::set_sprites(Some(self.sprites.clone()));
-        } else if !debug_rules && has_sprites {
-            ScreenDumper::set_sprites(None);
+        // If a RIGID movement is blocked then the board goes back to how it was before the rules ran
+        // and the rules run again, this time skipping the rule group that made the movement
+        let start = if self.has_rigid_rules {
+            Some(board.clone())
+        } else {
+            None
+        };
+        // Only needed to find out which sprites were created or destroyed
+        let before: Option<Vec<BitSet>> = if self.sounds.sprites.is_empty() {
+            None
+        } else {
+            Some(
+                board
+                    .positions_iter()
+                    .iter()
+                    .map(|pos| *board.as_sprites(pos))
+                    .collect(),
+            )
+        };
+        let mut rigid = RigidMovements::default();
+        let mut t;
+        let mut movements;
+        loop {
+            observer.phase_started(Phase::Rules);
+            t = self.evaluate_rules(rng, board, false, &mut rigid, observer);
+            // Short-circuit if we already cancelled
+            if t.cancel {
+                trace!("CANCEL command found while evaluating the non-late rules");
+                observer.phase_finished(Phase::Rules, board, &t);
+                return t;
+            }
+            observer.phase_finished(Phase::Rules, board, &t);
+            observer.phase_started(Phase::Movement);
+            let (blocked_rigid_groups, m) = self.evaluate_post(board, &rigid, observer);
+            movements = m;
+            match &start {
+                Some(start) if !blocked_rigid_groups.is_empty() => {
+                    debug!("RIGID movement was blocked. Re-running the rules");
+                    rigid.ban(&blocked_rigid_groups);
+                    *board = start.clone();
+                    observer.rigid_blocked(board, &t);
+                }
+                _ => break,
+            }
         }
+        observer.phase_finished(Phase::Movement, board, &t);
 
-        let mut t = self.evaluate_rules(rng, board, false);
-        // Short-circuit if we already cancelled
-        if t.cancel {
-            trace!("CANCEL command found while evaluating the non-late rules");
-            return t;
-        }
-        self.evaluate_post(board, &t);
-        if ScreenDumper::is_enabled() {
-            ScreenDumper::dump(board, &t, &String::from("Resolved remaining wantstomoves"));
-        }
+        observer.phase_started(Phase::LateRules);
+        t.merge(&self.evaluate_rules(rng, board, true, &mut RigidMovements::default(), observer));
+        observer.phase_finished(Phase::LateRules, board, &t);
 
-        if ScreenDumper::is_enabled() {
-            println!("Evaluating LATE rules...");
-        }
-        t.merge(&self.evaluate_rules(rng, board, true));
-        if ScreenDumper::is_enabled() {
-            println!("Evaluated LATE rules");
-        }
+        observer.phase_started(Phase::WinCheck);
         t.win |= self.check_win_conditions(board);
+        observer.phase_finished(Phase::WinCheck, board, &t);
+        if let Some(before) = &before {
+            if !t.cancel {
+                self.add_sprite_sounds(before, board, &movements, &mut t);
+            }
+        }
         t
     }
 
@@ -414,6 +593,7 @@ impl GameData {
         rng: &mut R,
         board: &mut Board,
         input: Input,
+        observer: &mut dyn EngineObserver,
     ) {
         let input_rule = match input {
             Input::Up => &self.input_rule_up,
@@ -422,7 +602,30 @@ impl GameData {
             Input::Right => &self.input_rule_right,
             Input::Action => &self.input_rule_action,
         };
-        input_rule.evaluate(rng, board, false);
+        observer.phase_started(Phase::Input);
+        let t = input_rule.evaluate(rng, board, false, &mut RigidMovements::default(), observer);
+        observer.phase_finished(Phase::Input, board, &t);
+    }
+
+    pub fn player_positions(&self, board: &Board) -> Vec<Position> {
+        board
+            .positions_iter()
+            .into_iter()
+            .filter(|p| board.matches(p, &self.player_tile, &None))
+            .collect()
+    }
+
+    /// True when the game has `require_player_movement` and none of the players on `before`
+    /// moved in `after`, so the turn should be cancelled. A level without a player never is
+    pub fn player_failed_to_move(&self, before: &Board, after: &Board) -> bool {
+        if !self.metadata.require_player_movement {
+            return false;
+        }
+        let positions = self.player_positions(before);
+        !positions.is_empty()
+            && positions
+                .iter()
+                .all(|p| after.matches(p, &self.player_tile, &None))
     }
 
     pub fn to_board(&self, level: &Level) -> Board {
@@ -451,7 +654,13 @@ mod tests {
     }
 
     fn did_trigger(t: &TriggeredCommands) -> bool {
-        t.again || t.cancel || t.checkpoint || t.restart || t.win || t.message.is_some() || t.sfx
+        t.again
+            || t.cancel
+            || t.checkpoint
+            || t.restart
+            || t.win
+            || t.message.is_some()
+            || !t.sfx.is_empty()
     }
 
     #[test]
@@ -463,6 +672,7 @@ mod tests {
             is_loop: false,
             rules: vec![RuleGroup {
                 random: false,
+                rigid_group: None,
                 rules: vec![Rule {
                     source_line_num: None,
                     causes_board_changes: None,
@@ -503,7 +713,7 @@ mod tests {
         );
         let mut board = game.to_board(&level);
 
-        game.evaluate(&mut rng, &mut board, false);
+        game.evaluate(&mut rng, &mut board, &mut ());
 
         assert!(board.has_sprite(&origin, &player));
         assert_eq!(
@@ -522,6 +732,7 @@ mod tests {
             is_loop: false,
             rules: vec![RuleGroup {
                 random: false,
+                rigid_group: None,
                 rules: vec![Rule {
                     source_line_num: None,
                     causes_board_changes: None,
@@ -561,7 +772,7 @@ mod tests {
         );
         let mut board = game.to_board(&level);
 
-        game.evaluate(&mut rng, &mut board, false);
+        game.evaluate(&mut rng, &mut board, &mut ());
 
         assert!(board.has_sprite(&origin, &player));
         assert_eq!(
@@ -583,6 +794,7 @@ mod tests {
             is_loop: false,
             rules: vec![RuleGroup {
                 random: false,
+                rigid_group: None,
                 rules: vec![Rule {
                     source_line_num: None,
                     causes_board_changes: None,
@@ -601,7 +813,7 @@ mod tests {
                         checkpoint: true,
                         win: true,
                         message: Some(String::from("YouWin!")),
-                        sfx: true,
+                        sfx: vec![12345],
                     },
                     late: false,
                     random: false,
@@ -633,7 +845,7 @@ mod tests {
 
         assert!(board.has_sprite(&origin, &player));
 
-        let t = game.evaluate(&mut rng, &mut board, false);
+        let t = game.evaluate(&mut rng, &mut board, &mut ());
 
         assert!(!board.has_sprite(&origin, &player));
 
@@ -649,7 +861,7 @@ mod tests {
         assert_eq!(t.checkpoint, true);
         assert_eq!(t.restart, true);
         assert_eq!(t.win, true);
-        assert_eq!(t.sfx, true);
+        assert_eq!(t.sfx, vec![12345]);
         assert_eq!(t.message, Some(String::from("YouWin!")));
     }
 
@@ -667,6 +879,7 @@ mod tests {
             is_loop: false,
             rules: vec![RuleGroup {
                 random: false,
+                rigid_group: None,
                 rules: vec![Rule {
                     source_line_num: None,
                     causes_board_changes: None,
@@ -685,7 +898,7 @@ mod tests {
                         checkpoint: false,
                         win: false,
                         message: None,
-                        sfx: false,
+                        sfx: vec![],
                     },
                     late: false,
                     random: false,
@@ -713,7 +926,7 @@ mod tests {
         );
         let mut board = game.to_board(&level);
 
-        let t = game.evaluate(&mut rng, &mut board, false);
+        let t = game.evaluate(&mut rng, &mut board, &mut ());
 
         assert_eq!(did_trigger(&t), true);
         assert_eq!(t.cancel, true);
@@ -730,6 +943,7 @@ mod tests {
             is_loop: false,
             rules: vec![RuleGroup {
                 random: false,
+                rigid_group: None,
                 rules: vec![Rule {
                     source_line_num: None,
                     causes_board_changes: None,
@@ -772,7 +986,13 @@ mod tests {
         );
         let mut board = game.to_board(&level);
 
-        game.evaluate_rules(&mut rng, &mut board, false);
+        game.evaluate_rules(
+            &mut rng,
+            &mut board,
+            false,
+            &mut RigidMovements::default(),
+            &mut (),
+        );
 
         // Verify that the rules marked all the players as wanting to move RIGHT
         assert_eq!(
@@ -788,7 +1008,7 @@ mod tests {
             None
         );
 
-        game.evaluate_post(&mut board, &TriggeredCommands::default());
+        game.evaluate_post(&mut board, &RigidMovements::default(), &mut ());
 
         assert!(!board.has_sprite(&origin, &player));
         assert!(board.has_sprite(&middle, &player));
@@ -824,7 +1044,7 @@ mod tests {
         assert!(board.add_sprite(&top, &rock, WantsToMove::Down));
         assert!(board.add_sprite(&left, &player, WantsToMove::Right));
 
-        game.evaluate_post(&mut board, &TriggeredCommands::default());
+        game.evaluate_post(&mut board, &RigidMovements::default(), &mut ());
 
         assert!(board.has_sprite(&end, &rock));
         assert!(!board.has_sprite(&end, &player));
@@ -849,6 +1069,7 @@ mod tests {
             is_loop: false,
             rules: vec![RuleGroup {
                 random: false,
+                rigid_group: None,
                 rules: vec![
                     Rule {
                         source_line_num: None,
@@ -907,7 +1128,7 @@ mod tests {
         );
         let mut board = game.to_board(&level);
 
-        game.evaluate(&mut rng, &mut board, false);
+        game.evaluate(&mut rng, &mut board, &mut ());
 
         // Verify that the player moved right AND THEN switched to be a star
         assert!(!board.has_sprite(&origin, &player));
@@ -950,13 +1171,13 @@ mod tests {
         let mut board = game.to_board(&level);
 
         // verify that _both_ win conditions must be satisfied
-        let t = game.evaluate(&mut rng, &mut board, false);
+        let t = game.evaluate(&mut rng, &mut board, &mut ());
         assert_eq!(t.win, false);
 
         board.remove_collision_layer(&origin, player.collision_layer);
 
         // verify that _both_ win conditions ARE satisfied
-        let t = game.evaluate(&mut rng, &mut board, false);
+        let t = game.evaluate(&mut rng, &mut board, &mut ());
         assert_eq!(t.win, true);
     }
 
@@ -997,7 +1218,7 @@ mod tests {
         let mut board = game.to_board(&level);
 
         // verify that the ON condition is be satisfied
-        let t = game.evaluate(&mut rng, &mut board, false);
+        let t = game.evaluate(&mut rng, &mut board, &mut ());
         assert_eq!(t.win, true);
     }
 
@@ -1016,6 +1237,7 @@ mod tests {
             is_loop: false,
             rules: vec![RuleGroup {
                 random: false,
+                rigid_group: None,
                 rules: vec![Rule {
                     conditions: vec![Bracket::new(
                         CardinalDirection::Right,
@@ -1058,10 +1280,136 @@ mod tests {
         let mut board = game.to_board(&level);
 
         // verify that the ON condition is be satisfied
-        let t = game.evaluate(&mut rng, &mut board, false);
+        let t = game.evaluate(&mut rng, &mut board, &mut ());
         assert_eq!(t.win, true);
     }
 
+    // Builds a 2-cell block (top & bottom) that the player pushes to the right:
+    //
+    // (RIGID) RIGHT [ > player | top ] -> [ > player | > top ]
+    // +       DOWN [ > top | bottom ] -> [ > top | > bottom ]
+    //
+    // player top    .
+    // .      bottom wall
+    fn build_rigid_game(rigid: bool) -> (GameData, Level, Vec<SpriteState>) {
+        let player = SpriteState::new(&String::from("player"), 0, 0);
+        let top = SpriteState::new(&String::from("top"), 1, 0);
+        let bottom = SpriteState::new(&String::from("bottom"), 2, 0);
+        let wall = SpriteState::new(&String::from("wall"), 3, 0);
+        let background = SpriteState::new(&String::from("background"), 4, 1);
+
+        let push_rule = |dir, first: &SpriteState, second: &SpriteState| {
+            let first_right = build_t(false, first, false, Some(WantsToMove::Right));
+            // No source line (like the rules of a game built in code)
+            Rule {
+                rigid,
+                conditions: vec![Bracket::new(
+                    dir,
+                    vec![
+                        Neighbor::new(vec![first_right.clone()]),
+                        Neighbor::new(vec![build_t(false, second, false, None)]),
+                    ],
+                )],
+                actions: vec![Bracket::new(
+                    dir,
+                    vec![
+                        Neighbor::new(vec![first_right.clone()]),
+                        Neighbor::new(vec![build_t(
+                            false,
+                            second,
+                            false,
+                            Some(WantsToMove::Right),
+                        )]),
+                    ],
+                )],
+                ..Rule::default()
+            }
+        };
+        let mut rule = RuleLoop {
+            is_loop: false,
+            rules: vec![RuleGroup {
+                random: false,
+                rigid_group: None,
+                rules: vec![
+                    push_rule(CardinalDirection::Right, &player, &top),
+                    push_rule(CardinalDirection::Down, &top, &bottom),
+                ],
+            }],
+        };
+        rule.prepare_actions();
+
+        let tile = |sprites| Tile::new(TileKind::And, &String::from("t"), sprites);
+        let level = Level::Map(vec![
+            vec![tile(vec![player]), tile(vec![top]), tile(vec![])],
+            vec![tile(vec![]), tile(vec![bottom]), tile(vec![wall])],
+        ]);
+        let game = GameData::new(
+            String::from("test"),
+            Metadata::default(),
+            FnvHashMap::default(),
+            build_t(false, &player, false, None).tile,
+            build_t(false, &background, false, None).tile,
+            vec![rule],
+            vec![level.clone()],
+            vec![],
+        );
+        (game, level, vec![player, top, bottom])
+    }
+
+    #[test]
+    fn game_non_rigid_block_splits_apart() {
+        init();
+        let (game, level, sprites) = build_rigid_game(false);
+        let mut rng = new_rng();
+        let mut board = game.to_board(&level);
+        board.set_wants_to_move(&Position::new(0, 0), 0, WantsToMove::Right);
+
+        game.evaluate(&mut rng, &mut board, &mut ());
+
+        assert!(board.has_sprite(&Position::new(1, 0), &sprites[0]));
+        assert!(board.has_sprite(&Position::new(2, 0), &sprites[1]));
+        assert!(board.has_sprite(&Position::new(1, 1), &sprites[2]));
+    }
+
+    #[test]
+    fn game_rigid_block_moves_together_or_not_at_all() {
+        init();
+        let (game, level, sprites) = build_rigid_game(true);
+        let mut rng = new_rng();
+        let mut board = game.to_board(&level);
+        board.set_wants_to_move(&Position::new(0, 0), 0, WantsToMove::Right);
+
+        game.evaluate(&mut rng, &mut board, &mut ());
+
+        // The bottom is blocked by the wall so nothing in the block moves (and the player is blocked by the block)
+        assert!(board.has_sprite(&Position::new(0, 0), &sprites[0]));
+        assert!(board.has_sprite(&Position::new(1, 0), &sprites[1]));
+        assert!(board.has_sprite(&Position::new(1, 1), &sprites[2]));
+        assert_eq!(
+            board.get_wants_to_move(&Position::new(0, 0), 0),
+            Some(WantsToMove::Stationary)
+        );
+    }
+
+    #[test]
+    fn game_rigid_block_moves_when_not_blocked() {
+        init();
+        let (game, _, sprites) = build_rigid_game(true);
+        let tile = |sprites| Tile::new(TileKind::And, &String::from("t"), sprites);
+        let level = Level::Map(vec![
+            vec![tile(vec![sprites[0]]), tile(vec![sprites[1]]), tile(vec![])],
+            vec![tile(vec![]), tile(vec![sprites[2]]), tile(vec![])],
+        ]);
+        let mut rng = new_rng();
+        let mut board = game.to_board(&level);
+        board.set_wants_to_move(&Position::new(0, 0), 0, WantsToMove::Right);
+
+        game.evaluate(&mut rng, &mut board, &mut ());
+
+        assert!(board.has_sprite(&Position::new(1, 0), &sprites[0]));
+        assert!(board.has_sprite(&Position::new(2, 0), &sprites[1]));
+        assert!(board.has_sprite(&Position::new(2, 1), &sprites[2]));
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,110 @@
+use crate::model::board::Board;
+use crate::model::game::GameData;
+use crate::model::game::Level;
+use crate::model::game::SpriteLookup;
+use crate::model::util::Position;
+use crate::model::util::SpriteState;
+
+/// The characters that the levels of a game are drawn with, and the sprites that each one
+/// puts in a cell (including the background). Used by `Board::to_text()` and `Board::from_text()`
+#[derive(Debug)]
+pub struct Legend {
+    glyphs: Vec<(char, Vec<SpriteState>)>,
+    lookup: SpriteLookup,
+}
+
+impl Legend {
+    pub fn new(game: &GameData) -> Self {
+        let mut glyphs: Vec<(char, Vec<SpriteState>)> = vec![];
+        for level in &game.levels {
+            let grid = match level {
+                Level::Map(grid) => grid,
+                Level::Message(_) => continue,
+            };
+            for tile in grid.iter().flatten().filter(|tile| !tile.is_or()) {
+                let mut chars = tile.name.chars();
+                let glyph = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => continue,
+                };
+                // `[` starts an inline list of sprites in the text form
+                if glyph.is_whitespace() || glyph == '[' || glyph == ']' {
+                    continue;
+                }
+                if glyphs.iter().any(|(c, _)| *c == glyph) {
+                    continue;
+                }
+                // Same sprites as the cell that `Board::from_tiles()` builds
+                let cell = Board::from_tiles(&vec![vec![tile.clone()]], &game.background_tile);
+                let mut sprites = cell.get_sprite_states(&Position::new(0, 0));
+                sprites.sort();
+                glyphs.push((glyph, sprites));
+            }
+        }
+        Self {
+            glyphs,
+            lookup: SpriteLookup::new(&game.sprites),
+        }
+    }
+
+    /// The character for a cell with exactly these (sorted) sprites. When several characters
+    /// draw the same sprites the one that appears first in the levels is used
+    pub fn glyph(&self, sprites: &[SpriteState]) -> Option<char> {
+        self.glyphs
+            .iter()
+            .find(|(_, s)| s.as_slice() == sprites)
+            .map(|(glyph, _)| *glyph)
+    }
+
+    pub fn sprites(&self, glyph: char) -> Option<&Vec<SpriteState>> {
+        self.glyphs
+            .iter()
+            .find(|(c, _)| *c == glyph)
+            .map(|(_, sprites)| sprites)
+    }
+
+    pub fn sprite_name(&self, sprite: &SpriteState) -> Option<&String> {
+        self.lookup.to_name(&sprite.index)
+    }
+
+    pub fn sprite(&self, name: &str) -> Option<SpriteState> {
+        self.lookup.to_id(&name.to_string()).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture;
+
+    fn build() -> (GameData, Legend) {
+        let game = fixture::game("", "#####\n#P.T#\n#_..#\n#####");
+        let legend = Legend::new(&game);
+        (game, legend)
+    }
+
+    #[test]
+    fn round_trips_a_level() {
+        let (game, legend) = build();
+        let board = game.to_board(&game.levels[0]);
+        let text = board.to_text(&legend);
+        // `_` draws the same cell as `.`, which comes first
+        assert_eq!(text, "#####\n#P.T#\n#...#\n#####\n");
+        assert_eq!(Board::from_text(&text, &legend).unwrap(), board);
+    }
+
+    #[test]
+    fn reads_glyphs_and_sprite_lists() {
+        let (_, legend) = build();
+        let board = Board::from_text("#.\n_[Background Target Player]\n", &legend).unwrap();
+        let player = legend.sprite("Player").unwrap();
+        assert!(board.has_sprite(&Position::new(1, 1), &player));
+        assert_eq!(board.to_text(&legend), "#.\n.[Background Target Player]\n");
+
+        assert!(Board::from_text("#?\n", &legend).is_err());
+        assert!(Board::from_text("#[Ghost]\n", &legend).is_err());
+        assert!(Board::from_text("##\n#\n", &legend).is_err());
+        assert!(Board::from_text("\n", &legend).is_err());
+    }
+}
@@ -8,6 +8,7 @@ use rand::Rng;
 
 use crate::bitset::BitSet;
 use crate::model::board::Board;
+use crate::model::rule::RigidMovements;
 use crate::model::tile::Tile;
 use crate::model::tile::TileKind;
 use crate::model::tile::TileWithModifier;
@@ -248,6 +249,8 @@ pub struct Neighbor {
     add_tiles: Vec<AddTile>,       // can be a set
     remove_tiles: Vec<RemoveTile>, // can be a set
     replace_directions: Vec<ReplaceDirection>,
+    // Collision layers whose movement a RIGID rule is responsible for (see `prepare_actions()`)
+    rigid_layers: Vec<u16>,
 
     all_sprites: BitSet,
     any_sprites: BitSet,
@@ -338,6 +341,7 @@ impl Neighbor {
             add_tiles: vec![],
             remove_tiles: vec![],
             replace_directions: vec![],
+            rigid_layers: vec![],
 
             all_sprites,
             any_sprites,
@@ -559,6 +563,24 @@ impl Neighbor {
                 }
             }
         }
+        // Same layers as the reference engine's movementsLayerMask: the ones that the action side
+        // gives a direction to, and the ones that it clears
+        let mut action_layers: FnvHashSet<u16> = FnvHashSet::default();
+        for t in action.tiles_with_modifier.iter().filter(|t| !t.negated) {
+            action_layers.extend(t.tile.get_collision_layers().iter());
+            if t.direction.is_some() {
+                self.rigid_layers.extend(t.tile.get_collision_layers());
+            }
+        }
+        for t in self.tiles_with_modifier.iter().filter(|t| !t.negated) {
+            for c in t.tile.get_collision_layers() {
+                if !action_layers.contains(c) {
+                    self.rigid_layers.push(*c);
+                }
+            }
+        }
+        self.rigid_layers.sort();
+        self.rigid_layers.dedup();
         self.prepared_actions = true;
 
         self.add_tiles.len() > 0 || self.remove_tiles.len() > 0 || self.replace_directions.len() > 0
@@ -594,6 +616,19 @@ impl Neighbor {
         something_evaluated
     }
 
+    /// Marks the movements in the collision layers that the action side writes as belonging to
+    /// `rigid_group` (unless another RIGID group already claimed them)
+    pub fn record_rigid_movements(
+        &self,
+        rigid: &mut RigidMovements,
+        pos: &Position,
+        rigid_group: usize,
+    ) {
+        for c in &self.rigid_layers {
+            rigid.record(pos, *c, rigid_group);
+        }
+    }
+
     fn pair_up_tiles(
         &self,
         action: &Neighbor,
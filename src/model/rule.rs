@@ -2,15 +2,17 @@ use log::debug;
 use log::trace;
 
 use fnv::FnvHashMap;
+use fnv::FnvHashSet;
 use rand::Rng;
 use std::fmt;
 
-use crate::debugger::ScreenDumper;
 use crate::model::board::Board;
 use crate::model::bracket::Bracket;
 use crate::model::bracket::BracketMatch;
 use crate::model::util::Position;
 use crate::model::util::TriggeredCommands;
+use crate::observer::EngineObserver;
+use crate::profile::RuleStats;
 
 #[derive(Clone, Debug)]
 pub enum Command {
@@ -20,7 +22,8 @@ pub enum Command {
     Checkpoint,
     Restart,
     Win,
-    Sfx,
+    // The sfxr seed of the sound to play
+    Sfx(u32),
 }
 
 impl Command {
@@ -31,7 +34,11 @@ impl Command {
             Command::Checkpoint => t.checkpoint = true,
             Command::Restart => t.restart = true,
             Command::Win => t.win = true,
-            Command::Sfx => t.sfx = true,
+            Command::Sfx(seed) => {
+                if !t.sfx.contains(seed) {
+                    t.sfx.push(*seed);
+                }
+            }
             Command::Message(m) => t.message = Some(m.clone()),
         }
     }
@@ -87,8 +94,42 @@ impl Rule {
         board: &mut Board,
         triggered: &mut TriggeredCommands,
         eval_once: bool,
+        observer: &mut dyn EngineObserver,
     ) -> bool {
+        self.evaluate_in_group(None, rng, board, triggered, eval_once, observer)
+    }
+
+    /// Same as `evaluate()` but when this rule is RIGID the collision layers it writes are
+    /// recorded in `rigid` as belonging to the rule group
+    pub fn evaluate_in_group<R: Rng + ?Sized>(
+        &self,
+        rigid: Option<(usize, &mut RigidMovements)>,
+        rng: &mut R,
+        board: &mut Board,
+        triggered: &mut TriggeredCommands,
+        eval_once: bool,
+        observer: &mut dyn EngineObserver,
+    ) -> bool {
+        let rigid = rigid.filter(|_| self.rigid);
+        observer.rule_started(self);
+        let (ret, mut stats) =
+            self.evaluate_counting(rng, board, triggered, eval_once, rigid, observer);
+        stats.attempts = 1;
+        observer.rule_finished(self, &stats);
+        ret
+    }
+
+    fn evaluate_counting<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        board: &mut Board,
+        triggered: &mut TriggeredCommands,
+        eval_once: bool,
+        mut rigid: Option<(usize, &mut RigidMovements)>,
+        observer: &mut dyn EngineObserver,
+    ) -> (bool, RuleStats) {
         trace!("Evaluating Rule '{}'... ", self);
+        let mut stats = RuleStats::default();
         let mut applied_pos = vec![];
         let mut board_changed_at_least_once = false;
 
@@ -99,21 +140,23 @@ impl Rule {
 
         // If each bracket contains at least 1 match then the rule is satisfied
         if !matches.is_empty() && matches.iter().all(|m| m.len() > 0) {
+            stats.matches += 1;
+            observer.rule_matched(self);
             // Since the conditions matched, set the commands
             triggered.merge(&self.commands);
+            if self.commands != TriggeredCommands::default() {
+                observer.command_triggered(self, &self.commands);
+            }
 
             // A rule can have no actions, just commands
             if self.has_only_commands() {
-                ScreenDumper::dump(
-                    board,
-                    triggered,
-                    &format!(
-                        "Evaluated command-only rule {} '{}'... ",
-                        if eval_once { "ONCE" } else { "" },
-                        self
-                    ),
-                );
-                return false;
+                let found: Vec<_> = matches
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(i, m)| m.into_iter().map(move |m| (i, m)))
+                    .collect();
+                observer.rule_applied(self, board, triggered, &found);
+                return (false, stats);
             } else {
                 // Evaluate all permutations but check to make sure each perm still matches
                 let perms = build_permutations(&matches);
@@ -135,50 +178,64 @@ impl Rule {
                         .all(|x| x);
 
                     if still_matches {
-                        self.conditions.iter().zip(perm).for_each(|(c, p)| {
-                            // Check again that the cell matches because a previous
-                            // permutation could have caused the cell to change
-                            if c.matches(board, p.clone()) {
-                                applied_pos.push(p.clone());
-                                board_changed_at_least_once |=
-                                    c.evaluate(rng, board, p.clone(), &magic_or_tiles);
-                            }
-                        });
+                        stats.permutations += 1;
+                        self.conditions
+                            .iter()
+                            .zip(perm)
+                            .enumerate()
+                            .for_each(|(i, (c, p))| {
+                                // Check again that the cell matches because a previous
+                                // permutation could have caused the cell to change
+                                if c.matches(board, p.clone()) {
+                                    applied_pos.push((i, p.clone()));
+                                    board_changed_at_least_once |=
+                                        c.evaluate(rng, board, p.clone(), &magic_or_tiles);
+                                    if let Some((rigid_group, rigid)) = &mut rigid {
+                                        c.record_rigid_movements(rigid, &p, *rigid_group);
+                                    }
+                                }
+                            });
                     }
 
                     if eval_once && board_changed_at_least_once {
-                        ScreenDumper::dump(
-                            board,
-                            triggered,
-                            &format!(
-                                "Evaluated_shouldbefalse? {} '{}'... ",
-                                if eval_once { "RNDM" } else { "" },
-                                self
-                            ),
-                        );
-                        return board_changed_at_least_once;
+                        observer.rule_applied(self, board, triggered, &applied_pos);
+                        return (board_changed_at_least_once, stats);
                     }
                 }
             }
         }
         if !board_changed_at_least_once {
             trace!("unchanged board");
-            return false;
+            return (false, stats);
         }
 
-        if ScreenDumper::is_enabled() {
-            ScreenDumper::dump(
-                board,
-                &triggered,
-                &format!(
-                    "Evaluated {} '{}'... ",
-                    if eval_once { "ONCE" } else { "" },
-                    self
-                ),
-            );
-        }
+        observer.rule_applied(self, board, triggered, &applied_pos);
         trace!("Board changed? {}", board_changed_at_least_once);
-        board_changed_at_least_once
+        (board_changed_at_least_once, stats)
+    }
+
+    /// The cells that each bracket matched, given the matches that were applied
+    /// (see `EngineObserver::rule_applied()`)
+    pub fn matched_cells(&self, matches: &[(usize, BracketMatch)]) -> Vec<Vec<Position>> {
+        let mut cells = vec![vec![]; self.conditions.len()];
+        for (i, m) in matches {
+            // The positions go all the way to the edge of the board
+            let bracket = &self.conditions[*i];
+            let before = m
+                .before_positions
+                .iter()
+                .take(bracket.before_neighbors.len());
+            let after = m
+                .after_positions
+                .iter()
+                .flat_map(|after| after.iter().take(bracket.after_neighbors.len()));
+            for pos in before.chain(after) {
+                if !cells[*i].contains(&pos) {
+                    cells[*i].push(pos);
+                }
+            }
+        }
+        cells
     }
 
     fn find_matches(&self, board: &Board) -> Vec<Vec<BracketMatch>> {
@@ -256,9 +313,45 @@ impl fmt::Display for Rule {
     }
 }
 
+/// What the RIGID rule groups did during one `GameData::evaluate()`. It only lives for that
+/// one evaluation so it is not part of the board
+#[derive(Default, Debug)]
+pub struct RigidMovements {
+    // Collision layers that RIGID rules wrote, keyed by the rule group that wrote them
+    written: FnvHashMap<(Position, u16), usize>,
+    // Rule groups that are not allowed to run because one of their RIGID movements was blocked
+    banned: FnvHashSet<usize>,
+}
+
+impl RigidMovements {
+    /// Remember that a RIGID rule group wrote this collision layer so the group can be undone if
+    /// the movement gets blocked. The first group to write a cell keeps it until the rules re-run
+    pub fn record(&mut self, pos: &Position, collision_layer: u16, rigid_group: usize) {
+        self.written
+            .entry((*pos, collision_layer))
+            .or_insert(rigid_group);
+    }
+
+    pub fn group_at(&self, pos: &Position, collision_layer: u16) -> Option<usize> {
+        self.written.get(&(*pos, collision_layer)).cloned()
+    }
+
+    pub fn is_banned(&self, rigid_group: usize) -> bool {
+        self.banned.contains(&rigid_group)
+    }
+
+    /// Stops these groups from running and forgets what was written so the rules can run again
+    pub fn ban(&mut self, rigid_groups: &FnvHashSet<usize>) {
+        self.banned.extend(rigid_groups);
+        self.written.clear();
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct RuleGroup {
     pub random: bool,
+    /// Identifies the group when one of its RIGID movements gets blocked (see `GameData::new()`)
+    pub rigid_group: Option<usize>,
     pub rules: Vec<Rule>,
 }
 
@@ -266,14 +359,27 @@ impl RuleGroup {
     pub fn prepare_actions(&mut self) {
         self.rules.iter_mut().for_each(|r| r.prepare_actions())
     }
+
+    pub fn is_rigid(&self) -> bool {
+        self.rules.iter().any(|r| r.rigid)
+    }
+
     pub fn evaluate<R: Rng + ?Sized>(
         &self,
         rng: &mut R,
         board: &mut Board,
         triggered: &mut TriggeredCommands,
         late: bool,
+        rigid: &mut RigidMovements,
+        observer: &mut dyn EngineObserver,
     ) -> bool {
         trace!("Start RuleGroup '{}'... ", self);
+        if let Some(rigid_group) = self.rigid_group {
+            if rigid.is_banned(rigid_group) {
+                debug!("Skipping RIGID RuleGroup because it was blocked '{}'", self);
+                return false;
+            }
+        }
         if self.random {
             let rnd = rng.gen_range(0, self.rules.len());
             // Keep trying a rule until one matches
@@ -286,7 +392,14 @@ impl RuleGroup {
                 let rule = &self.rules[(rnd + offset) % self.rules.len()];
                 if rule.late == late {
                     let before = triggered.clone();
-                    ret = rule.evaluate(rng, board, triggered, true);
+                    ret = rule.evaluate_in_group(
+                        self.rigid_group.map(|g| (g, &mut *rigid)),
+                        rng,
+                        board,
+                        triggered,
+                        true,
+                        observer,
+                    );
                     ret |= before != *triggered;
                     if ret {
                         debug!("Evaluated Rule {}", rule);
@@ -314,7 +427,14 @@ impl RuleGroup {
                     let mut ret;
                     loop {
                         // keep evaluating the rule until it is false (entanglement-two putting an arrow in a vactube)
-                        ret = r.evaluate(rng, board, triggered, false);
+                        ret = r.evaluate_in_group(
+                            self.rigid_group.map(|g| (g, &mut *rigid)),
+                            rng,
+                            board,
+                            triggered,
+                            false,
+                            observer,
+                        );
                         board_changed_this_iter |= ret;
                         if ret {
                             debug!("Evaluated Rule {}", r);
@@ -360,6 +480,8 @@ impl RuleLoop {
         rng: &mut R,
         board: &mut Board,
         late: bool,
+        rigid: &mut RigidMovements,
+        observer: &mut dyn EngineObserver,
     ) -> TriggeredCommands {
         trace!("Start RuleLoop/Group:loop?{} '{}'... ", self.is_loop, self);
         let mut ret = TriggeredCommands::default();
@@ -369,7 +491,7 @@ impl RuleLoop {
             let mut evaluated_something = false;
 
             for rule in &self.rules {
-                evaluated_something |= rule.evaluate(rng, board, &mut ret, late);
+                evaluated_something |= rule.evaluate(rng, board, &mut ret, late, rigid, observer);
             }
 
             // Only evaluate the rules once if this is _really_ a RuleGroup
@@ -459,6 +581,7 @@ mod tests {
         };
         let mut rule = RuleGroup {
             random: false,
+            rigid_group: None,
             rules: vec![rule],
         };
 
@@ -477,6 +600,8 @@ mod tests {
             &mut board,
             &mut TriggeredCommands::default(),
             false,
+            &mut RigidMovements::default(),
+            &mut (),
         );
 
         // make sure we did crawl
@@ -555,6 +680,7 @@ mod tests {
         };
         let mut rule = RuleGroup {
             random: false,
+            rigid_group: None,
             rules: vec![rule],
         };
 
@@ -584,6 +710,8 @@ mod tests {
             &mut board,
             &mut TriggeredCommands::default(),
             false,
+            &mut RigidMovements::default(),
+            &mut (),
         );
 
         assert_eq!(
@@ -640,6 +768,7 @@ mod tests {
         };
         let mut rule = RuleGroup {
             random: false,
+            rigid_group: None,
             rules: vec![rule],
         };
 
@@ -669,6 +798,8 @@ mod tests {
             &mut board,
             &mut TriggeredCommands::default(),
             false,
+            &mut RigidMovements::default(),
+            &mut (),
         );
 
         assert_eq!(
@@ -718,6 +849,7 @@ mod tests {
             &mut board,
             &mut TriggeredCommands::default(),
             true,
+            &mut (),
         ); // RANDOM so run once
 
         assert!(board.has_sprite(&origin, &player) ^ board.has_sprite(&end, &player));
@@ -748,7 +880,7 @@ mod tests {
 
         let mut commands = TriggeredCommands::default();
         assert!(
-            !rule.evaluate(&mut rng, &mut board, &mut commands, false),
+            !rule.evaluate(&mut rng, &mut board, &mut commands, false, &mut ()),
             "Board should not have changed, only the triggered commands"
         );
         assert!(commands.win);
@@ -779,7 +911,7 @@ mod tests {
 
         let mut commands = TriggeredCommands::default();
         assert!(
-            !rule.evaluate(&mut rng, &mut board, &mut commands, false),
+            !rule.evaluate(&mut rng, &mut board, &mut commands, false, &mut ()),
             "Board should not have changed"
         );
         assert!(commands.win);
@@ -805,6 +937,7 @@ mod tests {
             is_loop: true,
             rules: vec![RuleGroup {
                 random: false,
+                rigid_group: None,
                 rules: vec![rule],
             }],
         };
@@ -813,7 +946,13 @@ mod tests {
         let mut board = Board::new(1, 1);
 
         // test that we do not loop indefinitely
-        rule_loop.evaluate(&mut rng, &mut board, false);
+        rule_loop.evaluate(
+            &mut rng,
+            &mut board,
+            false,
+            &mut RigidMovements::default(),
+            &mut (),
+        );
     }
 
     #[test]
@@ -855,6 +994,7 @@ mod tests {
         };
         let mut rule = RuleGroup {
             random: false,
+            rigid_group: None,
             rules: vec![rule1, rule2],
         };
         rule.prepare_actions();
@@ -871,6 +1011,8 @@ mod tests {
             &mut board,
             &mut TriggeredCommands::default(),
             false,
+            &mut RigidMovements::default(),
+            &mut (),
         );
 
         assert!(board.has_sprite(&origin, &player));
@@ -926,6 +1068,7 @@ mod tests {
 
         let mut rule = RuleGroup {
             random: false,
+            rigid_group: None,
             rules: vec![rule1, rule2],
         };
         rule.prepare_actions();
@@ -943,6 +1086,8 @@ mod tests {
             &mut board,
             &mut TriggeredCommands::default(),
             false,
+            &mut RigidMovements::default(),
+            &mut (),
         );
 
         assert!(!board.has_sprite(&origin, &player));
@@ -983,6 +1128,7 @@ mod tests {
         };
         let mut rule = RuleGroup {
             random: false,
+            rigid_group: None,
             rules: vec![rule],
         };
 
@@ -999,7 +1145,14 @@ mod tests {
         assert!(board.has_sprite(&origin, &hat));
 
         let mut triggered = TriggeredCommands::default();
-        assert!(rule.evaluate(&mut rng, &mut board, &mut triggered, false));
+        assert!(rule.evaluate(
+            &mut rng,
+            &mut board,
+            &mut triggered,
+            false,
+            &mut RigidMovements::default(),
+            &mut ()
+        ));
 
         assert!(board.has_sprite(&origin, &marker));
 
@@ -1038,6 +1191,7 @@ mod tests {
         };
         let mut rule = RuleGroup {
             random: false,
+            rigid_group: None,
             rules: vec![rule],
         };
 
@@ -1057,7 +1211,9 @@ mod tests {
             &mut rng,
             &mut board,
             &mut TriggeredCommands::default(),
-            false
+            false,
+            &mut RigidMovements::default(),
+            &mut ()
         ));
 
         // Verify that we removed both sprites
@@ -178,7 +178,8 @@ pub struct TriggeredCommands {
     pub checkpoint: bool,
     pub restart: bool,
     pub win: bool,
-    pub sfx: bool,
+    // The sfxr seeds of the sounds to play (each one only once)
+    pub sfx: Vec<u32>,
 }
 
 impl TriggeredCommands {
@@ -191,7 +192,11 @@ impl TriggeredCommands {
         self.checkpoint |= other.checkpoint;
         self.restart |= other.restart;
         self.win |= other.win;
-        self.sfx |= other.sfx;
+        for seed in &other.sfx {
+            if !self.sfx.contains(seed) {
+                self.sfx.push(*seed);
+            }
+        }
     }
 }
 
@@ -212,8 +217,8 @@ impl fmt::Display for TriggeredCommands {
         if self.win {
             write!(f, " WIN")?;
         }
-        if self.sfx {
-            write!(f, " SFX")?;
+        for seed in &self.sfx {
+            write!(f, " SFX {}", seed)?;
         }
         if let Some(message) = &self.message {
             write!(f, " MESSAGE {}", message)?;
@@ -0,0 +1,237 @@
+use std::fmt;
+
+use crate::engine::EngineInput;
+use crate::engine::TickResult;
+use crate::model::board::Board;
+use crate::model::bracket::BracketMatch;
+use crate::model::game::GameData;
+use crate::model::rule::Rule;
+use crate::model::util::Position;
+use crate::model::util::TriggeredCommands;
+use crate::profile::RuleStats;
+
+/// The parts of a tick, in the order the engine runs them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Input,
+    Rules,
+    Movement,
+    LateRules,
+    WinCheck,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Phase::Input => "input",
+            Phase::Rules => "rules",
+            Phase::Movement => "movement",
+            Phase::LateRules => "late rules",
+            Phase::WinCheck => "win check",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Gets told what the engine does while it runs a tick (see `Engine::tick_with_observer()`).
+/// Every method does nothing by default so an observer only implements the events it needs.
+/// The step debugger (`stepper::Session`) and the profiler (`profile::Profiler`) are observers
+pub trait EngineObserver {
+    /// A tick on a level of `game` starts from `board`. Ticks on a message and the ticks that
+    /// only undo, redo or get ignored are not observed
+    fn tick_started(&mut self, _game: &GameData, _input: Option<EngineInput>, _board: &Board) {}
+
+    fn phase_started(&mut self, _phase: Phase) {}
+
+    /// `triggered` has the commands of the tick so far
+    fn phase_finished(&mut self, _phase: Phase, _board: &Board, _triggered: &TriggeredCommands) {}
+
+    /// The rule is about to be evaluated
+    fn rule_started(&mut self, _rule: &Rule) {}
+
+    /// Every bracket of the rule found a match
+    fn rule_matched(&mut self, _rule: &Rule) {}
+
+    /// The rule changed the board (or triggered its commands when it only has commands).
+    /// `matches` are the bracket index and match of every cell it was applied to
+    /// (see `Rule::matched_cells()`)
+    fn rule_applied(
+        &mut self,
+        _rule: &Rule,
+        _board: &Board,
+        _triggered: &TriggeredCommands,
+        _matches: &[(usize, BracketMatch)],
+    ) {
+    }
+
+    /// Done with the rule. `stats` counts what happened (the time is left for the observer to measure)
+    fn rule_finished(&mut self, _rule: &Rule, _stats: &RuleStats) {}
+
+    /// The rule matched and triggered `commands`
+    fn command_triggered(&mut self, _rule: &Rule, _commands: &TriggeredCommands) {}
+
+    fn sprite_moved(&mut self, _sprite_index: u16, _from: Position, _to: Position) {}
+
+    /// The sprite wanted to move but something was in the way (or the edge of the level)
+    fn movement_blocked(&mut self, _sprite_index: u16, _pos: Position) {}
+
+    /// A RIGID movement was blocked. The board went back to `board` and the rules run again
+    fn rigid_blocked(&mut self, _board: &Board, _triggered: &TriggeredCommands) {}
+
+    /// `board` is the level after the tick
+    fn tick_finished(&mut self, _board: &Board, _result: &TickResult) {}
+}
+
+/// Observes nothing. `Engine::tick()` uses it
+impl EngineObserver for () {}
+
+/// Tells every observer in the list, in order
+impl EngineObserver for Vec<&mut dyn EngineObserver> {
+    fn tick_started(&mut self, game: &GameData, input: Option<EngineInput>, board: &Board) {
+        self.iter_mut()
+            .for_each(|o| o.tick_started(game, input, board))
+    }
+
+    fn phase_started(&mut self, phase: Phase) {
+        self.iter_mut().for_each(|o| o.phase_started(phase))
+    }
+
+    fn phase_finished(&mut self, phase: Phase, board: &Board, triggered: &TriggeredCommands) {
+        self.iter_mut()
+            .for_each(|o| o.phase_finished(phase, board, triggered))
+    }
+
+    fn rule_started(&mut self, rule: &Rule) {
+        self.iter_mut().for_each(|o| o.rule_started(rule))
+    }
+
+    fn rule_matched(&mut self, rule: &Rule) {
+        self.iter_mut().for_each(|o| o.rule_matched(rule))
+    }
+
+    fn rule_applied(
+        &mut self,
+        rule: &Rule,
+        board: &Board,
+        triggered: &TriggeredCommands,
+        matches: &[(usize, BracketMatch)],
+    ) {
+        self.iter_mut()
+            .for_each(|o| o.rule_applied(rule, board, triggered, matches))
+    }
+
+    fn rule_finished(&mut self, rule: &Rule, stats: &RuleStats) {
+        self.iter_mut().for_each(|o| o.rule_finished(rule, stats))
+    }
+
+    fn command_triggered(&mut self, rule: &Rule, commands: &TriggeredCommands) {
+        self.iter_mut()
+            .for_each(|o| o.command_triggered(rule, commands))
+    }
+
+    fn sprite_moved(&mut self, sprite_index: u16, from: Position, to: Position) {
+        self.iter_mut()
+            .for_each(|o| o.sprite_moved(sprite_index, from, to))
+    }
+
+    fn movement_blocked(&mut self, sprite_index: u16, pos: Position) {
+        self.iter_mut()
+            .for_each(|o| o.movement_blocked(sprite_index, pos))
+    }
+
+    fn rigid_blocked(&mut self, board: &Board, triggered: &TriggeredCommands) {
+        self.iter_mut()
+            .for_each(|o| o.rigid_blocked(board, triggered))
+    }
+
+    fn tick_finished(&mut self, board: &Board, result: &TickResult) {
+        self.iter_mut().for_each(|o| o.tick_finished(board, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture;
+
+    // Writes down the events that do not happen once per rule
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl EngineObserver for Events {
+        fn tick_started(&mut self, _game: &GameData, input: Option<EngineInput>, _board: &Board) {
+            self.0.push(format!("start {:?}", input));
+        }
+
+        fn phase_started(&mut self, phase: Phase) {
+            self.0.push(phase.to_string());
+        }
+
+        fn rule_applied(
+            &mut self,
+            rule: &Rule,
+            _board: &Board,
+            _triggered: &TriggeredCommands,
+            matches: &[(usize, BracketMatch)],
+        ) {
+            let cells: Vec<String> = rule.matched_cells(matches)[0]
+                .iter()
+                .map(|pos| pos.to_string())
+                .collect();
+            self.0.push(format!("applied {}", cells.join(" ")));
+        }
+
+        fn command_triggered(&mut self, _rule: &Rule, commands: &TriggeredCommands) {
+            self.0.push(format!("command{}", commands));
+        }
+
+        fn sprite_moved(&mut self, _sprite_index: u16, from: Position, to: Position) {
+            self.0.push(format!("moved {} {}", from, to));
+        }
+
+        fn movement_blocked(&mut self, _sprite_index: u16, pos: Position) {
+            self.0.push(format!("blocked {}", pos));
+        }
+
+        fn tick_finished(&mut self, _board: &Board, result: &TickResult) {
+            self.0.push(format!("end {}", result.changed));
+        }
+    }
+
+    #[test]
+    fn tells_every_observer_what_happened() {
+        let rules = "[ > Player | Crate ] -> [ > Player | > Crate ] checkpoint";
+        let mut engine = fixture::engine(rules, "P.C#");
+        let mut first = Events::default();
+        let mut second = Events::default();
+        {
+            let mut observers: Vec<&mut dyn EngineObserver> = vec![&mut first, &mut second];
+            engine.tick_with_observer(Some(EngineInput::Right), &mut observers);
+            engine.tick_with_observer(Some(EngineInput::Right), &mut observers);
+        }
+        assert_eq!(first.0, second.0);
+        let second_tick: Vec<_> = first.0.iter().skip_while(|e| *e != "end true").collect();
+        assert_eq!(
+            second_tick,
+            vec![
+                "end true",
+                "start Some(Right)",
+                "input",
+                "applied @(1,0)",
+                "rules",
+                "command CHECKPOINT",
+                "applied @(1,0) @(2,0)",
+                // The rule keeps matching until it stops changing the board
+                "command CHECKPOINT",
+                "command CHECKPOINT",
+                "movement",
+                "blocked @(1,0)",
+                "blocked @(2,0)",
+                "late rules",
+                "win check",
+                "end false",
+            ]
+        );
+    }
+}
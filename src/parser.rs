@@ -1,7 +1,9 @@
 use fnv::FnvHashMap;
+use fnv::FnvHashSet;
 use log::{debug, trace};
 use std::cmp;
 use std::error::Error;
+use std::fmt;
 use std::io::Read;
 
 use crate::color::ColorSpace;
@@ -9,9 +11,12 @@ use crate::color::Rgb;
 use crate::json;
 use crate::model::bracket::Bracket;
 use crate::model::game::GameData;
+use crate::model::game::GameEvent;
 use crate::model::game::Level;
 use crate::model::game::Metadata;
+use crate::model::game::Sounds;
 use crate::model::game::Sprite;
+use crate::model::game::SpriteEvent;
 use crate::model::game::WinCondition;
 use crate::model::game::WinConditionOnQualifier;
 use crate::model::neighbor::Neighbor;
@@ -25,22 +30,114 @@ use crate::model::tile::TileWithModifier;
 use crate::model::util::Dimension;
 use crate::model::util::SpriteState;
 use crate::model::util::TriggeredCommands;
+use crate::source;
+
+/// An id that is used in the game but not defined, and the JSON section that used it
+/// (e.g. `tiles.tile-3` or `levels[2]`)
+#[derive(Debug, PartialEq)]
+pub struct Reference {
+    pub id: String,
+    pub section: String,
+}
+
+/// Why a `*.parsed.json` game (or the JSON that the source parser builds) could not be loaded
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    MissingSprite(Reference),
+    MissingTile(Reference),
+    MissingTileWithModifier(Reference),
+    MissingNeighbor(Reference),
+    MissingBracket(Reference),
+    MissingCommand(Reference),
+    MissingSound(Reference),
+    MissingRule(Reference),
+    /// A color that is not in the `colors` section
+    MissingColor(Reference),
+    /// A color that is not written as `#rrggbb`
+    InvalidColor(Reference),
+    NoPlayer,
+    NoBackground,
+}
 
-fn build_sprites(
-    sprite_lookup: &FnvHashMap<String, SpriteState>,
-    sprite_ids: Vec<String>,
-) -> Vec<SpriteState> {
-    let mut ret = vec![];
-    for sprite_id in sprite_ids {
-        let sprite = sprite_lookup.get(&sprite_id).unwrap();
-        ret.push(*sprite);
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (what, r) = match self {
+            ParseError::MissingSprite(r) => ("unknown sprite", r),
+            ParseError::MissingTile(r) => ("unknown tile", r),
+            ParseError::MissingTileWithModifier(r) => ("unknown tile with modifier", r),
+            ParseError::MissingNeighbor(r) => ("unknown neighbor", r),
+            ParseError::MissingBracket(r) => ("unknown bracket", r),
+            ParseError::MissingCommand(r) => ("unknown command", r),
+            ParseError::MissingSound(r) => ("unknown sound", r),
+            ParseError::MissingRule(r) => ("unknown rule", r),
+            ParseError::MissingColor(r) => ("unknown color", r),
+            ParseError::InvalidColor(r) => ("invalid color", r),
+            ParseError::NoPlayer => return write!(f, "the game has no Player sprite or tile"),
+            ParseError::NoBackground => {
+                return write!(f, "the game has no Background sprite or tile")
+            }
+        };
+        write!(f, "{} '{}' (referenced from {})", what, r.id, r.section)
     }
-    ret
 }
 
+impl Error for ParseError {}
+
+// Looks up an id that `section` refers to
+fn find<'a, T>(
+    map: &'a FnvHashMap<String, T>,
+    id: &str,
+    section: &str,
+    missing: fn(Reference) -> ParseError,
+) -> Result<&'a T, ParseError> {
+    map.get(id).ok_or_else(|| {
+        missing(Reference {
+            id: id.to_string(),
+            section: section.to_string(),
+        })
+    })
+}
+
+fn find_all<T: Clone>(
+    map: &FnvHashMap<String, T>,
+    ids: &[String],
+    section: &str,
+    missing: fn(Reference) -> ParseError,
+) -> Result<Vec<T>, ParseError> {
+    ids.iter()
+        .map(|id| find(map, id, section, missing).cloned())
+        .collect()
+}
+
+fn parse_color(hex: &str, section: &str) -> Result<Rgb, ParseError> {
+    Rgb::try_parse(hex).ok_or_else(|| {
+        ParseError::InvalidColor(Reference {
+            id: hex.to_string(),
+            section: section.to_string(),
+        })
+    })
+}
+
+fn to_qualifier(qualifier: &json::WinConditionOnQualifier) -> WinConditionOnQualifier {
+    match qualifier {
+        json::WinConditionOnQualifier::All => WinConditionOnQualifier::All,
+        json::WinConditionOnQualifier::No => WinConditionOnQualifier::No,
+        json::WinConditionOnQualifier::Some => WinConditionOnQualifier::Some,
+        json::WinConditionOnQualifier::Any => WinConditionOnQualifier::Any,
+    }
+}
+
+/// Parses the JSON that the PuzzleScript compiler writes out (`*.parsed.json`)
 pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
-    let ast = json::from_file(file)?;
+    build(json::from_file(file)?)
+}
+
+/// Parses the original PuzzleScript source (the text you would paste into the editor)
+pub fn parse_source<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
+    build(source::from_file(file)?)
+}
 
+fn build(ast: json::GameMap) -> Result<GameData, Box<dyn Error>> {
     let mut sprite_map = FnvHashMap::default(); // Map of UI sprites to SpriteState
     let mut sprite_ui_map = FnvHashMap::default();
 
@@ -54,10 +151,11 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
     let mut color_map = FnvHashMap::default();
     let mut flattened_colors = FnvHashMap::default();
 
-    let mut color_closeness: Vec<_> = ast.colors.keys().collect();
-    color_closeness.sort_by(|a, b| {
-        let a = Rgb::parse(a);
-        let b = Rgb::parse(b);
+    let mut colors = vec![];
+    for hex in ast.colors.keys() {
+        colors.push((hex, parse_color(hex, "colors")?));
+    }
+    colors.sort_by(|(_, a), (_, b)| {
         let distance_a = a.distance(&a.to_closest_256());
         let distance_b = b.distance(&b.to_closest_256());
         if distance_a == distance_b {
@@ -69,14 +167,13 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
         }
     });
 
-    for hex in color_closeness {
-        let color = Rgb::parse(hex);
+    for (hex, color) in colors {
         let new_color = if ColorSpace::get_colorspace().is_true_color() {
             color
         } else {
             color.to_variant(&mut flattened_colors)
         };
-        color_map.insert(hex, new_color);
+        color_map.insert(hex.clone(), new_color);
     }
 
     debug!("Collision_layers: {}", ast.collision_layers.len());
@@ -88,24 +185,22 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
             .or_insert(vec![]);
         sprites.push((id.clone(), sprite_def.name.clone()));
 
+        let section = format!("sprites.{}", id);
+        let mut pixels = vec![];
+        for row in &sprite_def.pixels {
+            let mut pixel_row = vec![];
+            for pixel in row {
+                pixel_row.push(match pixel {
+                    None => None,
+                    Some(hex) => Some(*find(&color_map, hex, &section, ParseError::MissingColor)?),
+                });
+            }
+            pixels.push(pixel_row);
+        }
         let sprite_ui = Sprite {
             id: sprite_index_global,
             name: sprite_def.name.clone(),
-            pixels: sprite_def
-                .pixels
-                .iter()
-                .map(|row| {
-                    row.iter()
-                        .map(|pixel| match pixel {
-                            None => None,
-                            Some(hex) => Some(match color_map.get(hex) {
-                                None => panic!("Could not look up '{}' in {:?}", hex, color_map),
-                                Some(color) => color.clone(),
-                            }),
-                        })
-                        .collect()
-                })
-                .collect(),
+            pixels,
         };
         trace!(
             "Sprite Index [{}] = {}",
@@ -146,23 +241,22 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
 
     let mut tile_lookup = FnvHashMap::default();
     for (id, tile_def) in ast.tiles {
+        let section = format!("tiles.{}", id);
+        let build_sprites =
+            |ids: &[String]| find_all(&sprite_lookup, ids, &section, ParseError::MissingSprite);
         let tile = match tile_def {
             json::Tile::Or { name, sprites } => {
-                Tile::new(TileKind::Or, &name, build_sprites(&sprite_lookup, sprites))
+                Tile::new(TileKind::Or, &name, build_sprites(&sprites)?)
             }
             json::Tile::And { name, sprites } => {
-                Tile::new(TileKind::And, &name, build_sprites(&sprite_lookup, sprites))
+                Tile::new(TileKind::And, &name, build_sprites(&sprites)?)
+            }
+            json::Tile::Sprite { name, sprite } => {
+                Tile::new(TileKind::And, &name, build_sprites(&[sprite])?)
+            }
+            json::Tile::Simple { name, sprite } => {
+                Tile::new(TileKind::And, &name, build_sprites(&[sprite])?)
             }
-            json::Tile::Sprite { name, sprite } => Tile::new(
-                TileKind::And,
-                &name,
-                build_sprites(&sprite_lookup, vec![sprite]),
-            ),
-            json::Tile::Simple { name, sprite } => Tile::new(
-                TileKind::And,
-                &name,
-                build_sprites(&sprite_lookup, vec![sprite]),
-            ),
         };
 
         // Check if they were a Player or Background Tile
@@ -177,10 +271,17 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
 
     let mut twm_lookup = FnvHashMap::default();
     for (id, twm_def) in ast.tiles_with_modifiers {
+        let section = format!("tiles_with_modifiers.{}", id);
         let twm = TileWithModifier {
             random: twm_def.random,
             negated: twm_def.negated,
-            tile: (&tile_lookup).get(&twm_def.tile).unwrap().clone(),
+            tile: find(
+                &tile_lookup,
+                &twm_def.tile,
+                &section,
+                ParseError::MissingTile,
+            )?
+            .clone(),
             direction: twm_def.direction,
         };
         twm_lookup.insert(id, twm);
@@ -188,41 +289,33 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
 
     let mut neighbor_lookup = FnvHashMap::default();
     for (id, neighbor_def) in ast.neighbors {
-        let twms = neighbor_def
-            .tile_with_modifiers
-            .iter()
-            .map(|twm_id| twm_lookup.get(twm_id).unwrap().clone())
-            .collect();
+        let twms = find_all(
+            &twm_lookup,
+            &neighbor_def.tile_with_modifiers,
+            &format!("neighbors.{}", id),
+            ParseError::MissingTileWithModifier,
+        )?;
         let neighbor = Neighbor::new(twms);
         neighbor_lookup.insert(id, neighbor);
     }
 
     let mut bracket_lookup = FnvHashMap::default();
     for (id, bracket_def) in ast.brackets {
+        let section = format!("brackets.{}", id);
+        let build_neighbors =
+            |ids: &[String]| find_all(&neighbor_lookup, ids, &section, ParseError::MissingNeighbor);
         let bracket = match bracket_def {
             json::Bracket::Simple {
                 direction,
                 neighbors,
-            } => Bracket::new(
-                direction,
-                neighbors
-                    .iter()
-                    .map(|n| neighbor_lookup.get(n).unwrap().clone())
-                    .collect(),
-            ),
+            } => Bracket::new(direction, build_neighbors(&neighbors)?),
             json::Bracket::Ellipsis {
                 direction,
                 before_neighbors,
                 after_neighbors,
             } => {
-                let before = before_neighbors
-                    .iter()
-                    .map(|n| neighbor_lookup.get(n).unwrap().clone())
-                    .collect();
-                let after = after_neighbors
-                    .iter()
-                    .map(|n| neighbor_lookup.get(n).unwrap().clone())
-                    .collect();
+                let before = build_neighbors(&before_neighbors)?;
+                let after = build_neighbors(&after_neighbors)?;
                 Bracket::new_ellipsis(direction, before, after)
             }
         };
@@ -238,7 +331,16 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
             json::Command::Restart {} => Command::Restart,
             json::Command::Win {} => Command::Win,
             json::Command::Message { message } => Command::Message(message),
-            json::Command::Sfx { sound: _ } => Command::Sfx,
+            json::Command::Sfx { sound } => match ast.sounds.get(&sound) {
+                Some(json::Sound::Sfx { sound_code, .. }) => Command::Sfx(*sound_code),
+                _ => {
+                    return Err(ParseError::MissingSound(Reference {
+                        id: sound,
+                        section: format!("commands.{}", id),
+                    })
+                    .into())
+                }
+            },
         };
         command_lookup.insert(id, command);
     }
@@ -263,11 +365,14 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
                     None => false,
                 };
                 trace!("RuleCaching {}", id);
+                let section = format!("rule_definitions.{}", id);
                 let mut triggered = TriggeredCommands::default();
-                for command in commands
-                    .iter()
-                    .map(|c| command_lookup.get(c).unwrap().clone())
-                {
+                for command in find_all(
+                    &command_lookup,
+                    &commands,
+                    &section,
+                    ParseError::MissingCommand,
+                )? {
                     command.merge(&mut triggered);
                 }
                 Rule {
@@ -276,14 +381,18 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
                     random: r,
                     late,
                     rigid,
-                    conditions: conditions
-                        .iter()
-                        .map(|c| bracket_lookup.get(c).unwrap().clone())
-                        .collect(),
-                    actions: actions
-                        .iter()
-                        .map(|c| bracket_lookup.get(c).unwrap().clone())
-                        .collect(),
+                    conditions: find_all(
+                        &bracket_lookup,
+                        &conditions,
+                        &section,
+                        ParseError::MissingBracket,
+                    )?,
+                    actions: find_all(
+                        &bracket_lookup,
+                        &actions,
+                        &section,
+                        ParseError::MissingBracket,
+                    )?,
                     commands: triggered,
                 }
             }
@@ -309,6 +418,7 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
     // Now add the rule groups since we can look up the simple rules
     let mut rule_group_lookup: FnvHashMap<String, RuleGroup> = FnvHashMap::default();
     let mut rule_loop_lookup: FnvHashMap<String, RuleLoop> = FnvHashMap::default();
+    let pending_ids: FnvHashSet<String> = tbd.iter().map(|(id, _, _)| id.clone()).collect();
     trace!("StartingWithGroups");
 
     for attempt in 0..5 {
@@ -317,15 +427,25 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
         for (id, random, rules) in tbd.clone() {
             let mut skip = false;
             let mut subrules = vec![];
-            rules.iter().for_each(|r| match rule_lookup.get(r) {
-                Some(r) => subrules.push(r.clone()),
-                None => match rule_group_lookup.get(r) {
-                    None => skip = true,
-                    Some(group) => {
-                        subrules.append(&mut group.rules.clone());
-                    }
-                },
-            });
+            for r in &rules {
+                match rule_lookup.get(r) {
+                    Some(r) => subrules.push(r.clone()),
+                    None => match rule_group_lookup.get(r) {
+                        // A group that has not been built yet is tried again in the next attempt
+                        None if pending_ids.contains(r) => skip = true,
+                        None => {
+                            return Err(ParseError::MissingRule(Reference {
+                                id: r.clone(),
+                                section: format!("rule_definitions.{}", id),
+                            })
+                            .into())
+                        }
+                        Some(group) => {
+                            subrules.append(&mut group.rules.clone());
+                        }
+                    },
+                }
+            }
 
             if !skip {
                 // Random is also a marker for RuleGroup (as opposed to RuleLoop)
@@ -333,6 +453,7 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
                     Some(random) => {
                         let rule_group = RuleGroup {
                             random,
+                            rigid_group: None,
                             rules: subrules,
                         };
 
@@ -354,13 +475,18 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
         }
         let skip = false;
         let mut subrules = vec![];
-        rules.iter().for_each(|r| match rule_group_lookup.get(r) {
-            Some(r) => subrules.push(r.clone()),
-            None => panic!(
-                "Could not find Rule Group for the loop. Maybe it was a single Rule? {}",
-                r
-            ),
-        });
+        // A loop only contains rule groups (a single rule is a group of 1)
+        for r in &rules {
+            subrules.push(
+                find(
+                    &rule_group_lookup,
+                    r,
+                    &format!("rule_definitions.{}", id),
+                    ParseError::MissingRule,
+                )?
+                .clone(),
+            );
+        }
 
         if !skip {
             // Random is also a marker for RuleGroup (as opposed to RuleLoop)
@@ -381,84 +507,63 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
         }
     }
 
-    let rules = ast
-        .rules
-        .iter()
-        .map(|r| {
-            match rule_lookup.get(r) {
-                Some(simple_rule) => RuleLoop {
-                    is_loop: false,
-                    rules: vec![RuleGroup {
-                        random: simple_rule.random,
-                        rules: vec![simple_rule.clone()],
-                    }],
-                },
-                None => {
-                    // Look up in the rule group
-                    match rule_group_lookup.get(r) {
-                        Some(rule_group) => RuleLoop {
-                            is_loop: false,
-                            rules: vec![rule_group.clone()],
-                        },
-                        None => match rule_loop_lookup.get(r) {
-                            None => panic!("Could not find {}", r),
-                            Some(rule_loop) => rule_loop.clone(),
-                        },
-                    }
+    let mut rules = vec![];
+    for r in &ast.rules {
+        let rule = match rule_lookup.get(r) {
+            Some(simple_rule) => RuleLoop {
+                is_loop: false,
+                rules: vec![RuleGroup {
+                    random: simple_rule.random,
+                    rigid_group: None,
+                    rules: vec![simple_rule.clone()],
+                }],
+            },
+            None => {
+                // Look up in the rule group
+                match rule_group_lookup.get(r) {
+                    Some(rule_group) => RuleLoop {
+                        is_loop: false,
+                        rules: vec![rule_group.clone()],
+                    },
+                    None => find(&rule_loop_lookup, r, "rules", ParseError::MissingRule)?.clone(),
                 }
             }
-        })
-        .collect();
-    let levels = ast
-        .levels
-        .iter()
-        .map(|l| match l {
+        };
+        rules.push(rule);
+    }
+
+    let mut levels = vec![];
+    for (index, l) in ast.levels.iter().enumerate() {
+        let level = match l {
             json::Level::Message { message } => Level::Message(message.clone()),
             json::Level::Map { cells } => {
+                let section = format!("levels[{}]", index);
                 let tiles = cells
                     .iter()
-                    .map(|row| {
-                        row.iter()
-                            .map(|t| tile_lookup.get(t).unwrap().clone())
-                            .collect()
-                    })
-                    .collect();
+                    .map(|row| find_all(&tile_lookup, row, &section, ParseError::MissingTile))
+                    .collect::<Result<_, _>>()?;
                 Level::Map(tiles)
             }
-        })
-        .collect();
-
-    let win_conditions = ast
-        .win_conditions
-        .iter()
-        .map(|w| match w {
-            json::WinCondition::Simple { qualifier, tile } => {
-                let qualifier = match qualifier {
-                    json::WinConditionOnQualifier::All => WinConditionOnQualifier::All,
-                    json::WinConditionOnQualifier::No => WinConditionOnQualifier::No,
-                    json::WinConditionOnQualifier::Some => WinConditionOnQualifier::Some,
-                    json::WinConditionOnQualifier::Any => WinConditionOnQualifier::Any,
-                };
-                let tile = tile_lookup.get(tile).unwrap();
-                WinCondition::Simple(qualifier, tile.clone())
+        };
+        levels.push(level);
+    }
+
+    let mut win_conditions = vec![];
+    for (index, w) in ast.win_conditions.iter().enumerate() {
+        let section = format!("win_conditions[{}]", index);
+        let tile = |id: &String| find(&tile_lookup, id, &section, ParseError::MissingTile).cloned();
+        let win_condition = match w {
+            json::WinCondition::Simple { qualifier, tile: t } => {
+                WinCondition::Simple(to_qualifier(qualifier), tile(t)?)
             }
             json::WinCondition::On {
                 qualifier,
-                tile,
+                tile: t,
                 on_tile,
-            } => {
-                let qualifier = match qualifier {
-                    json::WinConditionOnQualifier::All => WinConditionOnQualifier::All,
-                    json::WinConditionOnQualifier::No => WinConditionOnQualifier::No,
-                    json::WinConditionOnQualifier::Some => WinConditionOnQualifier::Some,
-                    json::WinConditionOnQualifier::Any => WinConditionOnQualifier::Any,
-                };
-                let tile = tile_lookup.get(tile).unwrap();
-                let on_tile = tile_lookup.get(on_tile).unwrap();
-                WinCondition::On(qualifier, tile.clone(), on_tile.clone())
-            }
-        })
-        .collect();
+            } => WinCondition::On(to_qualifier(qualifier), tile(t)?, tile(on_tile)?),
+        };
+        win_conditions.push(win_condition);
+    }
 
     let metadata = Metadata {
         author: ast.metadata.author.clone(),
@@ -473,8 +578,14 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
             height: d.height,
         }),
         color_palette: ast.metadata.color_palette.clone(),
-        background_color: ast.metadata.background_color.map(|h| Rgb::parse(&h)),
-        text_color: ast.metadata.text_color.map(|h| Rgb::parse(&h)),
+        background_color: match &ast.metadata.background_color {
+            None => None,
+            Some(hex) => Some(parse_color(hex, "metadata.background_color")?),
+        },
+        text_color: match &ast.metadata.text_color {
+            None => None,
+            Some(hex) => Some(parse_color(hex, "metadata.text_color")?),
+        },
         realtime_interval: ast.metadata.realtime_interval.clone(),
         key_repeat_interval: ast.metadata.key_repeat_interval.clone(),
         again_interval: ast.metadata.again_interval.clone(),
@@ -488,14 +599,188 @@ pub fn parse<R: Read>(file: R) -> Result<GameData, Box<dyn Error>> {
         verbose_logging: ast.metadata.verbose_logging.unwrap_or(false),
     };
 
-    Ok(GameData::new(
+    let mut sounds = Sounds::default();
+    for (id, sound) in &ast.sounds {
+        let section = format!("sounds.{}", id);
+        match sound {
+            json::Sound::Sfx { .. } => {} // These are looked up by the SFX commands
+            json::Sound::When { when, sound_code } => match to_game_event(when) {
+                None => debug!("Ignoring sound for unknown event {}", when),
+                Some(event) => {
+                    sounds.game.insert(event, *sound_code);
+                }
+            },
+            json::Sound::SpriteMove { sprite, sound_code } => {
+                let tile = find(&tile_lookup, sprite, &section, ParseError::MissingTile)?;
+                for s in tile.get_sprites() {
+                    sounds
+                        .sprites
+                        .insert((s.index, SpriteEvent::Move), *sound_code);
+                }
+            }
+            json::Sound::SpriteEvent {
+                sprite,
+                event,
+                sound_code,
+            } => match to_sprite_event(event) {
+                None => debug!("Ignoring sound for unknown event {}", event),
+                Some(event) => {
+                    let tile = find(&tile_lookup, sprite, &section, ParseError::MissingTile)?;
+                    for s in tile.get_sprites() {
+                        sounds.sprites.insert((s.index, event), *sound_code);
+                    }
+                }
+            },
+        }
+    }
+
+    let mut game_data = GameData::new(
         ast.title,
         metadata,
         sprite_map,
-        player_tile.unwrap(),
-        background_tile.unwrap(),
+        player_tile.ok_or(ParseError::NoPlayer)?,
+        background_tile.ok_or(ParseError::NoBackground)?,
         rules,
         levels,
         win_conditions,
-    ))
+    );
+    game_data.sounds = sounds;
+    Ok(game_data)
+}
+
+fn to_game_event(when: &str) -> Option<GameEvent> {
+    match when {
+        "STARTGAME" => Some(GameEvent::StartGame),
+        "ENDGAME" => Some(GameEvent::EndGame),
+        "STARTLEVEL" => Some(GameEvent::StartLevel),
+        "ENDLEVEL" => Some(GameEvent::EndLevel),
+        "RESTART" => Some(GameEvent::Restart),
+        "UNDO" => Some(GameEvent::Undo),
+        "SHOWMESSAGE" => Some(GameEvent::ShowMessage),
+        "CLOSEMESSAGE" => Some(GameEvent::CloseMessage),
+        "TITLESCREEN" => Some(GameEvent::TitleScreen),
+        _ => None,
+    }
+}
+
+fn to_sprite_event(event: &str) -> Option<SpriteEvent> {
+    match event {
+        "CREATE" => Some(SpriteEvent::Create),
+        "DESTROY" => Some(SpriteEvent::Destroy),
+        "CANTMOVE" => Some(SpriteEvent::CantMove),
+        "ACTION" => Some(SpriteEvent::Action),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture;
+
+    fn build_ast() -> json::GameMap {
+        let source = fixture::source("", "[ > Player ] -> [ > Player ] again", "P.");
+        source::from_str(&source).unwrap()
+    }
+
+    fn error(ast: json::GameMap) -> ParseError {
+        match build(ast) {
+            Ok(_) => panic!("Expected the game to not load"),
+            Err(e) => *e.downcast::<ParseError>().unwrap(),
+        }
+    }
+
+    #[test]
+    fn builds_the_game() {
+        let game = build(build_ast()).unwrap();
+        assert_eq!(game.levels.len(), 1);
+        assert_eq!(game.rules.len(), 1);
+    }
+
+    #[test]
+    fn names_the_missing_tile() {
+        let mut ast = build_ast();
+        let level_tile = match &mut ast.levels[0] {
+            json::Level::Map { cells } => &mut cells[0][0],
+            json::Level::Message { .. } => panic!("Expected a map"),
+        };
+        *level_tile = String::from("tile-404");
+        let e = error(ast);
+        assert_eq!(
+            e,
+            ParseError::MissingTile(Reference {
+                id: String::from("tile-404"),
+                section: String::from("levels[0]"),
+            })
+        );
+        assert_eq!(
+            e.to_string(),
+            "unknown tile 'tile-404' (referenced from levels[0])"
+        );
+    }
+
+    #[test]
+    fn names_the_missing_bracket_and_color() {
+        let mut ast = build_ast();
+        ast.brackets.clear();
+        assert!(
+            matches!(error(ast), ParseError::MissingBracket(r) if r.section.starts_with("rule_definitions."))
+        );
+
+        let mut ast = build_ast();
+        ast.colors.clear();
+        assert!(
+            matches!(error(ast), ParseError::MissingColor(r) if r.section.starts_with("sprites."))
+        );
+
+        let mut ast = build_ast();
+        ast.colors
+            .insert(String::from("#nothex"), String::from("x"));
+        assert_eq!(
+            error(ast),
+            ParseError::InvalidColor(Reference {
+                id: String::from("#nothex"),
+                section: String::from("colors"),
+            })
+        );
+    }
+
+    #[test]
+    fn names_the_missing_rule_of_a_group() {
+        let mut ast = build_ast();
+        let rule = ast.rules[0].clone();
+        ast.rule_definitions.insert(
+            String::from("group-1"),
+            json::RuleDefinition::Group {
+                random: false,
+                rules: vec![rule, String::from("rule-404")],
+            },
+        );
+        ast.rules.push(String::from("group-1"));
+        assert_eq!(
+            error(ast),
+            ParseError::MissingRule(Reference {
+                id: String::from("rule-404"),
+                section: String::from("rule_definitions.group-1"),
+            })
+        );
+    }
+
+    #[test]
+    fn needs_a_player() {
+        let mut ast = build_ast();
+        for sprite in ast.sprites.values_mut() {
+            sprite.name = sprite.name.replace("Player", "Ghost");
+        }
+        for tile in ast.tiles.values_mut() {
+            match tile {
+                json::Tile::Or { name, .. }
+                | json::Tile::And { name, .. }
+                | json::Tile::Sprite { name, .. }
+                | json::Tile::Simple { name, .. } => *name = name.replace("Player", "Ghost"),
+            }
+        }
+        assert_eq!(error(ast), ParseError::NoPlayer);
+    }
 }
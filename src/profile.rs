@@ -0,0 +1,159 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+
+use fnv::FnvHashMap;
+use serde::Serialize;
+
+use crate::model::rule::Rule;
+use crate::observer::EngineObserver;
+
+/// What happened while evaluating a rule (or all the rules that come from one source line)
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RuleStats {
+    /// How many times the rule was evaluated
+    pub attempts: u64,
+    /// How many of those found a match for every bracket
+    pub matches: u64,
+    /// How many permutations of the matches were applied to the board
+    pub permutations: u64,
+    pub time: Duration,
+}
+
+impl RuleStats {
+    fn add(&mut self, other: &RuleStats) {
+        self.attempts += other.attempts;
+        self.matches += other.matches;
+        self.permutations += other.permutations;
+        self.time += other.time;
+    }
+}
+
+/// Collects `RuleStats` for every rule that the engine evaluates (see `--profile`).
+/// Pass it to `Engine::tick_with_observer()`. Rules are keyed by their source line so the
+/// rules that the compiler expands from one line (e.g. one per direction) are added up
+#[derive(Default, Debug)]
+pub struct Profiler {
+    rules: FnvHashMap<Option<usize>, RuleStats>,
+    started: Option<Instant>,
+}
+
+impl Profiler {
+    pub fn record(&mut self, source_line_num: Option<usize>, stats: &RuleStats) {
+        self.rules.entry(source_line_num).or_default().add(stats);
+    }
+
+    /// Returns what was recorded so far (slowest rules first) and starts over
+    pub fn take(&mut self) -> Profile {
+        let mut rules: Vec<RuleProfile> = std::mem::take(&mut self.rules)
+            .into_iter()
+            .map(|(source_line_num, stats)| RuleProfile {
+                line: source_line_num.map(|line| line + 1),
+                attempts: stats.attempts,
+                matches: stats.matches,
+                permutations: stats.permutations,
+                time_ms: stats.time.as_secs_f64() * 1000.0,
+            })
+            .collect();
+        rules.sort_by(|a, b| {
+            b.time_ms
+                .partial_cmp(&a.time_ms)
+                .unwrap_or(Ordering::Equal)
+                .then(a.line.cmp(&b.line))
+        });
+        Profile { rules }
+    }
+}
+
+impl EngineObserver for Profiler {
+    fn rule_started(&mut self, _rule: &Rule) {
+        self.started = Some(Instant::now());
+    }
+
+    fn rule_finished(&mut self, rule: &Rule, stats: &RuleStats) {
+        let mut stats = *stats;
+        if let Some(started) = self.started.take() {
+            stats.time = started.elapsed();
+        }
+        self.record(rule.source_line_num, &stats);
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RuleProfile {
+    /// The line in the game source (starting at 1). None for the rules that the engine
+    /// adds to move the player
+    pub line: Option<usize>,
+    pub attempts: u64,
+    pub matches: u64,
+    pub permutations: u64,
+    pub time_ms: f64,
+}
+
+/// The report that `--profile` prints (as a table) or writes (as JSON)
+#[derive(Serialize, Debug)]
+pub struct Profile {
+    pub rules: Vec<RuleProfile>,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: f64 = self.rules.iter().map(|r| r.time_ms).sum();
+        writeln!(
+            f,
+            "{:>6} {:>10} {:>10} {:>10} {:>10} {:>6}",
+            "line", "attempts", "matches", "perms", "time (ms)", "time"
+        )?;
+        for rule in &self.rules {
+            let line = match rule.line {
+                None => String::from("input"),
+                Some(line) => format!("#{}", line),
+            };
+            let percent = if total > 0.0 {
+                rule.time_ms * 100.0 / total
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "{:>6} {:>10} {:>10} {:>10} {:>10.2} {:>5.1}%",
+                line, rule.attempts, rule.matches, rule.permutations, rule.time_ms, percent
+            )?;
+        }
+        write!(f, "Total: {:.2}ms in {} rules", total, self.rules.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(attempts: u64, millis: u64) -> RuleStats {
+        RuleStats {
+            attempts,
+            matches: 1,
+            permutations: 2,
+            time: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn adds_up_the_rules_of_a_line() {
+        let mut profiler = Profiler::default();
+        assert!(profiler.take().rules.is_empty());
+
+        profiler.record(Some(3), &stats(1, 1));
+        profiler.record(Some(9), &stats(5, 4));
+        profiler.record(Some(3), &stats(2, 2));
+        profiler.record(None, &stats(1, 0));
+
+        let profile = profiler.take();
+        let lines: Vec<_> = profile.rules.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![Some(10), Some(4), None]);
+        assert_eq!(profile.rules[1].attempts, 3);
+        assert_eq!(profile.rules[1].permutations, 4);
+        assert!(profile.to_string().contains("#10"));
+        assert!(profiler.take().rules.is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+use std::cmp;
+use std::error::Error;
+use std::io::Write;
+
+use fnv::FnvHashMap;
+use gif::SetParameter;
+
+use crate::color::Rgb;
+use crate::engine::BoardOrMessage;
+use crate::engine::Engine;
+use crate::model::board::Board;
+use crate::model::game::GameData;
+use crate::model::util::Dimension;
+use crate::model::util::Position;
+use crate::verify;
+
+/// A picture of the board. Every pixel of a sprite becomes a `scale` x `scale` square
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u16,
+    pub height: u16,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    fn new(width: u16, height: u16, background: Rgb) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width as usize * height as usize],
+        }
+    }
+
+    pub fn get(&self, x: u16, y: u16) -> Rgb {
+        self.pixels[x as usize + y as usize * self.width as usize]
+    }
+
+    // Same blending as the terminal renderer
+    fn fill(&mut self, x: u16, y: u16, size: u16, color: Rgb) {
+        for y in y..y + size {
+            for x in x..x + size {
+                let i = x as usize + y as usize * self.width as usize;
+                if color.a != 0 {
+                    self.pixels[i] = color.on_top_of(&self.pixels[i]);
+                } else {
+                    self.pixels[i] = color;
+                }
+            }
+        }
+    }
+
+    // Grow the image (down and to the right) so every frame of a GIF is the same size
+    fn padded(&self, width: u16, height: u16) -> Image {
+        let mut ret = Image::new(width, height, Rgb::black());
+        for y in 0..cmp::min(height, self.height) {
+            for x in 0..cmp::min(width, self.width) {
+                ret.pixels[x as usize + y as usize * width as usize] = self.get(x, y);
+            }
+        }
+        ret
+    }
+
+    fn to_rgb_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|c| vec![c.r, c.g, c.b])
+            .collect()
+    }
+}
+
+/// Draws the whole board (ignoring flickscreen/zoomscreen)
+pub fn render_board(game_data: &GameData, board: &Board, scale: u16) -> Image {
+    render_window(game_data, board, Position::new(0, 0), board.size(), scale)
+}
+
+/// Draws what the player sees: only the current screen for flickscreen/zoomscreen games.
+/// Returns None when a message is showing instead of the board
+pub fn render_engine(engine: &Engine, scale: u16) -> Option<Image> {
+    if engine.pending_message.is_some() {
+        return None;
+    }
+    match &engine.current_level {
+        BoardOrMessage::Message(_) => None,
+        BoardOrMessage::Board(board) => {
+            let (top_left, size) = engine.screen_window(board.size());
+            Some(render_window(
+                &engine.game_data,
+                board,
+                top_left,
+                size,
+                scale,
+            ))
+        }
+    }
+}
+
+fn render_window(
+    game_data: &GameData,
+    board: &Board,
+    top_left: Position,
+    size: Dimension,
+    scale: u16,
+) -> Image {
+    let (sprite_width, sprite_height) = game_data.sprite_size();
+    let mut image = Image::new(
+        size.width * sprite_width * scale,
+        size.height * sprite_height * scale,
+        game_data
+            .metadata
+            .background_color
+            .unwrap_or_else(Rgb::black),
+    );
+
+    for pos in board.positions_iter() {
+        if pos.x < top_left.x
+            || pos.y < top_left.y
+            || pos.x >= top_left.x + size.width
+            || pos.y >= top_left.y + size.height
+        {
+            continue;
+        }
+
+        let mut sprites = board.get_sprite_states(&pos);
+        sprites.sort();
+
+        for sprite in sprites {
+            let sprite = game_data.lookup_sprite(sprite);
+            for (sprite_y, row) in sprite.pixels.iter().enumerate() {
+                for (sprite_x, pixel) in row.iter().enumerate() {
+                    if let Some(rgb) = pixel {
+                        let x = (pos.x - top_left.x) * sprite_width + sprite_x as u16;
+                        let y = (pos.y - top_left.y) * sprite_height + sprite_y as u16;
+                        image.fill(x * scale, y * scale, scale, *rgb);
+                    }
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Plays the keys (same format as the `*.test-replay.json` files) and draws a frame after every tick
+pub fn render_replay(engine: &mut Engine, keys: &str, scale: u16) -> Vec<Image> {
+    let mut frames: Vec<Image> = render_engine(engine, scale).into_iter().collect();
+    verify::replay_with(engine, keys, |engine, _| {
+        if let Some(image) = render_engine(engine, scale) {
+            frames.push(image);
+        }
+    });
+    frames
+}
+
+pub fn write_png<W: Write>(image: &Image, w: W) -> Result<(), Box<dyn Error>> {
+    let mut encoder = png::Encoder::new(w, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.to_rgb_bytes())?;
+    Ok(())
+}
+
+/// Writes an animated (looping) GIF. `delay` is the time between frames in hundredths of a second
+pub fn write_gif<W: Write>(frames: &[Image], delay: u16, w: W) -> Result<(), Box<dyn Error>> {
+    let width = frames.iter().map(|f| f.width).max().unwrap_or(1);
+    let height = frames.iter().map(|f| f.height).max().unwrap_or(1);
+    let frames: Vec<Image> = frames.iter().map(|f| f.padded(width, height)).collect();
+
+    // Most games use only a handful of colors so they fit in one palette without any dithering
+    let mut palette = FnvHashMap::default();
+    for c in frames.iter().flat_map(|f| &f.pixels) {
+        let len = palette.len();
+        palette.entry((c.r, c.g, c.b)).or_insert(len);
+    }
+    let use_palette = palette.len() <= 256;
+
+    let mut global_palette = vec![];
+    if use_palette {
+        global_palette = vec![0; palette.len() * 3];
+        for ((r, g, b), i) in &palette {
+            global_palette[i * 3] = *r;
+            global_palette[i * 3 + 1] = *g;
+            global_palette[i * 3 + 2] = *b;
+        }
+    }
+
+    let mut encoder = gif::Encoder::new(w, width, height, &global_palette)?;
+    encoder.set(gif::Repeat::Infinite)?;
+    for image in &frames {
+        let mut frame = if use_palette {
+            let pixels: Vec<u8> = image
+                .pixels
+                .iter()
+                .map(|c| palette[&(c.r, c.g, c.b)] as u8)
+                .collect();
+            gif::Frame::from_indexed_pixels(width, height, &pixels, None)
+        } else {
+            gif::Frame::from_rgb_speed(width, height, &image.to_rgb_bytes(), 10)
+        };
+        frame.delay = delay;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture;
+
+    fn build_engine() -> Engine {
+        Engine::new(fixture::game_with_prelude("flickscreen 2x1", "", "P..T"), 0)
+    }
+
+    #[test]
+    fn renders_the_visible_screen() {
+        let engine = build_engine();
+        let image = render_engine(&engine, 2).unwrap();
+        // flickscreen only shows 2 of the 4 cells. Each cell is 5x5 sprite pixels
+        assert_eq!((image.width, image.height), (2 * 5 * 2, 5 * 2));
+        assert_eq!(image.get(0, 0), Rgb::new(255, 255, 255));
+        assert_eq!(image.get(10, 0), Rgb::new(0, 0, 0));
+
+        let board = match &engine.current_level {
+            BoardOrMessage::Board(board) => board,
+            BoardOrMessage::Message(_) => unreachable!(),
+        };
+        let image = render_board(&engine.game_data, board, 1);
+        assert_eq!((image.width, image.height), (4 * 5, 5));
+    }
+
+    #[test]
+    fn renders_a_frame_per_tick() {
+        let mut engine = build_engine();
+        let frames = render_replay(&mut engine, "DDD", 1);
+        assert_eq!(frames.len(), 4);
+        // The player moved to the next screen
+        assert_eq!(frames[2].get(0, 0), Rgb::new(255, 255, 255));
+
+        let mut gif = vec![];
+        write_gif(&frames, 10, &mut gif).unwrap();
+        assert_eq!(&gif[0..6], b"GIF89a");
+
+        let mut png = vec![];
+        write_png(&frames[0], &mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
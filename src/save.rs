@@ -1,32 +1,214 @@
+use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
 
 use std::error::Error;
+use std::fs;
 use std::fs::File;
 use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
 
 use log::debug;
 
+/// The version of the save files that `write_to_file()` writes. Older versions are migrated when they are read
+pub const VERSION: u8 = 2;
+
+/// The progress in a game: the current level, its checkpoint and the inputs played so far
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SaveState {
     pub version: u8,
+    // test_solutions.bash reads these so they stay in the same place as in version 1
     pub inputs: Vec<String>,
     pub level: u8,
     pub checkpoint: Option<Vec<Vec<String>>>,
+    #[serde(default)]
+    pub rng: Option<XorShiftRng>,
+    #[serde(default)]
+    pub levels: Vec<LevelProgress>,
+    /// The board of the current level when the game was quit (sprite names per cell, like
+    /// `checkpoint`). None when the level continues from its checkpoint or its start
+    #[serde(default)]
+    pub board: Option<Vec<Vec<String>>>,
+    /// The undo steps of the current level (oldest first). Each step lists the sprite names
+    /// of the cells it changes, keyed by the index of the cell. The newest one applies to `board`
+    /// (or to the checkpoint when there is no `board`)
+    #[serde(default)]
+    pub undo: Vec<Vec<(usize, Vec<String>)>>,
+}
+
+/// How far the player got in one level
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct LevelProgress {
+    pub completed: bool,
+    /// Keys pressed in the level (not counting ticks or checkpoints)
+    pub moves: u32,
+    /// The winning inputs with the fewest moves
+    pub best_solution: Option<String>,
+}
+
+// Version 1 only had the inputs, the level and its checkpoint
+#[derive(Deserialize)]
+struct SaveStateV1 {
+    inputs: Vec<String>,
+    level: u8,
+    checkpoint: Option<Vec<Vec<String>>>,
+    #[serde(default)]
+    rng: Option<XorShiftRng>,
+}
+
+impl From<SaveStateV1> for SaveState {
+    fn from(v1: SaveStateV1) -> Self {
+        // Every level before the current one was solved unless it was skipped with --level
+        let levels = v1
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(index, inputs)| {
+                let completed = index < v1.level as usize && !inputs.is_empty();
+                LevelProgress {
+                    completed,
+                    moves: count_moves(inputs),
+                    best_solution: if completed {
+                        Some(inputs.clone())
+                    } else {
+                        None
+                    },
+                }
+            })
+            .collect();
+        Self {
+            version: VERSION,
+            inputs: v1.inputs,
+            level: v1.level,
+            checkpoint: v1.checkpoint,
+            rng: v1.rng,
+            levels,
+            board: None,
+            undo: vec![],
+        }
+    }
+}
+
+/// The number of keys that were pressed (`.` and `,` are ticks and `#` is a checkpoint)
+pub fn count_moves(inputs: &str) -> u32 {
+    inputs.chars().filter(|c| !".,#".contains(*c)).count() as u32
+}
+
+impl LevelProgress {
+    /// Marks the level as completed and keeps `solution` if it is shorter than the best one so far
+    pub fn record_win(&mut self, solution: &str) {
+        self.completed = true;
+        let is_better = self
+            .best_solution
+            .as_ref()
+            .is_none_or(|best| count_moves(solution) < count_moves(best));
+        if is_better {
+            self.best_solution = Some(solution.to_string());
+        }
+    }
 }
 
 impl SaveState {
+    /// Fails (instead of panicking) when the file is not a save file so the caller can start over
     pub fn read_from_file(path: &str) -> Result<Self, Box<dyn Error>> {
         debug!("Reading {:?}", path);
 
         // Open the file in read-only mode with buffer.
         let f = File::open(path)?;
-        let r = BufReader::new(f);
-        Ok(serde_json::from_reader(r).unwrap())
+        Self::read(BufReader::new(f))
+            .map_err(|e| format!("Invalid save file {}: {}", path, e).into())
     }
 
+    pub fn read<R: Read>(r: R) -> Result<Self, Box<dyn Error>> {
+        let json: serde_json::Value = serde_json::from_reader(r)?;
+        match json.get("version").and_then(|v| v.as_u64()) {
+            Some(1) => Ok(serde_json::from_value::<SaveStateV1>(json)?.into()),
+            Some(version) if version == VERSION as u64 => Ok(serde_json::from_value(json)?),
+            Some(version) => Err(format!("unsupported version {}", version).into()),
+            None => Err("missing version".into()),
+        }
+    }
+
+    /// Writes a temporary file next to `path` and then renames it so an interrupted write
+    /// never leaves a half-written save behind
     pub fn write_to_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
         debug!("Writing {:?}", path);
-        let f = File::create(path)?;
-        Ok(serde_json::to_writer(f, &self).unwrap())
+        let tmp_path = format!("{}.tmp", path);
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut w, &self)?;
+        w.flush()?;
+        w.get_ref().sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_version_1() {
+        let v1 = r#"{"version":1,"inputs":["","DD#S","W"],"level":2,"checkpoint":null}"#;
+        let save = SaveState::read(v1.as_bytes()).unwrap();
+        assert_eq!(save.version, VERSION);
+        assert_eq!(save.inputs, vec!["", "DD#S", "W"]);
+        assert_eq!(save.level, 2);
+        assert!(!save.levels[0].completed);
+        assert_eq!(
+            save.levels[1],
+            LevelProgress {
+                completed: true,
+                moves: 3,
+                best_solution: Some(String::from("DD#S")),
+            }
+        );
+        assert!(!save.levels[2].completed);
+        assert_eq!(save.board, None);
+        assert!(save.undo.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(SaveState::read("{\"version\":1,".as_bytes()).is_err());
+        assert!(SaveState::read("{\"version\":9,\"inputs\":[]}".as_bytes()).is_err());
+        assert!(SaveState::read("[]".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn writes_and_reads_version_2() {
+        let mut level = LevelProgress::default();
+        level.record_win("DDSS.");
+        level.record_win("DDDSSS");
+        level.record_win("DS,,");
+        let save = SaveState {
+            version: VERSION,
+            inputs: vec![String::from("DS,,")],
+            level: 0,
+            checkpoint: None,
+            rng: None,
+            levels: vec![level],
+            board: Some(vec![vec![
+                String::from("Background"),
+                String::from("Player"),
+            ]]),
+            undo: vec![vec![(3, vec![String::from("Player")])]],
+        };
+        assert_eq!(save.levels[0].best_solution, Some(String::from("DS,,")));
+
+        let dir = std::env::temp_dir().join(format!("puzzlescript-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.save.json").to_string_lossy().to_string();
+        save.write_to_file(&path).unwrap();
+        let read = SaveState::read_from_file(&path).unwrap();
+        assert_eq!(read.levels, save.levels);
+        assert_eq!(read.board, save.board);
+        assert_eq!(read.undo, save.undo);
+        assert!(!dir.join("game.save.json.tmp").exists());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(SaveState::read_from_file(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,734 @@
+// A port of the sfxr synthesizer (https://www.drpetter.se/project_sfxr.html) that PuzzleScript
+// uses for its sounds. In a game a sound is just a number (e.g. `sfx0 83842103`): the last two
+// digits pick one of the presets below and the rest seeds the random number generator that
+// tweaks the preset.
+use std::f64::consts::PI;
+use std::io;
+use std::io::Write;
+
+use rand::Rng;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// sfxr has no other way to stop a sound so cap how long one can be
+const MAX_SAMPLES: usize = 10 * SAMPLE_RATE as usize;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WaveType {
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+    Triangle,
+    Breaker,
+}
+
+const WAVE_TYPES: [WaveType; 6] = [
+    WaveType::Square,
+    WaveType::Sawtooth,
+    WaveType::Sine,
+    WaveType::Noise,
+    WaveType::Triangle,
+    WaveType::Breaker,
+];
+
+/// The knobs of the synthesizer. Most are between 0 and 1; the ones marked signed are between -1 and 1
+#[derive(Clone, PartialEq, Debug)]
+pub struct Params {
+    pub wave_type: WaveType,
+    pub env_attack: f64,
+    pub env_sustain: f64,
+    pub env_punch: f64,
+    pub env_decay: f64,
+    pub base_freq: f64,
+    pub freq_limit: f64,
+    /// signed
+    pub freq_ramp: f64,
+    /// signed
+    pub freq_dramp: f64,
+    pub vib_strength: f64,
+    pub vib_speed: f64,
+    /// signed
+    pub arp_mod: f64,
+    pub arp_speed: f64,
+    pub duty: f64,
+    /// signed
+    pub duty_ramp: f64,
+    pub repeat_speed: f64,
+    /// signed
+    pub pha_offset: f64,
+    /// signed
+    pub pha_ramp: f64,
+    pub lpf_freq: f64,
+    /// signed
+    pub lpf_ramp: f64,
+    pub lpf_resonance: f64,
+    pub hpf_freq: f64,
+    /// signed
+    pub hpf_ramp: f64,
+    pub sound_vol: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            wave_type: WaveType::Square,
+            env_attack: 0.0,
+            env_sustain: 0.3,
+            env_punch: 0.0,
+            env_decay: 0.4,
+            base_freq: 0.3,
+            freq_limit: 0.0,
+            freq_ramp: 0.0,
+            freq_dramp: 0.0,
+            vib_strength: 0.0,
+            vib_speed: 0.0,
+            arp_mod: 0.0,
+            arp_speed: 0.0,
+            duty: 0.0,
+            duty_ramp: 0.0,
+            repeat_speed: 0.0,
+            pha_offset: 0.0,
+            pha_ramp: 0.0,
+            lpf_freq: 1.0,
+            lpf_ramp: 0.0,
+            lpf_resonance: 0.0,
+            hpf_freq: 0.0,
+            hpf_ramp: 0.0,
+            sound_vol: 0.5,
+        }
+    }
+}
+
+// The random number generator PuzzleScript seeds its sounds with (RC4 seeded with the digits of the number)
+struct Rc4 {
+    s: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    fn new(seed: &str) -> Self {
+        let mut s = [0; 256];
+        for (i, v) in s.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        let input = seed.as_bytes();
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            j = j.wrapping_add(s[i]).wrapping_add(input[i % input.len()]);
+            s.swap(i, j as usize);
+        }
+        Self { s, i: 0, j: 0 }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        self.i = self.i.wrapping_add(1);
+        self.j = self.j.wrapping_add(self.s[self.i as usize]);
+        self.s.swap(self.i as usize, self.j as usize);
+        self.s[self.s[self.i as usize].wrapping_add(self.s[self.j as usize]) as usize]
+    }
+
+    // A number in [0, 1] made from 7 bytes (enough for the 53 bits of an f64)
+    fn uniform(&mut self) -> f64 {
+        let mut output = 0.0;
+        for _ in 0..7 {
+            output = output * 256.0 + self.next_byte() as f64;
+        }
+        output / (2f64.powi(56) - 1.0)
+    }
+
+    fn frnd(&mut self, range: f64) -> f64 {
+        range * self.uniform()
+    }
+
+    // An integer in [0, max]
+    fn rnd(&mut self, max: u32) -> u32 {
+        (self.uniform() * (max + 1) as f64).floor() as u32
+    }
+
+    fn chance(&mut self, max: u32) -> bool {
+        self.rnd(max) == 0
+    }
+
+    fn wave_type(&mut self) -> WaveType {
+        let i = self.frnd(WAVE_TYPES.len() as f64) as usize;
+        WAVE_TYPES[i.min(WAVE_TYPES.len() - 1)]
+    }
+}
+
+impl Params {
+    /// Builds the sound that a PuzzleScript game refers to by number
+    pub fn from_seed(seed: u32) -> Self {
+        let mut rng = Rc4::new(&(seed / 100).to_string());
+        match seed % 100 % 10 {
+            0 => pickup_coin(&mut rng),
+            1 => laser_shoot(&mut rng),
+            2 => explosion(&mut rng),
+            3 => power_up(&mut rng),
+            4 => hit_hurt(&mut rng),
+            5 => jump(&mut rng),
+            6 => blip_select(&mut rng),
+            7 => push_sound(&mut rng),
+            8 => random(&mut rng),
+            _ => bird_sound(&mut rng),
+        }
+    }
+
+    /// Renders the sound as mono samples (between -1 and 1) at `SAMPLE_RATE`
+    pub fn synthesize(&self) -> Vec<f32> {
+        Synth::new(self).run()
+    }
+}
+
+fn not_noise(wave_type: WaveType) -> WaveType {
+    if wave_type == WaveType::Noise {
+        WaveType::Square
+    } else {
+        wave_type
+    }
+}
+
+fn pickup_coin(rng: &mut Rc4) -> Params {
+    let mut p = Params {
+        wave_type: not_noise(rng.wave_type()),
+        base_freq: 0.4 + rng.frnd(0.5),
+        env_attack: 0.0,
+        env_sustain: rng.frnd(0.1),
+        env_decay: 0.1 + rng.frnd(0.4),
+        env_punch: 0.3 + rng.frnd(0.3),
+        ..Params::default()
+    };
+    if !rng.chance(1) {
+        p.arp_speed = 0.5 + rng.frnd(0.2);
+        let num = (rng.frnd(7.0) as u32 | 1) + 1;
+        let den = num + (rng.frnd(7.0) as u32 | 1) + 2;
+        p.arp_mod = num as f64 / den as f64;
+    }
+    p
+}
+
+fn laser_shoot(rng: &mut Rc4) -> Params {
+    // PuzzleScript picks a wave type the sfxr way and then overwrites it (but still uses up the numbers)
+    if rng.rnd(2) == 2 && !rng.chance(1) {
+        rng.rnd(1);
+    }
+    let mut p = Params {
+        wave_type: not_noise(rng.wave_type()),
+        base_freq: 0.5 + rng.frnd(0.5),
+        ..Params::default()
+    };
+    p.freq_limit = (p.base_freq - 0.2 - rng.frnd(0.6)).max(0.2);
+    p.freq_ramp = -0.15 - rng.frnd(0.2);
+    if rng.chance(2) {
+        p.base_freq = 0.3 + rng.frnd(0.6);
+        p.freq_limit = rng.frnd(0.1);
+        p.freq_ramp = -0.35 - rng.frnd(0.3);
+    }
+    if !rng.chance(1) {
+        p.duty = rng.frnd(0.5);
+        p.duty_ramp = rng.frnd(0.2);
+    } else {
+        p.duty = 0.4 + rng.frnd(0.5);
+        p.duty_ramp = -rng.frnd(0.7);
+    }
+    p.env_attack = 0.0;
+    p.env_sustain = 0.1 + rng.frnd(0.2);
+    p.env_decay = rng.frnd(0.4);
+    if !rng.chance(1) {
+        p.env_punch = rng.frnd(0.3);
+    }
+    if rng.chance(2) {
+        p.pha_offset = rng.frnd(0.2);
+        p.pha_ramp = -rng.frnd(0.2);
+    }
+    if !rng.chance(1) {
+        p.hpf_freq = rng.frnd(0.3);
+    }
+    p
+}
+
+fn explosion(rng: &mut Rc4) -> Params {
+    let mut p = Params {
+        wave_type: WaveType::Noise,
+        ..Params::default()
+    };
+    if !rng.chance(1) {
+        p.base_freq = 0.1 + rng.frnd(0.4);
+        p.freq_ramp = -0.1 + rng.frnd(0.4);
+    } else {
+        p.base_freq = 0.2 + rng.frnd(0.7);
+        p.freq_ramp = -0.2 - rng.frnd(0.2);
+    }
+    p.base_freq *= p.base_freq;
+    if rng.chance(4) {
+        p.freq_ramp = 0.0;
+    }
+    if rng.chance(2) {
+        p.repeat_speed = 0.3 + rng.frnd(0.5);
+    }
+    p.env_attack = 0.0;
+    p.env_sustain = 0.1 + rng.frnd(0.3);
+    p.env_decay = rng.frnd(0.5);
+    if rng.chance(1) {
+        p.pha_offset = -0.3 + rng.frnd(0.9);
+        p.pha_ramp = -rng.frnd(0.3);
+    }
+    p.env_punch = 0.2 + rng.frnd(0.6);
+    if !rng.chance(1) {
+        p.vib_strength = rng.frnd(0.7);
+        p.vib_speed = rng.frnd(0.6);
+    }
+    if rng.chance(2) {
+        p.arp_speed = 0.6 + rng.frnd(0.3);
+        p.arp_mod = 0.8 - rng.frnd(1.6);
+    }
+    p
+}
+
+fn power_up(rng: &mut Rc4) -> Params {
+    let mut p = Params::default();
+    if !rng.chance(1) {
+        p.wave_type = WaveType::Sawtooth;
+    } else {
+        p.duty = rng.frnd(0.6);
+    }
+    p.wave_type = not_noise(rng.wave_type());
+    if !rng.chance(1) {
+        p.base_freq = 0.2 + rng.frnd(0.3);
+        p.freq_ramp = 0.1 + rng.frnd(0.4);
+        p.repeat_speed = 0.4 + rng.frnd(0.4);
+    } else {
+        p.base_freq = 0.2 + rng.frnd(0.3);
+        p.freq_ramp = 0.05 + rng.frnd(0.2);
+        if !rng.chance(1) {
+            p.vib_strength = rng.frnd(0.7);
+            p.vib_speed = rng.frnd(0.6);
+        }
+    }
+    p.env_attack = 0.0;
+    p.env_sustain = rng.frnd(0.4);
+    p.env_decay = 0.1 + rng.frnd(0.4);
+    p
+}
+
+fn hit_hurt(rng: &mut Rc4) -> Params {
+    let mut p = Params::default();
+    if rng.rnd(2) == 0 {
+        p.duty = rng.frnd(0.6);
+    }
+    p.wave_type = rng.wave_type();
+    p.base_freq = 0.2 + rng.frnd(0.6);
+    p.freq_ramp = -0.3 - rng.frnd(0.4);
+    p.env_attack = 0.0;
+    p.env_sustain = rng.frnd(0.1);
+    p.env_decay = 0.1 + rng.frnd(0.2);
+    if !rng.chance(1) {
+        p.hpf_freq = rng.frnd(0.3);
+    }
+    p
+}
+
+fn jump(rng: &mut Rc4) -> Params {
+    let mut p = Params {
+        wave_type: not_noise(rng.wave_type()),
+        duty: rng.frnd(0.6),
+        base_freq: 0.3 + rng.frnd(0.3),
+        freq_ramp: 0.1 + rng.frnd(0.2),
+        env_attack: 0.0,
+        env_sustain: 0.1 + rng.frnd(0.3),
+        env_decay: 0.1 + rng.frnd(0.2),
+        ..Params::default()
+    };
+    if !rng.chance(1) {
+        p.hpf_freq = rng.frnd(0.3);
+    }
+    if !rng.chance(1) {
+        p.lpf_freq = 1.0 - rng.frnd(0.6);
+    }
+    p
+}
+
+fn blip_select(rng: &mut Rc4) -> Params {
+    let mut p = Params::default();
+    rng.rnd(1); // Overwritten like in laser_shoot()
+    p.wave_type = rng.wave_type();
+    if p.wave_type == WaveType::Noise {
+        p.wave_type = WAVE_TYPES[rng.rnd(1) as usize];
+    }
+    if p.wave_type == WaveType::Square {
+        p.duty = rng.frnd(0.6);
+    }
+    p.base_freq = 0.2 + rng.frnd(0.4);
+    p.env_attack = 0.0;
+    p.env_sustain = 0.1 + rng.frnd(0.1);
+    p.env_decay = rng.frnd(0.2);
+    p.hpf_freq = 0.1;
+    p
+}
+
+fn push_sound(rng: &mut Rc4) -> Params {
+    let wave_type = match rng.wave_type() {
+        WaveType::Sine => WaveType::Noise,
+        WaveType::Square => WaveType::Noise,
+        w => w,
+    };
+    Params {
+        wave_type,
+        base_freq: 0.1 + rng.frnd(0.4),
+        freq_ramp: 0.05 + rng.frnd(0.2),
+        env_attack: 0.01 + rng.frnd(0.09),
+        env_sustain: 0.01 + rng.frnd(0.09),
+        env_decay: 0.01 + rng.frnd(0.09),
+        repeat_speed: 0.3 + rng.frnd(0.5),
+        pha_offset: -0.3 + rng.frnd(0.9),
+        pha_ramp: -rng.frnd(0.3),
+        arp_speed: 0.6 + rng.frnd(0.3),
+        arp_mod: 0.8 - rng.frnd(1.6),
+        ..Params::default()
+    }
+}
+
+fn random(rng: &mut Rc4) -> Params {
+    let mut p = Params {
+        wave_type: rng.wave_type(),
+        ..Params::default()
+    };
+    p.base_freq = (rng.frnd(2.0) - 1.0).powi(2);
+    if !rng.chance(1) {
+        p.base_freq = (rng.frnd(2.0) - 1.0).powi(3) + 0.5;
+    }
+    p.freq_limit = 0.0;
+    p.freq_ramp = (rng.frnd(2.0) - 1.0).powi(5);
+    if p.base_freq > 0.7 && p.freq_ramp > 0.2 {
+        p.freq_ramp = -p.freq_ramp;
+    }
+    if p.base_freq < 0.2 && p.freq_ramp < -0.05 {
+        p.freq_ramp = -p.freq_ramp;
+    }
+    p.freq_dramp = (rng.frnd(2.0) - 1.0).powi(3);
+    p.duty = rng.frnd(2.0) - 1.0;
+    p.duty_ramp = (rng.frnd(2.0) - 1.0).powi(3);
+    p.vib_strength = (rng.frnd(2.0) - 1.0).powi(3);
+    p.vib_speed = rng.frnd(2.0) - 1.0;
+    p.env_attack = (rng.frnd(2.0) - 1.0).powi(3);
+    p.env_sustain = (rng.frnd(2.0) - 1.0).powi(2);
+    p.env_decay = rng.frnd(2.0) - 1.0;
+    p.env_punch = rng.frnd(0.8).powi(2);
+    if p.env_attack + p.env_sustain + p.env_decay < 0.2 {
+        p.env_sustain += 0.2 + rng.frnd(0.3);
+        p.env_decay += 0.2 + rng.frnd(0.3);
+    }
+    p.lpf_resonance = rng.frnd(2.0) - 1.0;
+    p.lpf_freq = 1.0 - rng.frnd(1.0).powi(3);
+    p.lpf_ramp = (rng.frnd(2.0) - 1.0).powi(3);
+    if p.lpf_freq < 0.1 && p.lpf_ramp < -0.05 {
+        p.lpf_ramp = -p.lpf_ramp;
+    }
+    p.hpf_freq = rng.frnd(1.0).powi(5);
+    p.hpf_ramp = (rng.frnd(2.0) - 1.0).powi(5);
+    p.pha_offset = (rng.frnd(2.0) - 1.0).powi(3);
+    p.pha_ramp = (rng.frnd(2.0) - 1.0).powi(3);
+    p.repeat_speed = rng.frnd(2.0) - 1.0;
+    p.arp_speed = rng.frnd(2.0) - 1.0;
+    p.arp_mod = rng.frnd(2.0) - 1.0;
+    p
+}
+
+// A short, high chirp that warbles
+fn bird_sound(rng: &mut Rc4) -> Params {
+    let mut p = Params {
+        wave_type: not_noise(rng.wave_type()),
+        env_attack: rng.frnd(0.2),
+        env_sustain: 0.1 + rng.frnd(0.2),
+        env_punch: rng.frnd(0.3),
+        env_decay: 0.1 + rng.frnd(0.2),
+        base_freq: 0.6 + rng.frnd(0.35),
+        freq_ramp: -0.3 + rng.frnd(0.2),
+        vib_strength: 0.2 + rng.frnd(0.4),
+        vib_speed: 0.6 + rng.frnd(0.3),
+        duty: rng.frnd(0.6),
+        ..Params::default()
+    };
+    if !rng.chance(1) {
+        p.freq_ramp = 0.1 + rng.frnd(0.15);
+    }
+    if !rng.chance(1) {
+        p.repeat_speed = 0.5 + rng.frnd(0.3);
+    }
+    p
+}
+
+// The state of the synthesizer while it renders one sound
+struct Synth<'a> {
+    p: &'a Params,
+    fperiod: f64,
+    fmaxperiod: f64,
+    fslide: f64,
+    fdslide: f64,
+    square_duty: f64,
+    square_slide: f64,
+    arp_mod: f64,
+    arp_time: usize,
+    arp_limit: usize,
+}
+
+impl<'a> Synth<'a> {
+    fn new(p: &'a Params) -> Self {
+        let mut synth = Self {
+            p,
+            fperiod: 0.0,
+            fmaxperiod: 0.0,
+            fslide: 0.0,
+            fdslide: 0.0,
+            square_duty: 0.0,
+            square_slide: 0.0,
+            arp_mod: 0.0,
+            arp_time: 0,
+            arp_limit: 0,
+        };
+        synth.reset();
+        synth
+    }
+
+    // Called at the start and every time the sound repeats
+    fn reset(&mut self) {
+        let p = self.p;
+        self.fperiod = 100.0 / (p.base_freq * p.base_freq + 0.001);
+        self.fmaxperiod = 100.0 / (p.freq_limit * p.freq_limit + 0.001);
+        self.fslide = 1.0 - p.freq_ramp.powi(3) * 0.01;
+        self.fdslide = -p.freq_dramp.powi(3) * 0.000_001;
+        self.square_duty = 0.5 - p.duty * 0.5;
+        self.square_slide = -p.duty_ramp * 0.000_05;
+        self.arp_mod = if p.arp_mod >= 0.0 {
+            1.0 - p.arp_mod.powi(2) * 0.9
+        } else {
+            1.0 + p.arp_mod.powi(2) * 10.0
+        };
+        self.arp_time = 0;
+        self.arp_limit = ((1.0 - p.arp_speed).powi(2) * 20000.0 + 32.0) as usize;
+        if p.arp_speed == 1.0 {
+            self.arp_limit = 0;
+        }
+    }
+
+    fn run(&mut self) -> Vec<f32> {
+        let p = self.p;
+        // The noise only needs to sound random, not be random, so the same seed always sounds the same
+        let mut rng = XorShiftRng::seed_from_u64(0);
+
+        // Filters
+        let mut fltp = 0.0;
+        let mut fltdp = 0.0;
+        let mut fltw = p.lpf_freq.powi(3) * 0.1;
+        let fltw_d = 1.0 + p.lpf_ramp * 0.0001;
+        let fltdmp = (5.0 / (1.0 + p.lpf_resonance.powi(2) * 20.0) * (0.01 + fltw)).min(0.8);
+        let mut fltphp = 0.0;
+        let mut flthp = p.hpf_freq.powi(2) * 0.1;
+        let flthp_d = 1.0 + p.hpf_ramp * 0.0003;
+
+        // Vibrato
+        let mut vib_phase = 0.0;
+        let vib_speed = p.vib_speed.powi(2) * 0.01;
+        let vib_amp = p.vib_strength * 0.5;
+
+        // Envelope
+        let env_length = [
+            ((p.env_attack * p.env_attack * 100_000.0) as usize).max(1),
+            ((p.env_sustain * p.env_sustain * 100_000.0) as usize).max(1),
+            ((p.env_decay * p.env_decay * 100_000.0) as usize).max(1),
+        ];
+        let mut env_stage = 0;
+        let mut env_time = 0;
+
+        // Phaser
+        let mut fphase = p.pha_offset.powi(2) * 1020.0 * p.pha_offset.signum();
+        let fdphase = p.pha_ramp.powi(2) * p.pha_ramp.signum();
+        let mut ipp = 0;
+        let mut phaser_buffer = [0.0; 1024];
+
+        let mut noise_buffer = [0.0; 32];
+        for n in noise_buffer.iter_mut() {
+            *n = rng.gen_range(-1.0, 1.0);
+        }
+
+        // Repeat
+        let mut rep_time = 0;
+        let rep_limit = if p.repeat_speed == 0.0 {
+            0
+        } else {
+            ((1.0 - p.repeat_speed).powi(2) * 20000.0 + 32.0) as usize
+        };
+
+        let gain = p.sound_vol.exp() - 1.0;
+        let mut phase = 0;
+        let mut samples = vec![];
+
+        while samples.len() < MAX_SAMPLES {
+            rep_time += 1;
+            if rep_limit != 0 && rep_time >= rep_limit {
+                rep_time = 0;
+                self.reset();
+            }
+
+            // Arpeggio (a single jump in pitch)
+            self.arp_time += 1;
+            if self.arp_limit != 0 && self.arp_time >= self.arp_limit {
+                self.arp_limit = 0;
+                self.fperiod *= self.arp_mod;
+            }
+
+            // Frequency slide, and the slide of the slide
+            self.fslide += self.fdslide;
+            self.fperiod *= self.fslide;
+            if self.fperiod > self.fmaxperiod {
+                self.fperiod = self.fmaxperiod;
+                if p.freq_limit > 0.0 {
+                    break;
+                }
+            }
+
+            let mut rfperiod = self.fperiod;
+            if vib_amp > 0.0 {
+                vib_phase += vib_speed;
+                rfperiod = self.fperiod * (1.0 + f64::sin(vib_phase) * vib_amp);
+            }
+            let period = (rfperiod as usize).max(8);
+
+            self.square_duty = (self.square_duty + self.square_slide).clamp(0.0, 0.5);
+
+            // Volume envelope
+            env_time += 1;
+            if env_time > env_length[env_stage] {
+                env_time = 0;
+                env_stage += 1;
+                if env_stage == 3 {
+                    break;
+                }
+            }
+            let env_vol = match env_stage {
+                0 => env_time as f64 / env_length[0] as f64,
+                1 => 1.0 + (1.0 - env_time as f64 / env_length[1] as f64) * 2.0 * p.env_punch,
+                _ => 1.0 - env_time as f64 / env_length[2] as f64,
+            };
+
+            fphase += fdphase;
+            let iphase = (fphase.floor().abs() as usize).min(1023);
+
+            flthp = (flthp * flthp_d).clamp(0.000_01, 0.1);
+
+            // 8x supersampling
+            let mut sample = 0.0;
+            for _ in 0..8 {
+                phase += 1;
+                if phase >= period {
+                    phase %= period;
+                    if p.wave_type == WaveType::Noise {
+                        for n in noise_buffer.iter_mut() {
+                            *n = rng.gen_range(-1.0, 1.0);
+                        }
+                    }
+                }
+
+                let fp = phase as f64 / period as f64;
+                let mut sub_sample = match p.wave_type {
+                    WaveType::Square => {
+                        if fp < self.square_duty {
+                            0.5
+                        } else {
+                            -0.5
+                        }
+                    }
+                    WaveType::Sawtooth => 1.0 - fp * 2.0,
+                    WaveType::Sine => f64::sin(fp * 2.0 * PI),
+                    WaveType::Noise => noise_buffer[phase * 32 / period],
+                    WaveType::Triangle => (1.0 - fp * 2.0).abs() - 1.0,
+                    WaveType::Breaker => (1.0 - fp * fp * 2.0).abs() - 1.0,
+                };
+
+                // Low-pass filter
+                let pp = fltp;
+                fltw = (fltw * fltw_d).clamp(0.0, 0.1);
+                if p.lpf_freq != 1.0 {
+                    fltdp += (sub_sample - fltp) * fltw;
+                    fltdp -= fltdp * fltdmp;
+                } else {
+                    fltp = sub_sample;
+                    fltdp = 0.0;
+                }
+                fltp += fltdp;
+
+                // High-pass filter
+                fltphp += fltp - pp;
+                fltphp -= fltphp * flthp;
+                sub_sample = fltphp;
+
+                // Phaser
+                phaser_buffer[ipp & 1023] = sub_sample;
+                sub_sample += phaser_buffer[(ipp + 1024 - iphase) & 1023];
+                ipp = (ipp + 1) & 1023;
+
+                sample += sub_sample * env_vol;
+            }
+
+            let sample = sample / 8.0 * gain;
+            samples.push(sample.clamp(-1.0, 1.0) as f32);
+        }
+        samples
+    }
+}
+
+/// Writes the samples as a 16-bit mono WAV file
+pub fn write_wav<W: Write>(samples: &[f32], mut w: W) -> io::Result<()> {
+    let data_len = samples.len() as u32 * 2;
+    w.write_all(b"RIFF")?;
+    w.write_all(&(36 + data_len).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&1u16.to_le_bytes())?; // PCM
+    w.write_all(&1u16.to_le_bytes())?; // mono
+    w.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    w.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // bytes per second
+    w.write_all(&2u16.to_le_bytes())?; // bytes per sample
+    w.write_all(&16u16.to_le_bytes())?; // bits per sample
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())?;
+    for s in samples {
+        w.write_all(&((s * i16::MAX as f32) as i16).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_last_digits_pick_the_preset() {
+        // 83842103 is a power up and 38183900 is a coin
+        assert_eq!(Params::from_seed(83842103), Params::from_seed(83842103));
+        assert_eq!(Params::from_seed(38183900).env_attack, 0.0);
+        assert_eq!(Params::from_seed(12302).wave_type, WaveType::Noise);
+        assert_ne!(Params::from_seed(83842103), Params::from_seed(83842203));
+    }
+
+    #[test]
+    fn synthesizes_a_sound() {
+        let samples = Params::from_seed(83842103).synthesize();
+        assert!(!samples.is_empty());
+        assert!(samples.len() < MAX_SAMPLES);
+        assert!(samples.iter().all(|s| *s >= -1.0 && *s <= 1.0));
+        assert!(samples.iter().any(|s| *s != 0.0));
+        // The same seed always sounds the same (even the noise)
+        assert_eq!(samples, Params::from_seed(83842103).synthesize());
+
+        let mut wav = vec![];
+        write_wav(&samples, &mut wav).unwrap();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(wav.len(), 44 + samples.len() * 2);
+    }
+}
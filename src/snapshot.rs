@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use log::debug;
+
+use crate::engine::BoardOrMessage;
+use crate::engine::Engine;
+use crate::model::board::Board;
+use crate::model::game::GameData;
+use crate::model::util::Position;
+use crate::verify;
+
+// Starts the heading line of every snapshot in a golden file. Sprite names cannot contain `=`
+const HEADING: &str = "== level ";
+
+/// The sprites on the board as text, one line per row. Each cell is the names of its sprites
+/// (from `Board::get_sprite_states()`, in collision layer order) joined with a `+`, and the
+/// cells of a row are separated by a space
+pub fn board_to_text(game: &GameData, board: &Board) -> String {
+    let size = board.size();
+    let mut text = String::new();
+    for y in 0..size.height {
+        let row: Vec<String> = (0..size.width)
+            .map(|x| {
+                let mut sprites = board.get_sprite_states(&Position::new(x, y));
+                sprites.sort();
+                let names: Vec<&str> = sprites
+                    .into_iter()
+                    .map(|sprite| game.lookup_sprite(sprite).name.as_str())
+                    .collect();
+                names.join("+")
+            })
+            .collect();
+        text.push_str(&row.join(" "));
+        text.push('\n');
+    }
+    text
+}
+
+/// A board of a replayed level, taken after a checkpoint or when the level was completed
+#[derive(Debug, PartialEq, Clone)]
+pub struct Snapshot {
+    /// `checkpoint 1`, `checkpoint 2`, ... or `end`
+    pub label: String,
+    /// See `board_to_text()`
+    pub board: String,
+}
+
+/// Replays the keys (same format as the `*.test-replay.json` files) on the level that `engine`
+/// is at and takes a snapshot after every checkpoint and when the level is completed.
+/// Returns true if the level was completed
+pub fn record(engine: &mut Engine, keys: &str) -> (bool, Vec<Snapshot>) {
+    let mut snapshots = vec![];
+    let mut checkpoints = 0;
+    let mut done = false;
+    let did_win = verify::replay_with(engine, keys, |engine, tr| {
+        if done {
+            return;
+        }
+        let label = if tr.completed_level.is_some() {
+            done = true;
+            String::from("end")
+        } else if tr.checkpoint.is_some() {
+            checkpoints += 1;
+            format!("checkpoint {}", checkpoints)
+        } else {
+            return;
+        };
+        if let BoardOrMessage::Board(board) = &engine.current_level {
+            snapshots.push(Snapshot {
+                label,
+                board: board_to_text(&engine.game_data, board),
+            });
+        }
+    });
+    (did_win, snapshots)
+}
+
+/// Says where the two lists of snapshots of a level first differ (None when they are the same)
+pub fn compare(expected: &[Snapshot], actual: &[Snapshot]) -> Option<String> {
+    for (e, a) in expected.iter().zip(actual) {
+        if e.label != a.label {
+            return Some(format!(
+                "expected a snapshot at {} but got {}",
+                e.label, a.label
+            ));
+        }
+        if e.board != a.board {
+            return Some(format!(
+                "the board at {} differs from the snapshot",
+                e.label
+            ));
+        }
+    }
+    if expected.len() != actual.len() {
+        return Some(format!(
+            "expected {} snapshots but got {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    None
+}
+
+/// The golden snapshots of the levels of a game. They are stored next to the game in a
+/// `*.test-snapshots.txt` file (see `verify --update-snapshots`)
+#[derive(Debug, Default, PartialEq)]
+pub struct Snapshots {
+    pub levels: BTreeMap<usize, Vec<Snapshot>>,
+}
+
+impl Snapshots {
+    /// The golden file of the `*.parsed.json` game at `game_path`
+    pub fn path_for(game_path: &str) -> String {
+        format!("{}.test-snapshots.txt", game_path)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut levels: BTreeMap<usize, Vec<Snapshot>> = BTreeMap::new();
+        let mut current: Option<(usize, Snapshot)> = None;
+        for (num, line) in text.lines().enumerate() {
+            if let Some(heading) = line.strip_prefix(HEADING) {
+                let mut parts = heading.splitn(2, ' ');
+                let index = parts
+                    .next()
+                    .and_then(|index| index.parse().ok())
+                    .ok_or_else(|| format!("line {}: expected a level number", num + 1))?;
+                let label = parts.next().unwrap_or("").to_string();
+                if let Some((index, snapshot)) = current.take() {
+                    levels.entry(index).or_default().push(snapshot);
+                }
+                current = Some((
+                    index,
+                    Snapshot {
+                        label,
+                        board: String::new(),
+                    },
+                ));
+            } else if line.is_empty() {
+                continue;
+            } else {
+                match &mut current {
+                    Some((_, snapshot)) => {
+                        snapshot.board.push_str(line);
+                        snapshot.board.push('\n');
+                    }
+                    None => return Err(format!("line {}: expected a heading", num + 1).into()),
+                }
+            }
+        }
+        if let Some((index, snapshot)) = current {
+            levels.entry(index).or_default().push(snapshot);
+        }
+        Ok(Self { levels })
+    }
+
+    pub fn read_from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        debug!("Reading {:?}", path);
+        Self::parse(&fs::read_to_string(path)?)
+            .map_err(|e| format!("Invalid snapshot file {}: {}", path, e).into())
+    }
+
+    /// Writes a temporary file next to `path` and then renames it (like `SaveState::write_to_file()`)
+    pub fn write_to_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        debug!("Writing {:?}", path);
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, self.to_string())?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Snapshots {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, snapshots) in &self.levels {
+            for snapshot in snapshots {
+                writeln!(f, "{}{} {}", HEADING, index, snapshot.label)?;
+                writeln!(f, "{}", snapshot.board)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture;
+
+    fn build_engine() -> Engine {
+        fixture::engine("late [ Player Flag ] -> [ Player ] checkpoint", "P.F.T")
+    }
+
+    #[test]
+    fn records_checkpoints_and_the_end() {
+        let (did_win, snapshots) = record(&mut build_engine(), "DDDD");
+        assert!(did_win);
+        let labels: Vec<_> = snapshots.iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, vec!["checkpoint 1", "end"]);
+        assert_eq!(
+            snapshots[0].board,
+            "Background Background Background+Player Background Background+Target\n"
+        );
+        assert_eq!(
+            snapshots[1].board,
+            "Background Background Background Background Background+Target+Player\n"
+        );
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let (_, snapshots) = record(&mut build_engine(), "DDDD");
+        let mut golden = Snapshots::default();
+        golden.levels.insert(0, snapshots.clone());
+        golden.levels.insert(3, snapshots[1..].to_vec());
+        let text = golden.to_string();
+        assert!(text.starts_with("== level 0 checkpoint 1\nBackground "));
+        assert_eq!(Snapshots::parse(&text).unwrap(), golden);
+        assert!(Snapshots::parse("Background\n").is_err());
+    }
+
+    #[test]
+    fn finds_the_first_difference() {
+        let (_, expected) = record(&mut build_engine(), "DDDD");
+        assert_eq!(compare(&expected, &expected), None);
+
+        let (_, actual) = record(&mut build_engine(), "DD");
+        assert_eq!(
+            compare(&expected, &actual),
+            Some(String::from("expected 2 snapshots but got 1"))
+        );
+
+        let mut actual = expected.clone();
+        actual[1].board = String::from("Background\n");
+        assert_eq!(
+            compare(&expected, &actual),
+            Some(String::from("the board at end differs from the snapshot"))
+        );
+    }
+}
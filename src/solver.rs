@@ -0,0 +1,362 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BinaryHeap;
+use std::hash::{Hash, Hasher};
+use std::time;
+
+use fnv::FnvHashMap;
+use fnv::FnvHashSet;
+use log::debug;
+use rand_xorshift::XorShiftRng;
+
+use crate::engine::new_rng;
+use crate::model::board::Board;
+use crate::model::game::GameData;
+use crate::model::game::Input;
+use crate::model::game::Level;
+use crate::model::game::WinCondition;
+use crate::model::game::WinConditionOnQualifier;
+use crate::model::tile::Tile;
+use crate::model::util::Position;
+
+// Stop following AGAIN commands after this many ticks (some games loop forever)
+const MAX_AGAIN_TICKS: usize = 100;
+
+/// When to give up searching. `None` means there is no limit
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The most inputs a solution can have
+    pub max_depth: Option<usize>,
+    /// The most boards to visit
+    pub max_nodes: Option<usize>,
+    pub timeout: Option<time::Duration>,
+}
+
+/// The order in which `solve()` visits the boards
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// Visits the boards in the order they were reached, so the solution is one of the shortest
+    BreadthFirst,
+    /// A*: visits the boards that look closest to meeting the win conditions first (see
+    /// `distance_to_win()`). It usually visits far fewer boards but the estimate can be too high
+    /// (rules may move things further than one cell per turn) so the solution may be longer
+    AStar,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SolveResult {
+    /// The keys to press, in the same format as the `*.test-replay.json` files
+    Solved(String),
+    /// Every reachable board was visited
+    Unsolvable,
+    /// One of the `Limits` was reached
+    GaveUp,
+}
+
+#[derive(Debug)]
+pub struct Solution {
+    pub result: SolveResult,
+    pub nodes: usize,
+    pub elapsed: time::Duration,
+}
+
+// How a board was reached during the search
+struct Node {
+    parent: Option<usize>,
+    keys: String,
+}
+
+/// Searches for a sequence of inputs that completes a level.
+/// Replay the result with `--level {level_num} --scripted`
+pub fn solve(game_data: &GameData, level_num: u8, strategy: Strategy, limits: &Limits) -> Solution {
+    let start = time::Instant::now();
+    let done = |result, nodes| Solution {
+        result,
+        nodes,
+        elapsed: start.elapsed(),
+    };
+
+    let board = match &game_data.levels[level_num as usize] {
+        Level::Message(_) => return done(SolveResult::Solved(String::from("X")), 0),
+        level => game_data.to_board(level),
+    };
+
+    let mut inputs = vec![
+        Some(Input::Up),
+        Some(Input::Down),
+        Some(Input::Left),
+        Some(Input::Right),
+    ];
+    if !game_data.metadata.no_action {
+        inputs.push(Some(Input::Action));
+    }
+    // Realtime games change even when no key is pressed
+    if game_data.metadata.realtime_interval.is_some() {
+        inputs.push(None);
+    }
+
+    let mut nodes = vec![Node {
+        parent: None,
+        keys: String::new(),
+    }];
+    // A* prefers the board that is closer to winning when two are estimated to be as good.
+    // Boards with the same priority are visited in the order they were reached
+    let priority = |depth: usize, board: &Board| match strategy {
+        Strategy::BreadthFirst => (depth, 0),
+        Strategy::AStar => {
+            let distance = distance_to_win(game_data, board);
+            (depth + distance, distance)
+        }
+    };
+    let mut seen = FnvHashSet::default();
+    seen.insert(fingerprint(&board));
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((priority(0, &board), 0)));
+    let mut unvisited = FnvHashMap::default();
+    unvisited.insert(0, (0, board, new_rng()));
+    let mut pruned = false;
+
+    while let Some(Reverse((_, index))) = queue.pop() {
+        let (depth, board, rng) = unvisited.remove(&index).unwrap();
+        if limits.max_nodes.is_some_and(|max| nodes.len() >= max)
+            || limits.timeout.is_some_and(|max| start.elapsed() >= max)
+        {
+            return done(SolveResult::GaveUp, nodes.len());
+        }
+        if limits.max_depth.is_some_and(|max| depth >= max) {
+            pruned = true;
+            continue;
+        }
+
+        for input in &inputs {
+            let mut rng = rng.clone();
+            if let Some((next, keys, won)) = step(game_data, &board, &mut rng, *input) {
+                if won || seen.insert(fingerprint(&next)) {
+                    nodes.push(Node {
+                        parent: Some(index),
+                        keys,
+                    });
+                    if won {
+                        debug!("Solved after visiting {} boards", nodes.len());
+                        let solution = keys_to(&nodes, nodes.len() - 1);
+                        return done(SolveResult::Solved(solution), nodes.len());
+                    }
+                    let index = nodes.len() - 1;
+                    queue.push(Reverse((priority(depth + 1, &next), index)));
+                    unvisited.insert(index, (depth + 1, next, rng));
+                }
+            }
+        }
+    }
+
+    if pruned {
+        done(SolveResult::GaveUp, nodes.len())
+    } else {
+        done(SolveResult::Unsolvable, nodes.len())
+    }
+}
+
+// Plays one input the same way Engine::tick() does, followed by any AGAIN ticks.
+// Returns the new board, the keys that were pressed, and whether the level was won.
+// Returns None when the input has no effect.
+fn step(
+    game_data: &GameData,
+    board: &Board,
+    rng: &mut XorShiftRng,
+    input: Option<Input>,
+) -> Option<(Board, String, bool)> {
+    let mut keys = String::new();
+    keys.push(to_key(input));
+
+    let mut new = board.clone();
+    if let Some(input) = input {
+        game_data.evaluate_player_input(rng, &mut new, input, &mut ());
+    }
+    let mut t = game_data.evaluate(rng, &mut new, &mut ());
+
+    let is_direction = match input {
+        Some(Input::Up) | Some(Input::Down) | Some(Input::Left) | Some(Input::Right) => true,
+        Some(Input::Action) | None => false,
+    };
+    if t.cancel || t.restart || (is_direction && game_data.player_failed_to_move(board, &new)) {
+        return None;
+    }
+    if t.win {
+        return Some((new, keys, true));
+    }
+
+    // A message stops the AGAIN ticks (the engine waits for the message to be dismissed)
+    let mut again_ticks = 0;
+    while t.again && t.message.is_none() && again_ticks < MAX_AGAIN_TICKS {
+        keys.push('.');
+        again_ticks += 1;
+
+        let mut next = new.clone();
+        t = game_data.evaluate(rng, &mut next, &mut ());
+        if t.restart {
+            return None;
+        }
+        if t.cancel {
+            break;
+        }
+        new = next;
+        if t.win {
+            return Some((new, keys, true));
+        }
+    }
+    if t.message.is_some() {
+        keys.push('X');
+    }
+
+    if new == *board {
+        None
+    } else {
+        Some((new, keys, false))
+    }
+}
+
+fn to_key(input: Option<Input>) -> char {
+    match input {
+        Some(Input::Up) => 'W',
+        Some(Input::Down) => 'S',
+        Some(Input::Left) => 'A',
+        Some(Input::Right) => 'D',
+        Some(Input::Action) => 'X',
+        None => '.',
+    }
+}
+
+// Estimates how many moves are left: for each win condition, how many objects are in the way
+// or how far (in cells) the objects are from the tiles they need to be on
+fn distance_to_win(game_data: &GameData, board: &Board) -> usize {
+    let positions = board.positions_iter();
+    let find = |tile: &Tile| -> Vec<Position> {
+        positions
+            .iter()
+            .filter(|p| board.matches(p, tile, &None))
+            .cloned()
+            .collect()
+    };
+    let distance = |a: &Position, b: &Position| {
+        ((a.x as i32 - b.x as i32).abs() + (a.y as i32 - b.y as i32).abs()) as usize
+    };
+
+    game_data
+        .win_conditions
+        .iter()
+        .map(|w| match w {
+            WinCondition::Simple(WinConditionOnQualifier::No, tile) => find(tile).len(),
+            WinCondition::Simple(_, tile) => find(tile).is_empty() as usize,
+            WinCondition::On(qualifier, tile, on_tile) => {
+                let targets = find(on_tile);
+                let distances = find(tile)
+                    .into_iter()
+                    .map(|p| targets.iter().map(|t| distance(&p, t)).min().unwrap_or(1));
+                match qualifier {
+                    WinConditionOnQualifier::All => distances.sum(),
+                    WinConditionOnQualifier::No => distances.filter(|d| *d == 0).count(),
+                    WinConditionOnQualifier::Some | WinConditionOnQualifier::Any => {
+                        distances.min().unwrap_or(1)
+                    }
+                }
+            }
+        })
+        .sum()
+}
+
+fn fingerprint(board: &Board) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    board.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn keys_to(nodes: &[Node], index: usize) -> String {
+    let mut parts = vec![];
+    let mut cur = Some(index);
+    while let Some(index) = cur {
+        parts.push(nodes[index].keys.as_str());
+        cur = nodes[index].parent;
+    }
+    parts.reverse();
+    parts.concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::engine::Engine;
+    use crate::fixture;
+    use crate::verify;
+
+    // Solves the level and checks that the keys complete it when they are replayed like the
+    // solutions in the *.test-replay.json files
+    fn solve_and_replay(game: GameData, strategy: Strategy) -> (String, usize) {
+        let solution = solve(&game, 0, strategy, &Limits::default());
+        let keys = match solution.result {
+            SolveResult::Solved(keys) => keys,
+            other => panic!("Expected a solution but got {:?}", other),
+        };
+        assert!(
+            verify::replay(&mut Engine::new(game, 0), &keys),
+            "{} did not complete the level",
+            keys
+        );
+        (keys, solution.nodes)
+    }
+
+    #[test]
+    fn finds_the_shortest_solution() {
+        let game = fixture::game("", "P.#\n..#\n..T");
+        let (keys, _) = solve_and_replay(game, Strategy::BreadthFirst);
+        assert_eq!(keys.len(), 4);
+    }
+
+    #[test]
+    fn plays_the_again_ticks() {
+        // Stepping on the ice turns it into the target one tick later
+        let rules =
+            "[ Player Ice ] -> [ Player Target ]\nlate [ Player Ice ] -> [ Player Ice ] again";
+        let (keys, _) = solve_and_replay(fixture::game(rules, "PI"), Strategy::BreadthFirst);
+        assert_eq!(keys, "D.");
+    }
+
+    #[test]
+    fn dismisses_messages() {
+        let rules = "late [ Player Sign ] -> [ Player ] message hello";
+        let (keys, _) = solve_and_replay(fixture::game(rules, "PST"), Strategy::BreadthFirst);
+        assert_eq!(keys, "DXD");
+    }
+
+    #[test]
+    fn astar_visits_fewer_boards() {
+        let level = "P.....\n......\n......\n.....T";
+        let (bfs_keys, bfs_nodes) =
+            solve_and_replay(fixture::game("", level), Strategy::BreadthFirst);
+        let (astar_keys, astar_nodes) = solve_and_replay(fixture::game("", level), Strategy::AStar);
+        assert_eq!(astar_keys.len(), bfs_keys.len());
+        assert!(astar_nodes < bfs_nodes);
+    }
+
+    #[test]
+    fn reports_unsolvable_levels() {
+        for strategy in &[Strategy::BreadthFirst, Strategy::AStar] {
+            let game = fixture::game("", "P#T");
+            let solution = solve(&game, 0, *strategy, &Limits::default());
+            assert_eq!(solution.result, SolveResult::Unsolvable);
+        }
+    }
+
+    #[test]
+    fn gives_up_at_the_depth_limit() {
+        let game = fixture::game("", "P...T");
+        let limits = Limits {
+            max_depth: Some(2),
+            ..Limits::default()
+        };
+        assert_eq!(
+            solve(&game, 0, Strategy::BreadthFirst, &limits).result,
+            SolveResult::GaveUp
+        );
+    }
+}
@@ -0,0 +1,1794 @@
+// Reads the original PuzzleScript source format (the `.txt` files you would paste into
+// the online editor) and produces the same `json::GameMap` that the external Node
+// toolchain writes out. That way `parser` lowers both formats into `GameData`.
+use fnv::FnvHashMap;
+use log::{debug, warn};
+use std::error::Error;
+use std::io::Read;
+
+use crate::json;
+use crate::json::GameMap;
+use crate::model::util::CardinalDirection;
+use crate::model::util::WantsToMove;
+
+const SPRITE_SIZE: usize = 5;
+
+// Sounds that play when something happens to the game (instead of to an object)
+const SOUND_EVENTS: [&str; 9] = [
+    "startgame",
+    "endgame",
+    "startlevel",
+    "endlevel",
+    "restart",
+    "undo",
+    "showmessage",
+    "closemessage",
+    "titlescreen",
+];
+const SPRITE_EVENTS: [&str; 4] = ["create", "destroy", "cantmove", "action"];
+
+pub fn from_file<R: Read>(mut file: R) -> Result<GameMap, Box<dyn Error>> {
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    from_str(&text)
+}
+
+pub fn from_str(text: &str) -> Result<GameMap, Box<dyn Error>> {
+    let mut parser = SourceParser::default();
+    parser.read(&strip_comments(text))?;
+    parser.build()
+}
+
+// Comments are `( ... )` and may nest or span multiple lines.
+// Keep the newlines so line numbers still line up.
+fn strip_comments(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            '\n' => ret.push(c),
+            _ if depth == 0 => ret.push(c),
+            _ => {}
+        }
+    }
+    ret
+}
+
+// sfx0 through sfx10
+fn is_sfx(name: &str) -> bool {
+    name.starts_with("sfx") && name[3..].parse::<u8>().is_ok_and(|n| n <= 10)
+}
+
+fn err<T>(line_num: usize, msg: String) -> Result<T, Box<dyn Error>> {
+    Err(format!("line {}: {}", line_num + 1, msg).into())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Section {
+    Prelude,
+    Objects,
+    Legend,
+    Sounds,
+    CollisionLayers,
+    Rules,
+    WinConditions,
+    Levels,
+}
+
+impl Section {
+    fn from_header(line: &str) -> Option<Section> {
+        match line {
+            "objects" => Some(Section::Objects),
+            "legend" => Some(Section::Legend),
+            "sounds" => Some(Section::Sounds),
+            "collisionlayers" => Some(Section::CollisionLayers),
+            "rules" => Some(Section::Rules),
+            "winconditions" => Some(Section::WinConditions),
+            "levels" => Some(Section::Levels),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ObjectState {
+    Name,
+    Colors,
+    Matrix,
+}
+
+#[derive(Debug)]
+struct ObjectDef {
+    name: String,
+    line_num: usize,
+    colors: Vec<Option<String>>,
+    matrix: Vec<Vec<char>>,
+}
+
+// What a name (object, legend entry or glyph) refers to. The Vecs hold object indexes.
+#[derive(Clone, Debug)]
+enum Definition {
+    Object(usize),
+    Or(Vec<usize>),
+    And(Vec<usize>),
+}
+
+impl Definition {
+    fn objects(&self) -> Vec<usize> {
+        match self {
+            Definition::Object(o) => vec![*o],
+            Definition::Or(v) | Definition::And(v) => v.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NamedDefinition {
+    name: String,
+    def: Definition,
+}
+
+#[derive(Debug)]
+enum LevelDef {
+    Message(String),
+    Map(usize, Vec<Vec<char>>),
+}
+
+#[derive(Default, Debug)]
+struct SourceParser {
+    title: Option<String>,
+    metadata: Vec<(usize, String, String)>,
+    objects: Vec<ObjectDef>,
+    object_state: Option<ObjectState>,
+    // lowercased name -> index into `definitions`
+    names: FnvHashMap<String, usize>,
+    definitions: Vec<NamedDefinition>,
+    collision_layers: Vec<Vec<usize>>,
+    sounds: Vec<(usize, String)>,
+    rules: Vec<(usize, String)>,
+    win_conditions: Vec<(usize, String)>,
+    levels: Vec<LevelDef>,
+}
+
+impl SourceParser {
+    fn read(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let mut section = Section::Prelude;
+        let mut in_level = false;
+
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let lower = line.to_ascii_lowercase();
+
+            if line.chars().all(|c| c == '=') {
+                // Section separators (and blank lines) are only meaningful between levels
+                in_level = false;
+                if self.object_state == Some(ObjectState::Matrix) {
+                    self.object_state = Some(ObjectState::Name);
+                }
+                continue;
+            }
+            if let Some(s) = Section::from_header(&lower) {
+                section = s;
+                in_level = false;
+                continue;
+            }
+
+            match section {
+                Section::Prelude => self.read_prelude(line_num, line, &lower)?,
+                Section::Objects => self.read_object(line_num, line, &lower)?,
+                Section::Legend => self.read_legend(line_num, line, &lower)?,
+                Section::Sounds if !lower.is_empty() => self.sounds.push((line_num, lower)),
+                Section::Sounds => {}
+                Section::CollisionLayers => self.read_collision_layer(line_num, &lower)?,
+                Section::Rules => self.rules.push((line_num, line.to_string())),
+                Section::WinConditions => self.win_conditions.push((line_num, lower)),
+                Section::Levels => {
+                    if lower.starts_with("message") {
+                        self.levels.push(LevelDef::Message(
+                            line["message".len()..].trim().to_string(),
+                        ));
+                        in_level = false;
+                    } else {
+                        let row = lower.chars().collect();
+                        match self.levels.last_mut() {
+                            Some(LevelDef::Map(_, rows)) if in_level => rows.push(row),
+                            _ => self.levels.push(LevelDef::Map(line_num, vec![row])),
+                        }
+                        in_level = true;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_prelude(
+        &mut self,
+        line_num: usize,
+        line: &str,
+        lower: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let key = parts.next().unwrap_or("").to_ascii_lowercase();
+        let value = parts.next().unwrap_or("").trim().to_string();
+        if key == "title" {
+            self.title = Some(value);
+        } else {
+            debug!("Metadata: {}", lower);
+            self.metadata.push((line_num, key, value));
+        }
+        Ok(())
+    }
+
+    fn read_object(
+        &mut self,
+        line_num: usize,
+        line: &str,
+        lower: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let state = self.object_state.unwrap_or(ObjectState::Name);
+        let is_matrix_row = lower.chars().all(|c| c == '.' || c.is_ascii_digit());
+
+        if state == ObjectState::Matrix && is_matrix_row {
+            let obj = self.objects.last_mut().unwrap();
+            obj.matrix.push(lower.chars().collect());
+            if obj.matrix.len() == SPRITE_SIZE {
+                self.object_state = Some(ObjectState::Name);
+            }
+            return Ok(());
+        }
+
+        match state {
+            ObjectState::Name | ObjectState::Matrix => {
+                let mut tokens = line.split_whitespace();
+                let name = tokens.next().unwrap().to_string();
+                let index = self.objects.len();
+                self.define(line_num, &name, Definition::Object(index))?;
+                // Extra words on the name line are aliases (usually a single glyph for the level)
+                for alias in tokens {
+                    self.define(line_num, alias, Definition::Object(index))?;
+                }
+                self.objects.push(ObjectDef {
+                    name,
+                    line_num,
+                    colors: vec![],
+                    matrix: vec![],
+                });
+                self.object_state = Some(ObjectState::Colors);
+            }
+            ObjectState::Colors => {
+                let mut colors = vec![];
+                for c in lower.split_whitespace() {
+                    match to_hex(c) {
+                        Some(hex) => colors.push(hex),
+                        None => return err(line_num, format!("unknown color '{}'", c)),
+                    }
+                }
+                self.objects.last_mut().unwrap().colors = colors;
+                self.object_state = Some(ObjectState::Matrix);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_legend(
+        &mut self,
+        line_num: usize,
+        line: &str,
+        lower: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut sides = line.splitn(2, '=');
+        let name = sides.next().unwrap().trim();
+        let rest = match sides.next() {
+            None => {
+                return err(
+                    line_num,
+                    format!("expected 'name = ...' but got '{}'", lower),
+                )
+            }
+            Some(rest) => rest.to_ascii_lowercase(),
+        };
+        let tokens: Vec<_> = rest.split_whitespace().collect();
+        if tokens.is_empty() {
+            return err(line_num, format!("'{}' is not defined as anything", name));
+        }
+        let is_or = tokens.contains(&"or");
+        let is_and = tokens.contains(&"and");
+        if is_or && is_and {
+            return err(
+                line_num,
+                String::from("cannot mix 'and' and 'or' in a legend entry"),
+            );
+        }
+
+        let mut objects = vec![];
+        for (i, token) in tokens.iter().enumerate() {
+            if i % 2 == 1 {
+                if *token != "or" && *token != "and" {
+                    return err(
+                        line_num,
+                        format!("expected 'and' or 'or' but got '{}'", token),
+                    );
+                }
+                continue;
+            }
+            let def = self.lookup(line_num, token)?.clone();
+            match (&def, is_or) {
+                (Definition::And(_), true) => {
+                    return err(
+                        line_num,
+                        format!(
+                            "cannot use '{}' in an 'or' because it is an aggregate",
+                            token
+                        ),
+                    )
+                }
+                (Definition::Or(_), false) if tokens.len() > 1 => {
+                    return err(
+                        line_num,
+                        format!(
+                            "cannot use '{}' in an 'and' because it is a property",
+                            token
+                        ),
+                    )
+                }
+                _ => {}
+            }
+            for o in def.objects() {
+                if !objects.contains(&o) {
+                    objects.push(o);
+                }
+            }
+        }
+
+        let def = if tokens.len() == 1 {
+            self.lookup(line_num, tokens[0])?.clone()
+        } else if is_or {
+            Definition::Or(objects)
+        } else {
+            Definition::And(objects)
+        };
+        self.define(line_num, name, def)
+    }
+
+    fn read_collision_layer(&mut self, line_num: usize, lower: &str) -> Result<(), Box<dyn Error>> {
+        let mut layer = vec![];
+        for name in lower.split(|c: char| c == ',' || c.is_whitespace()) {
+            if name.is_empty() {
+                continue;
+            }
+            for o in self.lookup(line_num, name)?.objects() {
+                layer.push(o);
+            }
+        }
+        self.collision_layers.push(layer);
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        line_num: usize,
+        name: &str,
+        def: Definition,
+    ) -> Result<(), Box<dyn Error>> {
+        let key = name.to_ascii_lowercase();
+        if self.names.contains_key(&key) {
+            return err(line_num, format!("'{}' is defined more than once", name));
+        }
+        self.names.insert(key, self.definitions.len());
+        self.definitions.push(NamedDefinition {
+            name: name.to_string(),
+            def,
+        });
+        Ok(())
+    }
+
+    fn lookup(&self, line_num: usize, name: &str) -> Result<&Definition, Box<dyn Error>> {
+        match self.names.get(&name.to_ascii_lowercase()) {
+            None => err(
+                line_num,
+                format!("unknown object or legend entry '{}'", name),
+            ),
+            Some(i) => Ok(&self.definitions[*i].def),
+        }
+    }
+
+    fn build(self) -> Result<GameMap, Box<dyn Error>> {
+        let mut b = Builder::default();
+
+        // Sprites (and their colors)
+        let mut object_layers = vec![None; self.objects.len()];
+        for (layer, objects) in self.collision_layers.iter().enumerate() {
+            for o in objects {
+                object_layers[*o] = Some(layer as u16);
+            }
+        }
+        for (index, obj) in self.objects.iter().enumerate() {
+            let collision_layer = match object_layers[index] {
+                None => {
+                    return err(
+                        obj.line_num,
+                        format!("'{}' is not in any collision layer", obj.name),
+                    )
+                }
+                Some(c) => c,
+            };
+            let pixels = obj.pixels(&mut b.colors)?;
+            b.sprites.insert(
+                sprite_id(index),
+                json::Sprite {
+                    name: obj.name.clone(),
+                    collision_layer,
+                    pixels,
+                },
+            );
+        }
+
+        // Every object and legend entry becomes a Tile
+        let mut tile_ids = FnvHashMap::default();
+        for (index, named) in self.definitions.iter().enumerate() {
+            let id = format!("tile-{}", index);
+            let tile = match &named.def {
+                Definition::Object(o) if self.objects[*o].name == named.name => {
+                    json::Tile::Sprite {
+                        name: named.name.clone(),
+                        sprite: sprite_id(*o),
+                    }
+                }
+                Definition::Object(o) => json::Tile::Simple {
+                    name: named.name.clone(),
+                    sprite: sprite_id(*o),
+                },
+                Definition::Or(objects) => json::Tile::Or {
+                    name: named.name.clone(),
+                    sprites: objects.iter().map(|o| sprite_id(*o)).collect(),
+                },
+                Definition::And(objects) => json::Tile::And {
+                    name: named.name.clone(),
+                    sprites: objects.iter().map(|o| sprite_id(*o)).collect(),
+                },
+            };
+            b.tiles.insert(id.clone(), tile);
+            tile_ids.insert(named.name.to_ascii_lowercase(), id);
+        }
+        for required in &["player", "background"] {
+            if !tile_ids.contains_key(*required) {
+                return err(0, format!("the game must define a '{}'", required));
+            }
+        }
+
+        // Sounds
+        let mut sfx_ids = FnvHashMap::default();
+        for (line_num, line) in &self.sounds {
+            let tokens: Vec<_> = line.split_whitespace().collect();
+            let sound_code = match tokens.last().map(|t| t.parse()) {
+                Some(Ok(code)) if tokens.len() > 1 => code,
+                _ => return err(*line_num, format!("expected a sound number in '{}'", line)),
+            };
+            let id = format!("sound-{}", b.sounds.len());
+            let sound = match tokens[0] {
+                t if is_sfx(t) => {
+                    sfx_ids.insert(t.to_string(), id.clone());
+                    json::Sound::Sfx {
+                        sound_effect: t.to_ascii_uppercase(),
+                        sound_code,
+                    }
+                }
+                t if SOUND_EVENTS.contains(&t) => json::Sound::When {
+                    when: t.to_ascii_uppercase(),
+                    sound_code,
+                },
+                name => {
+                    let sprite = match tile_ids.get(name) {
+                        None => return err(*line_num, format!("unknown object '{}'", name)),
+                        Some(id) => id.clone(),
+                    };
+                    // The directions after the event (e.g. `Player move up 123`) are ignored, like the compiler does
+                    match tokens.get(1) {
+                        Some(&"move") => json::Sound::SpriteMove { sprite, sound_code },
+                        Some(event) if SPRITE_EVENTS.contains(event) => json::Sound::SpriteEvent {
+                            sprite,
+                            event: event.to_ascii_uppercase(),
+                            sound_code,
+                        },
+                        _ => {
+                            return err(*line_num, format!("could not understand sound '{}'", line))
+                        }
+                    }
+                }
+            };
+            b.sounds.insert(id, sound);
+        }
+
+        // Rules
+        let mut rule_compiler = RuleCompiler {
+            b: &mut b,
+            tile_ids: &tile_ids,
+            sfx_ids: &sfx_ids,
+        };
+        let rules = rule_compiler.compile(&self.rules)?;
+
+        // Win Conditions
+        let mut win_conditions = vec![];
+        for (line_num, line) in &self.win_conditions {
+            let tokens: Vec<_> = line.split_whitespace().collect();
+            let qualifier = match tokens.first() {
+                Some(&"all") => json::WinConditionOnQualifier::All,
+                Some(&"no") => json::WinConditionOnQualifier::No,
+                Some(&"some") => json::WinConditionOnQualifier::Some,
+                Some(&"any") => json::WinConditionOnQualifier::Any,
+                _ => return err(*line_num, format!("unknown win condition '{}'", line)),
+            };
+            let tile = |name: Option<&&str>| match name.and_then(|n| tile_ids.get(*n)) {
+                None => err(
+                    *line_num,
+                    format!("unknown object in win condition '{}'", line),
+                ),
+                Some(id) => Ok(id.clone()),
+            };
+            match tokens.len() {
+                2 => win_conditions.push(json::WinCondition::Simple {
+                    qualifier,
+                    tile: tile(tokens.get(1))?,
+                }),
+                4 if tokens[2] == "on" => win_conditions.push(json::WinCondition::On {
+                    qualifier,
+                    tile: tile(tokens.get(1))?,
+                    on_tile: tile(tokens.get(3))?,
+                }),
+                _ => {
+                    return err(
+                        *line_num,
+                        format!("could not understand win condition '{}'", line),
+                    )
+                }
+            }
+        }
+
+        // Levels
+        let mut levels = vec![];
+        for level in &self.levels {
+            match level {
+                LevelDef::Message(message) => levels.push(json::Level::Message {
+                    message: message.clone(),
+                }),
+                LevelDef::Map(line_num, rows) => {
+                    let width = rows[0].len();
+                    let mut cells = vec![];
+                    for (y, row) in rows.iter().enumerate() {
+                        if row.len() != width {
+                            return err(
+                                line_num + y,
+                                String::from("every row in a level must be the same length"),
+                            );
+                        }
+                        let mut ids = vec![];
+                        for glyph in row {
+                            let name = glyph.to_string();
+                            match self.lookup(line_num + y, &name)? {
+                                Definition::Or(_) => {
+                                    return err(
+                                        line_num + y,
+                                        format!(
+                                            "'{}' is a property and cannot be used in a level",
+                                            glyph
+                                        ),
+                                    )
+                                }
+                                _ => ids.push(tile_ids[&name].clone()),
+                            }
+                        }
+                        cells.push(ids);
+                    }
+                    levels.push(json::Level::Map { cells });
+                }
+            }
+        }
+
+        let metadata = build_metadata(&self.metadata, &mut b.colors)?;
+
+        Ok(GameMap {
+            title: self
+                .title
+                .unwrap_or_else(|| String::from("PuzzleScript Game")),
+            metadata,
+            colors: b.colors,
+            sounds: b.sounds,
+            collision_layers: (0..self.collision_layers.len())
+                .map(|id| json::CollisionLayer { id: id as u16 })
+                .collect(),
+            commands: b.commands,
+            sprites: b.sprites,
+            tiles: b.tiles,
+            tiles_with_modifiers: b.tiles_with_modifiers,
+            neighbors: b.neighbors,
+            brackets: b.brackets,
+            rule_definitions: b.rule_definitions,
+            rules,
+            levels,
+            win_conditions,
+        })
+    }
+}
+
+impl ObjectDef {
+    fn pixels(
+        &self,
+        colors: &mut FnvHashMap<String, String>,
+    ) -> Result<Vec<Vec<Option<String>>>, Box<dyn Error>> {
+        if self.colors.is_empty() {
+            return err(
+                self.line_num,
+                format!("'{}' needs at least one color", self.name),
+            );
+        }
+        for hex in self.colors.iter().flatten() {
+            colors.insert(hex.clone(), hex.clone());
+        }
+        if self.matrix.is_empty() {
+            // No pixels means the whole sprite is the first color
+            return Ok(vec![vec![self.colors[0].clone(); SPRITE_SIZE]; SPRITE_SIZE]);
+        }
+        if self.matrix.len() != SPRITE_SIZE
+            || self.matrix.iter().any(|row| row.len() != SPRITE_SIZE)
+        {
+            return err(
+                self.line_num,
+                format!(
+                    "the sprite for '{}' must be {}x{}",
+                    self.name, SPRITE_SIZE, SPRITE_SIZE
+                ),
+            );
+        }
+        let mut rows = vec![];
+        for row in &self.matrix {
+            let mut pixels = vec![];
+            for c in row {
+                match c.to_digit(10) {
+                    None => pixels.push(None),
+                    Some(i) => match self.colors.get(i as usize) {
+                        None => {
+                            return err(
+                                self.line_num,
+                                format!(
+                                    "'{}' uses color {} but only defines {} colors",
+                                    self.name,
+                                    i,
+                                    self.colors.len()
+                                ),
+                            )
+                        }
+                        Some(hex) => pixels.push(hex.clone()),
+                    },
+                }
+            }
+            rows.push(pixels);
+        }
+        Ok(rows)
+    }
+}
+
+fn sprite_id(index: usize) -> String {
+    format!("sprite-{}", index)
+}
+
+// The default "arnecolors" palette from the PuzzleScript editor
+const PALETTE: [(&str, &str); 24] = [
+    ("black", "#000000"),
+    ("white", "#ffffff"),
+    ("grey", "#9d9d9d"),
+    ("darkgrey", "#697175"),
+    ("lightgrey", "#cccccc"),
+    ("gray", "#9d9d9d"),
+    ("darkgray", "#697175"),
+    ("lightgray", "#cccccc"),
+    ("red", "#be2633"),
+    ("darkred", "#732930"),
+    ("lightred", "#e06f8b"),
+    ("brown", "#a46422"),
+    ("darkbrown", "#493c2b"),
+    ("lightbrown", "#eeb62f"),
+    ("orange", "#eb8931"),
+    ("yellow", "#f7e26b"),
+    ("green", "#44891a"),
+    ("darkgreen", "#2f484e"),
+    ("lightgreen", "#a3ce27"),
+    ("blue", "#1d57f7"),
+    ("lightblue", "#b2dcef"),
+    ("darkblue", "#1b2632"),
+    ("purple", "#342a97"),
+    ("pink", "#de65e2"),
+];
+
+// Returns None if the color is not valid and Some(None) if it is transparent
+fn to_hex(color: &str) -> Option<Option<String>> {
+    let color = color.to_ascii_lowercase();
+    if color == "transparent" {
+        return Some(None);
+    }
+    if let Some(digits) = color.strip_prefix('#') {
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        return match digits.len() {
+            3 | 4 => Some(Some(digits.chars().fold(String::from("#"), |mut s, c| {
+                s.push(c);
+                s.push(c);
+                s
+            }))),
+            6 | 8 => Some(Some(color.clone())),
+            _ => None,
+        };
+    }
+    PALETTE
+        .iter()
+        .find(|(name, _)| *name == color)
+        .map(|(_, hex)| Some(hex.to_string()))
+}
+
+fn build_metadata(
+    entries: &[(usize, String, String)],
+    colors: &mut FnvHashMap<String, String>,
+) -> Result<json::Metadata, Box<dyn Error>> {
+    let mut m = json::Metadata {
+        author: None,
+        homepage: None,
+        youtube: None,
+        zoomscreen: None,
+        flickscreen: None,
+        color_palette: None,
+        background_color: None,
+        text_color: None,
+        realtime_interval: None,
+        key_repeat_interval: None,
+        again_interval: None,
+        no_action: false,
+        no_undo: false,
+        run_rules_on_level_start: None,
+        no_repeat_action: false,
+        scanline: None,
+        throttle_movement: None,
+        no_restart: None,
+        require_player_movement: None,
+        verbose_logging: None,
+    };
+    for (line_num, key, value) in entries {
+        let line_num = *line_num;
+        let dimension = || {
+            let mut parts = value
+                .to_ascii_lowercase()
+                .splitn(2, 'x')
+                .map(|s| s.trim().parse::<u16>())
+                .collect::<Vec<_>>();
+            match (parts.pop(), parts.pop()) {
+                (Some(Ok(height)), Some(Ok(width))) => Ok(Some(json::Dimension { width, height })),
+                _ => err(
+                    line_num,
+                    format!("expected WIDTHxHEIGHT but got '{}'", value),
+                ),
+            }
+        };
+        let seconds = || match value.parse::<f32>() {
+            Ok(s) => Ok(Some(s)),
+            Err(_) => err(
+                line_num,
+                format!("expected a number of seconds but got '{}'", value),
+            ),
+        };
+        let mut color = || match to_hex(value) {
+            Some(Some(hex)) => {
+                colors.insert(hex.clone(), hex.clone());
+                Ok(Some(hex))
+            }
+            _ => err(line_num, format!("unknown color '{}'", value)),
+        };
+        match key.as_str() {
+            "author" => m.author = Some(value.clone()),
+            "homepage" => m.homepage = Some(value.clone()),
+            "youtube" => m.youtube = Some(value.clone()),
+            "color_palette" => m.color_palette = Some(value.clone()),
+            "background_color" => m.background_color = color()?,
+            "text_color" => m.text_color = color()?,
+            "zoomscreen" => m.zoomscreen = dimension()?,
+            "flickscreen" => m.flickscreen = dimension()?,
+            "realtime_interval" => m.realtime_interval = seconds()?,
+            "key_repeat_interval" => m.key_repeat_interval = seconds()?,
+            "again_interval" => m.again_interval = seconds()?,
+            "noaction" => m.no_action = true,
+            "noundo" => m.no_undo = true,
+            "norepeat_action" => m.no_repeat_action = true,
+            "run_rules_on_level_start" => m.run_rules_on_level_start = Some(true),
+            "scanline" => m.scanline = Some(true),
+            "throttle_movement" => m.throttle_movement = Some(true),
+            "norestart" => m.no_restart = Some(true),
+            "require_player_movement" => m.require_player_movement = Some(true),
+            "verbose_logging" => m.verbose_logging = Some(true),
+            "debug" => {}
+            _ => warn!("line {}: Ignoring unknown metadata '{}'", line_num + 1, key),
+        }
+    }
+    Ok(m)
+}
+
+// Accumulates all the id-keyed maps that make up a GameMap
+#[derive(Default)]
+struct Builder {
+    colors: FnvHashMap<String, String>,
+    sounds: FnvHashMap<String, json::Sound<String>>,
+    commands: FnvHashMap<String, json::Command<String>>,
+    sprites: FnvHashMap<String, json::Sprite<u16>>,
+    tiles: FnvHashMap<String, json::Tile<String>>,
+    tiles_with_modifiers: FnvHashMap<String, json::TileWithModifier<String>>,
+    neighbors: FnvHashMap<String, json::Neighbor<String>>,
+    brackets: FnvHashMap<String, json::Bracket<String>>,
+    rule_definitions: FnvHashMap<String, json::RuleDefinition<String, String, String>>,
+}
+
+impl Builder {
+    fn add_rule(&mut self, rule: json::RuleDefinition<String, String, String>) -> String {
+        let id = format!("rule-{}", self.rule_definitions.len());
+        self.rule_definitions.insert(id.clone(), rule);
+        id
+    }
+}
+
+// One tile inside a cell of a rule bracket, e.g. `NO Wall` or `> Player`
+#[derive(Clone, Debug)]
+struct CellTile {
+    negated: bool,
+    random: bool,
+    movement: Option<String>,
+    name: String,
+}
+
+#[derive(Clone, Debug)]
+enum Cell {
+    Tiles(Vec<CellTile>),
+    Ellipsis,
+}
+
+#[derive(Debug)]
+struct ParsedRule {
+    line_num: usize,
+    is_continuation: bool,
+    late: bool,
+    rigid: bool,
+    random: bool,
+    directions: Vec<CardinalDirection>,
+    conditions: Vec<Vec<Cell>>,
+    actions: Vec<Vec<Cell>>,
+    commands: Vec<json::Command<String>>,
+}
+
+const RELATIVE_MOVEMENTS: [&str; 6] = ["^", "v", "<", ">", "parallel", "perpendicular"];
+const MOVEMENTS: [&str; 17] = [
+    "^",
+    "v",
+    "<",
+    ">",
+    "parallel",
+    "perpendicular",
+    "up",
+    "down",
+    "left",
+    "right",
+    "moving",
+    "stationary",
+    "action",
+    "randomdir",
+    "horizontal",
+    "vertical",
+    "orthogonal",
+];
+
+fn opposite(dir: CardinalDirection) -> CardinalDirection {
+    match dir {
+        CardinalDirection::Up => CardinalDirection::Down,
+        CardinalDirection::Down => CardinalDirection::Up,
+        CardinalDirection::Left => CardinalDirection::Right,
+        CardinalDirection::Right => CardinalDirection::Left,
+    }
+}
+
+fn dir_name(dir: CardinalDirection) -> &'static str {
+    match dir {
+        CardinalDirection::Up => "up",
+        CardinalDirection::Down => "down",
+        CardinalDirection::Left => "left",
+        CardinalDirection::Right => "right",
+    }
+}
+
+// Converts `>`, `<`, `^`, `v`, `parallel` and `perpendicular` into absolute movements
+fn to_absolute(movement: &str, dir: CardinalDirection) -> String {
+    let (up, down) = match dir {
+        CardinalDirection::Right => (CardinalDirection::Up, CardinalDirection::Down),
+        CardinalDirection::Up => (CardinalDirection::Left, CardinalDirection::Right),
+        CardinalDirection::Down => (CardinalDirection::Right, CardinalDirection::Left),
+        CardinalDirection::Left => (CardinalDirection::Down, CardinalDirection::Up),
+    };
+    let is_horizontal = dir == CardinalDirection::Left || dir == CardinalDirection::Right;
+    match movement {
+        ">" => dir_name(dir).to_string(),
+        "<" => dir_name(opposite(dir)).to_string(),
+        "^" => dir_name(up).to_string(),
+        "v" => dir_name(down).to_string(),
+        "parallel" => String::from(if is_horizontal {
+            "horizontal"
+        } else {
+            "vertical"
+        }),
+        "perpendicular" => String::from(if is_horizontal {
+            "vertical"
+        } else {
+            "horizontal"
+        }),
+        m => m.to_string(),
+    }
+}
+
+// Movements that stand for several concrete movements. These are expanded into one rule per movement.
+fn ambiguous_movements(movement: &str) -> Option<Vec<&'static str>> {
+    match movement {
+        "moving" => Some(vec!["up", "down", "left", "right", "action"]),
+        "orthogonal" => Some(vec!["up", "down", "left", "right"]),
+        "horizontal" => Some(vec!["left", "right"]),
+        "vertical" => Some(vec!["up", "down"]),
+        _ => None,
+    }
+}
+
+fn to_wants_to_move(movement: &str) -> Option<WantsToMove> {
+    match movement {
+        "up" => Some(WantsToMove::Up),
+        "down" => Some(WantsToMove::Down),
+        "left" => Some(WantsToMove::Left),
+        "right" => Some(WantsToMove::Right),
+        "stationary" => Some(WantsToMove::Stationary),
+        "action" => Some(WantsToMove::Action),
+        "randomdir" => Some(WantsToMove::RandomDir),
+        _ => None,
+    }
+}
+
+struct RuleCompiler<'a> {
+    b: &'a mut Builder,
+    tile_ids: &'a FnvHashMap<String, String>,
+    // `sfx0` -> the id of the sound
+    sfx_ids: &'a FnvHashMap<String, String>,
+}
+
+impl<'a> RuleCompiler<'a> {
+    // Returns the ids of the top-level rules (Groups and Loops)
+    fn compile(&mut self, lines: &[(usize, String)]) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut top_level = vec![];
+        let mut loop_groups: Option<Vec<String>> = None;
+        // (random, simple rule ids) for the group currently being built
+        let mut group: Option<(bool, Vec<String>)> = None;
+
+        for (line_num, line) in lines {
+            let lower = line.to_ascii_lowercase();
+            if lower.is_empty() {
+                continue;
+            }
+            if lower == "startloop" || lower == "endloop" {
+                if let Some(g) = group.take() {
+                    self.finish_group(g, &mut top_level, &mut loop_groups);
+                }
+                if lower == "startloop" {
+                    if loop_groups.is_some() {
+                        return err(*line_num, String::from("loops cannot be nested"));
+                    }
+                    loop_groups = Some(vec![]);
+                } else {
+                    match loop_groups.take() {
+                        None => return err(*line_num, String::from("endloop without a startloop")),
+                        Some(groups) => {
+                            let id = self
+                                .b
+                                .add_rule(json::RuleDefinition::Loop { rules: groups });
+                            top_level.push(id);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let rule = parse_rule(*line_num, line)?;
+            let ids = self.expand(&rule)?;
+            match &mut group {
+                Some((_, rules)) if rule.is_continuation => rules.extend(ids),
+                _ => {
+                    if let Some(g) = group.take() {
+                        self.finish_group(g, &mut top_level, &mut loop_groups);
+                    }
+                    group = Some((rule.random, ids));
+                }
+            }
+        }
+        if let Some(g) = group.take() {
+            self.finish_group(g, &mut top_level, &mut loop_groups);
+        }
+        if loop_groups.is_some() {
+            return err(0, String::from("startloop without an endloop"));
+        }
+        Ok(top_level)
+    }
+
+    fn finish_group(
+        &mut self,
+        (random, rules): (bool, Vec<String>),
+        top_level: &mut Vec<String>,
+        loop_groups: &mut Option<Vec<String>>,
+    ) {
+        let id = self
+            .b
+            .add_rule(json::RuleDefinition::Group { random, rules });
+        match loop_groups {
+            Some(groups) => groups.push(id),
+            None => top_level.push(id),
+        }
+    }
+
+    // Expands a rule into one Simple rule per direction (and per concrete movement) and returns their ids
+    fn expand(&mut self, rule: &ParsedRule) -> Result<Vec<String>, Box<dyn Error>> {
+        let all_cells = || rule.conditions.iter().chain(&rule.actions).flatten();
+        let uses_relative = all_cells().any(|cell| match cell {
+            Cell::Tiles(tiles) => tiles.iter().any(|t| match &t.movement {
+                Some(m) => RELATIVE_MOVEMENTS.contains(&m.as_str()),
+                None => false,
+            }),
+            Cell::Ellipsis => false,
+        });
+        let single_cells = rule.conditions.iter().all(|b| b.len() == 1);
+
+        // A rule that looks the same in every direction only needs to be evaluated once
+        let directions = if !uses_relative && single_cells {
+            vec![rule.directions[0]]
+        } else {
+            rule.directions.clone()
+        };
+
+        let mut ids = vec![];
+        for dir in directions {
+            let absolute = |brackets: &Vec<Vec<Cell>>| -> Vec<Vec<Cell>> {
+                brackets
+                    .iter()
+                    .map(|b| {
+                        b.iter()
+                            .map(|cell| match cell {
+                                Cell::Ellipsis => Cell::Ellipsis,
+                                Cell::Tiles(tiles) => Cell::Tiles(
+                                    tiles
+                                        .iter()
+                                        .map(|t| CellTile {
+                                            movement: t
+                                                .movement
+                                                .as_ref()
+                                                .map(|m| to_absolute(m, dir)),
+                                            ..t.clone()
+                                        })
+                                        .collect(),
+                                ),
+                            })
+                            .collect()
+                    })
+                    .collect()
+            };
+            let conditions = absolute(&rule.conditions);
+            let actions = absolute(&rule.actions);
+
+            for (conditions, actions) in expand_ambiguous(rule.line_num, conditions, actions)? {
+                let id = self.build_simple(rule, dir, &conditions, &actions)?;
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn build_simple(
+        &mut self,
+        rule: &ParsedRule,
+        dir: CardinalDirection,
+        conditions: &[Vec<Cell>],
+        actions: &[Vec<Cell>],
+    ) -> Result<String, Box<dyn Error>> {
+        let mut condition_ids = vec![];
+        for b in conditions {
+            condition_ids.push(self.build_bracket(rule.line_num, dir, b)?);
+        }
+        let mut action_ids = vec![];
+        for b in actions {
+            action_ids.push(self.build_bracket(rule.line_num, dir, b)?);
+        }
+        let mut command_ids = vec![];
+        for c in &rule.commands {
+            let id = format!("command-{}", self.b.commands.len());
+            let command = match c {
+                json::Command::Win {} => json::Command::Win {},
+                json::Command::Again {} => json::Command::Again {},
+                json::Command::Cancel {} => json::Command::Cancel {},
+                json::Command::Checkpoint {} => json::Command::Checkpoint {},
+                json::Command::Restart {} => json::Command::Restart {},
+                json::Command::Message { message } => json::Command::Message {
+                    message: message.clone(),
+                },
+                json::Command::Sfx { sound } => match self.sfx_ids.get(sound) {
+                    None => {
+                        return err(
+                            rule.line_num,
+                            format!("'{}' is not defined in the SOUNDS section", sound),
+                        )
+                    }
+                    Some(id) => json::Command::Sfx { sound: id.clone() },
+                },
+            };
+            self.b.commands.insert(id.clone(), command);
+            command_ids.push(id);
+        }
+        Ok(self.b.add_rule(json::RuleDefinition::Simple {
+            source_line_num: Some(rule.line_num),
+            directions: vec![dir],
+            conditions: condition_ids,
+            actions: action_ids,
+            commands: command_ids,
+            random: Some(rule.random),
+            late: rule.late,
+            rigid: rule.rigid,
+        }))
+    }
+
+    fn build_bracket(
+        &mut self,
+        line_num: usize,
+        dir: CardinalDirection,
+        cells: &[Cell],
+    ) -> Result<String, Box<dyn Error>> {
+        let mut before = vec![];
+        let mut after = vec![];
+        let mut is_after = false;
+        for cell in cells {
+            match cell {
+                Cell::Ellipsis => {
+                    if is_after {
+                        return err(
+                            line_num,
+                            String::from("only one ellipsis is allowed per bracket"),
+                        );
+                    }
+                    is_after = true;
+                }
+                Cell::Tiles(tiles) => {
+                    let id = self.build_neighbor(line_num, tiles)?;
+                    if is_after {
+                        after.push(id);
+                    } else {
+                        before.push(id);
+                    }
+                }
+            }
+        }
+        let bracket = if is_after {
+            json::Bracket::Ellipsis {
+                direction: dir,
+                before_neighbors: before,
+                after_neighbors: after,
+            }
+        } else {
+            json::Bracket::Simple {
+                direction: dir,
+                neighbors: before,
+            }
+        };
+        let id = format!("bracket-{}", self.b.brackets.len());
+        self.b.brackets.insert(id.clone(), bracket);
+        Ok(id)
+    }
+
+    fn build_neighbor(
+        &mut self,
+        line_num: usize,
+        tiles: &[CellTile],
+    ) -> Result<String, Box<dyn Error>> {
+        let mut twm_ids = vec![];
+        for t in tiles {
+            let tile = match self.tile_ids.get(&t.name) {
+                None => {
+                    return err(
+                        line_num,
+                        format!("unknown object or legend entry '{}'", t.name),
+                    )
+                }
+                Some(id) => id.clone(),
+            };
+            let direction = match &t.movement {
+                None => None,
+                Some(m) => match to_wants_to_move(m) {
+                    None => return err(line_num, format!("'{}' is not a valid movement here", m)),
+                    Some(w) => Some(w),
+                },
+            };
+            let id = format!("twm-{}", self.b.tiles_with_modifiers.len());
+            self.b.tiles_with_modifiers.insert(
+                id.clone(),
+                json::TileWithModifier {
+                    direction,
+                    negated: t.negated,
+                    random: t.random,
+                    tile,
+                },
+            );
+            twm_ids.push(id);
+        }
+        let id = format!("neighbor-{}", self.b.neighbors.len());
+        self.b.neighbors.insert(
+            id.clone(),
+            json::Neighbor {
+                tile_with_modifiers: twm_ids,
+            },
+        );
+        Ok(id)
+    }
+}
+
+type Brackets = Vec<Vec<Cell>>;
+
+// `moving`, `horizontal`, etc. on the condition side are expanded into every concrete movement.
+// The same word on the action side refers to whichever movement was matched.
+fn expand_ambiguous(
+    line_num: usize,
+    conditions: Brackets,
+    actions: Brackets,
+) -> Result<Vec<(Brackets, Brackets)>, Box<dyn Error>> {
+    let mut found: Option<(String, String)> = None;
+    for cell in conditions.iter().flatten() {
+        if let Cell::Tiles(tiles) = cell {
+            for t in tiles {
+                if let Some(m) = &t.movement {
+                    if ambiguous_movements(m).is_some() {
+                        found = Some((m.clone(), t.name.clone()));
+                        break;
+                    }
+                }
+            }
+        }
+        if found.is_some() {
+            break;
+        }
+    }
+
+    match found {
+        None => {
+            for cell in actions.iter().flatten() {
+                if let Cell::Tiles(tiles) = cell {
+                    for t in tiles {
+                        if let Some(m) = &t.movement {
+                            if ambiguous_movements(m).is_some() {
+                                return err(line_num, format!("'{} {}' on the right side of a rule needs a matching '{} {}' on the left", m, t.name, m, t.name));
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(vec![(conditions, actions)])
+        }
+        Some((movement, name)) => {
+            // When only one object uses the word on the left, every use of it on the right follows that object
+            let mut names = conditions.iter().flatten().filter_map(|cell| match cell {
+                Cell::Tiles(tiles) => Some(tiles),
+                Cell::Ellipsis => None,
+            });
+            let is_unique = names.all(|tiles| {
+                tiles
+                    .iter()
+                    .all(|t| t.name == name || t.movement.as_ref() != Some(&movement))
+            });
+            let replace = |brackets: &Brackets, concrete: &str, any_name: bool| -> Brackets {
+                brackets
+                    .iter()
+                    .map(|b| {
+                        b.iter()
+                            .map(|cell| match cell {
+                                Cell::Ellipsis => Cell::Ellipsis,
+                                Cell::Tiles(tiles) => Cell::Tiles(
+                                    tiles
+                                        .iter()
+                                        .map(|t| {
+                                            if (any_name || t.name == name)
+                                                && t.movement.as_ref() == Some(&movement)
+                                            {
+                                                CellTile {
+                                                    movement: Some(concrete.to_string()),
+                                                    ..t.clone()
+                                                }
+                                            } else {
+                                                t.clone()
+                                            }
+                                        })
+                                        .collect(),
+                                ),
+                            })
+                            .collect()
+                    })
+                    .collect()
+            };
+            let mut ret = vec![];
+            for concrete in ambiguous_movements(&movement).unwrap() {
+                // Recurse in case there are more ambiguous movements
+                ret.append(&mut expand_ambiguous(
+                    line_num,
+                    replace(&conditions, concrete, false),
+                    replace(&actions, concrete, is_unique),
+                )?);
+            }
+            Ok(ret)
+        }
+    }
+}
+
+// Splits a rule into tokens, keeping `[`, `]`, `|` and `->` as their own tokens.
+// Each token comes with the byte offset where it starts in `line`
+fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = vec![];
+    let mut word = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        let symbol_len = match c {
+            '[' | ']' | '|' => 1,
+            '-' if line[i + 1..].starts_with('>') => 2,
+            _ => 0,
+        };
+        if symbol_len == 0 && !c.is_whitespace() {
+            word.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = word.take() {
+            tokens.push((start, &line[start..i]));
+        }
+        if symbol_len > 0 {
+            tokens.push((i, &line[i..i + symbol_len]));
+        }
+        if symbol_len == 2 {
+            chars.next();
+        }
+    }
+    if let Some(start) = word {
+        tokens.push((start, &line[start..]));
+    }
+    tokens
+}
+
+fn parse_rule(line_num: usize, line: &str) -> Result<ParsedRule, Box<dyn Error>> {
+    let spans = tokenize(line);
+    let tokens: Vec<String> = spans.iter().map(|(_, t)| t.to_ascii_lowercase()).collect();
+    let mut rule = ParsedRule {
+        line_num,
+        is_continuation: false,
+        late: false,
+        rigid: false,
+        random: false,
+        directions: vec![],
+        conditions: vec![],
+        actions: vec![],
+        commands: vec![],
+    };
+
+    let mut i = 0;
+    // Prefixes
+    while i < tokens.len() && tokens[i] != "[" {
+        match tokens[i].as_str() {
+            "+" => rule.is_continuation = true,
+            "late" => rule.late = true,
+            "rigid" => rule.rigid = true,
+            "random" => rule.random = true,
+            "up" => rule.directions.push(CardinalDirection::Up),
+            "down" => rule.directions.push(CardinalDirection::Down),
+            "left" => rule.directions.push(CardinalDirection::Left),
+            "right" => rule.directions.push(CardinalDirection::Right),
+            "horizontal" => rule
+                .directions
+                .extend(&[CardinalDirection::Left, CardinalDirection::Right]),
+            "vertical" => rule
+                .directions
+                .extend(&[CardinalDirection::Up, CardinalDirection::Down]),
+            "orthogonal" => rule.directions.extend(&[
+                CardinalDirection::Up,
+                CardinalDirection::Down,
+                CardinalDirection::Left,
+                CardinalDirection::Right,
+            ]),
+            t => {
+                return err(
+                    line_num,
+                    format!("unexpected '{}' before the first bracket", t),
+                )
+            }
+        }
+        i += 1;
+    }
+    if rule.directions.is_empty() {
+        rule.directions = vec![
+            CardinalDirection::Up,
+            CardinalDirection::Down,
+            CardinalDirection::Left,
+            CardinalDirection::Right,
+        ];
+    }
+
+    let mut is_action = false;
+    while i < tokens.len() {
+        match tokens[i].as_str() {
+            "[" => {
+                let (bracket, next) = parse_bracket(line_num, &tokens, i + 1)?;
+                if is_action {
+                    rule.actions.push(bracket);
+                } else {
+                    rule.conditions.push(bracket);
+                }
+                i = next;
+            }
+            "->" => {
+                if is_action {
+                    return err(line_num, String::from("a rule can only have one '->'"));
+                }
+                is_action = true;
+                i += 1;
+            }
+            _ => break,
+        }
+    }
+    if !is_action {
+        return err(line_num, String::from("a rule needs a '->'"));
+    }
+    if rule.conditions.is_empty() {
+        return err(
+            line_num,
+            String::from("a rule needs at least one bracket on the left"),
+        );
+    }
+    if !rule.actions.is_empty() {
+        if rule.actions.len() != rule.conditions.len() {
+            return err(
+                line_num,
+                String::from("both sides of a rule need the same number of brackets"),
+            );
+        }
+        for (c, a) in rule.conditions.iter().zip(&rule.actions) {
+            if c.len() != a.len() {
+                return err(
+                    line_num,
+                    String::from("brackets on both sides of a rule need the same number of cells"),
+                );
+            }
+        }
+    }
+
+    // Commands
+    while i < tokens.len() {
+        let command = match tokens[i].as_str() {
+            "again" => json::Command::Again {},
+            "cancel" => json::Command::Cancel {},
+            "checkpoint" => json::Command::Checkpoint {},
+            "restart" => json::Command::Restart {},
+            "win" => json::Command::Win {},
+            "message" => {
+                // The message is the rest of the line (after this token) in its original case
+                let (offset, token) = spans[i];
+                let start = offset + token.len();
+                rule.commands.push(json::Command::Message {
+                    message: line[start..].trim().to_string(),
+                });
+                break;
+            }
+            t if is_sfx(t) => json::Command::Sfx {
+                sound: t.to_string(),
+            },
+            t => return err(line_num, format!("unknown command '{}'", t)),
+        };
+        rule.commands.push(command);
+        i += 1;
+    }
+    Ok(rule)
+}
+
+// Parses the cells of a bracket starting just after the `[`. Returns the index after the `]`
+fn parse_bracket(
+    line_num: usize,
+    tokens: &[String],
+    start: usize,
+) -> Result<(Vec<Cell>, usize), Box<dyn Error>> {
+    let mut cells = vec![];
+    let mut current = vec![];
+    let mut i = start;
+    loop {
+        let token = match tokens.get(i) {
+            None => return err(line_num, String::from("missing ']'")),
+            Some(t) => t.as_str(),
+        };
+        match token {
+            "]" | "|" => {
+                if current.len() == 1 && current[0] == "..." {
+                    cells.push(Cell::Ellipsis);
+                } else {
+                    cells.push(Cell::Tiles(parse_cell(line_num, &current)?));
+                }
+                current.clear();
+                if token == "]" {
+                    return Ok((cells, i + 1));
+                }
+            }
+            t => current.push(t.to_string()),
+        }
+        i += 1;
+    }
+}
+
+fn parse_cell(line_num: usize, tokens: &[String]) -> Result<Vec<CellTile>, Box<dyn Error>> {
+    let mut tiles = vec![];
+    let mut negated = false;
+    let mut random = false;
+    let mut movement = None;
+    for token in tokens {
+        let t = token.as_str();
+        if t == "no" {
+            negated = true;
+        } else if t == "random" {
+            random = true;
+        } else if MOVEMENTS.contains(&t) {
+            movement = Some(t.to_string());
+        } else if t == "..." {
+            return err(
+                line_num,
+                String::from("an ellipsis must be in a cell by itself"),
+            );
+        } else {
+            tiles.push(CellTile {
+                negated,
+                random,
+                movement: movement.take(),
+                name: t.to_string(),
+            });
+            negated = false;
+            random = false;
+        }
+    }
+    if negated || random || movement.is_some() {
+        return err(
+            line_num,
+            format!("a modifier in '{}' is missing an object", tokens.join(" ")),
+        );
+    }
+    Ok(tiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::engine::Engine;
+    use crate::engine::EngineInput;
+    use crate::parser;
+
+    const SOKOBAN: &str = "
+title Simple Block Pushing Game
+author David Skinner
+homepage www.puzzlescript.net
+
+========
+OBJECTS
+========
+
+Background
+LIGHTGREEN GREEN
+11111
+01111
+11101
+11111
+10111
+
+Target (a comment
+that spans lines)
+DarkBlue
+.....
+.000.
+.0.0.
+.000.
+.....
+
+Wall
+BROWN DARKBROWN
+00010
+11111
+01000
+11111
+00010
+
+Player
+Black Orange White Blue
+.000.
+.111.
+22222
+.333.
+.3.3.
+
+Crate
+#f80
+00000
+0...0
+0...0
+0...0
+00000
+
+=======
+LEGEND
+=======
+
+. = Background
+# = Wall
+P = Player
+* = Crate
+@ = Crate and Target
+O = Target
+Obstacle = Wall or Crate
+
+=======
+SOUNDS
+=======
+
+Crate MOVE 36772507
+
+================
+COLLISIONLAYERS
+================
+
+Background
+Target
+Player, Wall, Crate
+
+======
+RULES
+======
+
+[ > Player | Crate ] -> [ > Player | > Crate ]
+
+==============
+WINCONDITIONS
+==============
+
+All Target on Crate
+
+=======
+LEVELS
+=======
+
+message Push the crate onto the target
+
+#####
+#P*O#
+#####
+";
+
+    #[test]
+    fn parses_sections() {
+        let ast = from_str(SOKOBAN).unwrap();
+        assert_eq!(ast.title, "Simple Block Pushing Game");
+        assert_eq!(ast.metadata.author, Some(String::from("David Skinner")));
+        assert_eq!(ast.sprites.len(), 5);
+        assert_eq!(ast.collision_layers.len(), 3);
+        assert_eq!(ast.levels.len(), 2);
+        assert_eq!(ast.win_conditions.len(), 1);
+        // short hex colors are expanded
+        assert!(ast.colors.contains_key("#ff8800"));
+
+        // The single rule is expanded into one rule per direction
+        assert_eq!(ast.rules.len(), 1);
+        match &ast.rule_definitions[&ast.rules[0]] {
+            json::RuleDefinition::Group { rules, .. } => assert_eq!(rules.len(), 4),
+            _ => panic!("Expected a rule group"),
+        }
+    }
+
+    #[test]
+    fn plays_a_parsed_game() {
+        let game = parser::parse_source(SOKOBAN.as_bytes()).unwrap();
+        let mut engine = Engine::new(game, 1);
+        let t = engine.tick(Some(EngineInput::Right));
+        assert_eq!(t.completed_level, Some(1));
+    }
+
+    #[test]
+    fn expands_moving_and_single_cell_rules() {
+        let source = SOKOBAN.replace(
+            "[ > Player | Crate ] -> [ > Player | > Crate ]",
+            "[ moving Player | Crate ] -> [ moving Player | moving Crate ]\n+ late [ Crate Target ] -> [ Crate Target ] again",
+        );
+        let ast = from_str(&source).unwrap();
+        match &ast.rule_definitions[&ast.rules[0]] {
+            // 4 directions * 5 movements + 1 direction-independent late rule
+            json::RuleDefinition::Group { rules, .. } => assert_eq!(rules.len(), 21),
+            _ => panic!("Expected a rule group"),
+        }
+    }
+
+    #[test]
+    fn keeps_the_sfx_seed() {
+        let source = SOKOBAN
+            .replace(
+                "Crate MOVE 36772507",
+                "Crate MOVE 36772507\nsfx3 83842103\nendlevel 123",
+            )
+            .replace("> Crate ]", "> Crate ] sfx3");
+        let ast = from_str(&source).unwrap();
+        assert_eq!(ast.sounds.len(), 3);
+
+        let game = parser::parse_source(source.as_bytes()).unwrap();
+        let mut engine = Engine::new(game, 1);
+        let t = engine.tick(Some(EngineInput::Right));
+        // endlevel, the rule's sfx3 and then the crate moving
+        assert_eq!(t.sfx, vec![123, 83842103, 36772507]);
+
+        let source = SOKOBAN.replace("> Crate ]", "> Crate ] sfx3");
+        let e = from_str(&source).unwrap_err();
+        assert!(e.to_string().contains("sfx3"), "{}", e);
+    }
+
+    #[test]
+    fn keeps_the_whole_message() {
+        let source = SOKOBAN.replace(
+            "-> [ > Player | > Crate ]",
+            "-> [ > Player | > Crate ] message Read this MESSAGE carefully",
+        );
+        let ast = from_str(&source).unwrap();
+        let mut messages: Vec<_> = ast
+            .commands
+            .values()
+            .filter_map(|c| match c {
+                json::Command::Message { message } => Some(message.as_str()),
+                _ => None,
+            })
+            .collect();
+        // One command per direction that the rule was expanded into
+        messages.dedup();
+        assert_eq!(messages, vec!["Read this MESSAGE carefully"]);
+
+        assert_eq!(
+            tokenize("[ a|b ]->[ É ] message Ünïcode"),
+            vec![
+                (0, "["),
+                (2, "a"),
+                (3, "|"),
+                (4, "b"),
+                (6, "]"),
+                (7, "->"),
+                (9, "["),
+                (11, "É"),
+                (14, "]"),
+                (16, "message"),
+                (24, "Ünïcode"),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_directions() {
+        assert_eq!(to_absolute(">", CardinalDirection::Up), "up");
+        assert_eq!(to_absolute("<", CardinalDirection::Up), "down");
+        assert_eq!(to_absolute("^", CardinalDirection::Right), "up");
+        assert_eq!(to_absolute("v", CardinalDirection::Right), "down");
+        assert_eq!(to_absolute("^", CardinalDirection::Left), "down");
+        assert_eq!(to_absolute("parallel", CardinalDirection::Down), "vertical");
+    }
+
+    #[test]
+    fn reports_unknown_names() {
+        let source = SOKOBAN.replace("All Target on Crate", "All Target on Box");
+        let e = from_str(&source).unwrap_err();
+        assert!(e.to_string().contains("win condition"), "{}", e);
+
+        let source = SOKOBAN.replace("[ > Player | Crate ]", "[ > Player | Box ]");
+        let e = from_str(&source).unwrap_err();
+        assert!(e.to_string().contains("'box'"), "{}", e);
+    }
+}
@@ -0,0 +1,465 @@
+use std::fmt;
+
+use crate::engine::EngineInput;
+use crate::engine::TickResult;
+use crate::model::board::Board;
+use crate::model::bracket::BracketMatch;
+use crate::model::game::GameData;
+use crate::model::rule::Rule;
+use crate::model::util::CardinalDirection;
+use crate::model::util::Dimension;
+use crate::model::util::Position;
+use crate::model::util::SpriteState;
+use crate::model::util::TriggeredCommands;
+use crate::observer::EngineObserver;
+use crate::observer::Phase;
+
+// A rule that keeps matching would otherwise fill up the memory with boards
+const MAX_STEPS: usize = 10_000;
+
+/// What one rule (or the end of a phase) did to the board
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub phase: Phase,
+    /// The line of the rule in the game source (starting at 1). None at the end of a phase
+    /// and for the rules that the engine adds to move the player
+    pub line: Option<usize>,
+    pub description: String,
+    /// The cells that each bracket of the rule matched
+    pub matches: Vec<Vec<Position>>,
+    /// The board right after the step
+    pub board: Board,
+    /// The commands that were triggered so far
+    pub triggered: TriggeredCommands,
+}
+
+/// Every step of one tick
+#[derive(Clone, Debug)]
+pub struct Recording {
+    /// The board before the input
+    pub start: Board,
+    pub steps: Vec<Step>,
+    /// True when the tick had more than `MAX_STEPS` steps and the rest were not recorded
+    pub truncated: bool,
+    phase: Phase,
+}
+
+impl Recording {
+    /// The board that step `index` started from
+    pub fn board_before(&self, index: usize) -> &Board {
+        match index.checked_sub(1).and_then(|i| self.steps.get(i)) {
+            None => &self.start,
+            Some(step) => &step.board,
+        }
+    }
+
+    fn push(
+        &mut self,
+        board: &Board,
+        triggered: &TriggeredCommands,
+        line: Option<usize>,
+        description: String,
+        matches: Vec<Vec<Position>>,
+    ) {
+        if self.steps.len() >= MAX_STEPS {
+            self.truncated = true;
+            return;
+        }
+        self.steps.push(Step {
+            phase: self.phase,
+            line,
+            description,
+            matches,
+            board: board.clone(),
+            triggered: triggered.clone(),
+        });
+    }
+
+    /// The first step from `index` on that one of the breakpoints stops at, and why
+    pub fn find_break(&self, index: usize, breakpoints: &[Breakpoint]) -> Option<(usize, String)> {
+        (index..self.steps.len()).find_map(|i| {
+            breakpoints
+                .iter()
+                .find_map(|b| b.hit(self.board_before(i), &self.steps[i]))
+                .map(|reason| (i, reason))
+        })
+    }
+}
+
+/// Where the step debugger stops when it continues
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Breakpoint {
+    /// A rule from this line of the game source (starting at 1) is applied
+    Line(usize),
+    /// The sprite shows up in a cell that did not have it
+    Appears(SpriteState),
+    /// The sprite is removed from a cell
+    Disappears(SpriteState),
+}
+
+impl Breakpoint {
+    /// Reads `12` (a rule line), `+Crate` (the sprite appears) or `-Crate` (it disappears)
+    pub fn parse(text: &str, game: &GameData) -> Result<Self, String> {
+        let text = text.trim();
+        let sprite = |name: &str| {
+            let name = name.trim();
+            game.sprites
+                .iter()
+                .find(|(_, sprite)| sprite.name.eq_ignore_ascii_case(name))
+                .map(|(state, _)| *state)
+                .ok_or_else(|| format!("There is no sprite named '{}'", name))
+        };
+        if let Some(name) = text.strip_prefix('+') {
+            Ok(Breakpoint::Appears(sprite(name)?))
+        } else if let Some(name) = text.strip_prefix('-') {
+            Ok(Breakpoint::Disappears(sprite(name)?))
+        } else {
+            text.parse()
+                .map(Breakpoint::Line)
+                .map_err(|_| String::from("Enter a rule line, +sprite or -sprite"))
+        }
+    }
+
+    /// Why the debugger stops at `step` (None when it does not)
+    pub fn hit(&self, before: &Board, step: &Step) -> Option<String> {
+        let changed = |sprite: &SpriteState, appears: bool| {
+            if before.size() != step.board.size() {
+                return None;
+            }
+            step.board.positions_iter().into_iter().find(|pos| {
+                before.has_sprite(pos, sprite) != appears
+                    && step.board.has_sprite(pos, sprite) == appears
+            })
+        };
+        match self {
+            Breakpoint::Line(line) if step.line == Some(*line) => Some(format!("line {}", line)),
+            Breakpoint::Line(_) => None,
+            Breakpoint::Appears(sprite) => {
+                changed(sprite, true).map(|pos| format!("{} appeared {}", sprite, pos))
+            }
+            Breakpoint::Disappears(sprite) => {
+                changed(sprite, false).map(|pos| format!("{} disappeared {}", sprite, pos))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "line {}", line),
+            Breakpoint::Appears(sprite) => write!(f, "+{}", sprite),
+            Breakpoint::Disappears(sprite) => write!(f, "-{}", sprite),
+        }
+    }
+}
+
+/// What the step debugger shows: the steps of the last tick, the current one, the breakpoints
+/// and the cell that is being inspected. It records the steps as the engine's observer
+/// (see `Engine::tick_with_observer()`)
+#[derive(Default, Debug)]
+pub struct Session {
+    /// True while the step debugger is open
+    pub enabled: bool,
+    pub recording: Option<Recording>,
+    pub step: usize,
+    pub breakpoints: Vec<Breakpoint>,
+    /// The cell whose sprites are listed
+    pub cursor: Position,
+    /// When true the arrow keys move the cursor instead of the player
+    pub inspecting: bool,
+    /// The breakpoint that is being typed in
+    pub prompt: Option<String>,
+    pub status: Option<String>,
+}
+
+impl Session {
+    /// Shows a new tick. Without breakpoints it starts at the first step, otherwise it runs
+    /// to the first breakpoint
+    pub fn load(&mut self, recording: Option<Recording>) {
+        self.recording = recording;
+        self.step = 0;
+        self.status = None;
+        if !self.breakpoints.is_empty() {
+            self.resume_from(0);
+        }
+    }
+
+    pub fn current(&self) -> Option<&Step> {
+        self.recording.as_ref()?.steps.get(self.step)
+    }
+
+    fn len(&self) -> usize {
+        self.recording.as_ref().map(|r| r.steps.len()).unwrap_or(0)
+    }
+
+    /// The reasons the breakpoints stop at the current step
+    pub fn hits(&self) -> Vec<String> {
+        match (&self.recording, self.current()) {
+            (Some(recording), Some(step)) => {
+                let before = recording.board_before(self.step);
+                self.breakpoints
+                    .iter()
+                    .filter_map(|b| b.hit(before, step))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Goes to the next step. False when this was the last step of the tick
+    pub fn next_step(&mut self) -> bool {
+        self.status = None;
+        if self.step + 1 < self.len() {
+            self.step += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn prev_step(&mut self) {
+        self.status = None;
+        self.step = self.step.saturating_sub(1);
+    }
+
+    /// Goes to the first step of the next phase. False when this was the last phase of the tick
+    pub fn next_phase(&mut self) -> bool {
+        self.status = None;
+        let steps = match &self.recording {
+            None => return false,
+            Some(recording) => &recording.steps,
+        };
+        let phase = match steps.get(self.step) {
+            None => return false,
+            Some(step) => step.phase,
+        };
+        match (self.step..steps.len()).find(|i| steps[*i].phase != phase) {
+            None => false,
+            Some(i) => {
+                self.step = i;
+                true
+            }
+        }
+    }
+
+    /// Goes to the first step of the current phase, or of the previous one when already there
+    pub fn prev_phase(&mut self) {
+        self.status = None;
+        let steps = match &self.recording {
+            None => return,
+            Some(recording) => &recording.steps,
+        };
+        let start_of = |index: usize| {
+            let phase = steps[index].phase;
+            (0..=index)
+                .rev()
+                .take_while(|i| steps[*i].phase == phase)
+                .last()
+                .unwrap_or(index)
+        };
+        if self.step >= steps.len() {
+            return;
+        }
+        let start = start_of(self.step);
+        self.step = if start == self.step && start > 0 {
+            start_of(start - 1)
+        } else {
+            start
+        };
+    }
+
+    /// Runs to the next breakpoint, or to the end of the tick when there is none
+    pub fn resume(&mut self) {
+        self.resume_from(self.step + 1)
+    }
+
+    fn resume_from(&mut self, index: usize) {
+        let found = self
+            .recording
+            .as_ref()
+            .and_then(|r| r.find_break(index, &self.breakpoints));
+        match found {
+            Some((step, reason)) => {
+                self.step = step;
+                self.status = Some(format!("Stopped at {}", reason));
+            }
+            None => {
+                self.step = self.len().saturating_sub(1);
+                self.status = Some(String::from("End of the tick"));
+            }
+        }
+    }
+
+    /// Adds the breakpoint or removes it when it is already there
+    pub fn toggle_breakpoint(&mut self, breakpoint: Breakpoint) {
+        match self.breakpoints.iter().position(|b| *b == breakpoint) {
+            Some(index) => {
+                self.breakpoints.remove(index);
+                self.status = Some(format!("Removed breakpoint {}", breakpoint));
+            }
+            None => {
+                self.breakpoints.push(breakpoint);
+                self.status = Some(format!("Added breakpoint {}", breakpoint));
+            }
+        }
+    }
+
+    /// Moves the inspected cell, staying inside a board of `size`
+    pub fn move_cursor(&mut self, dir: CardinalDirection, size: Dimension) {
+        let Position { x, y } = self.cursor;
+        let (x, y) = match dir {
+            CardinalDirection::Up => (x, y.saturating_sub(1)),
+            CardinalDirection::Down => (x, y + 1),
+            CardinalDirection::Left => (x.saturating_sub(1), y),
+            CardinalDirection::Right => (x + 1, y),
+        };
+        self.cursor = Position::new(
+            x.min(size.width.saturating_sub(1)),
+            y.min(size.height.saturating_sub(1)),
+        );
+    }
+}
+
+impl EngineObserver for Session {
+    fn tick_started(&mut self, _game: &GameData, _input: Option<EngineInput>, board: &Board) {
+        self.recording = Some(Recording {
+            start: board.clone(),
+            steps: vec![],
+            truncated: false,
+            phase: Phase::Input,
+        });
+    }
+
+    fn phase_started(&mut self, phase: Phase) {
+        if let Some(recording) = &mut self.recording {
+            recording.phase = phase;
+        }
+    }
+
+    fn phase_finished(&mut self, phase: Phase, board: &Board, triggered: &TriggeredCommands) {
+        let description = match phase {
+            Phase::Input => "Applied the input",
+            Phase::Rules if triggered.cancel => "CANCEL: the turn is undone",
+            Phase::Rules => "Ran the rules",
+            Phase::Movement => "Moved the sprites",
+            Phase::LateRules => "Ran the late rules",
+            Phase::WinCheck if triggered.win => "Won",
+            Phase::WinCheck => "Not won yet",
+        };
+        if let Some(recording) = &mut self.recording {
+            recording.push(board, triggered, None, description.to_string(), vec![]);
+        }
+    }
+
+    // Shows the board after the rule and the cells each bracket matched
+    fn rule_applied(
+        &mut self,
+        rule: &Rule,
+        board: &Board,
+        triggered: &TriggeredCommands,
+        matches: &[(usize, BracketMatch)],
+    ) {
+        if let Some(recording) = &mut self.recording {
+            let line = rule.source_line_num.map(|line| line + 1);
+            let cells = rule.matched_cells(matches);
+            recording.push(board, triggered, line, rule.to_string(), cells);
+        }
+    }
+
+    fn rigid_blocked(&mut self, board: &Board, triggered: &TriggeredCommands) {
+        if let Some(recording) = &mut self.recording {
+            let description = "A RIGID movement was blocked so the rules run again";
+            recording.push(board, triggered, None, description.to_string(), vec![]);
+        }
+    }
+
+    fn tick_finished(&mut self, _board: &Board, _result: &TickResult) {
+        let recording = self.recording.take();
+        self.load(recording);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::engine::Engine;
+    use crate::fixture;
+
+    fn record_tick(input: EngineInput) -> (Engine, Recording) {
+        let rules =
+            "[ > Player | Crate ] -> [ > Player | > Crate ]\nlate [ Crate Target ] -> [ Target ]";
+        let mut engine = fixture::engine(rules, "P@T");
+        let mut session = Session::default();
+        engine.tick_with_observer(Some(input), &mut session);
+        let recording = session.recording.unwrap();
+        (engine, recording)
+    }
+
+    #[test]
+    fn records_the_steps_of_a_tick() {
+        let (_, recording) = record_tick(EngineInput::Right);
+        let phases: Vec<_> = recording.steps.iter().map(|s| s.phase).collect();
+        assert_eq!(
+            phases,
+            vec![
+                Phase::Input,
+                Phase::Input,
+                Phase::Rules,
+                Phase::Rules,
+                Phase::Movement,
+                Phase::LateRules,
+                Phase::LateRules,
+                Phase::WinCheck,
+            ]
+        );
+        // The push rule matched the player and the crate next to it
+        let push = &recording.steps[2];
+        assert_eq!(push.line, Some(fixture::FIRST_RULE_LINE));
+        assert_eq!(
+            push.matches,
+            vec![vec![Position::new(0, 0), Position::new(1, 0)]]
+        );
+        assert!(recording.steps[7].triggered.win);
+    }
+
+    #[test]
+    fn stops_at_breakpoints() {
+        let (engine, recording) = record_tick(EngineInput::Right);
+        let game = &engine.game_data;
+        let late_rule_line = fixture::FIRST_RULE_LINE + 1;
+        assert_eq!(
+            Breakpoint::parse(&format!(" {} ", late_rule_line), game),
+            Ok(Breakpoint::Line(late_rule_line))
+        );
+        assert!(Breakpoint::parse("+nothing", game).is_err());
+        assert!(Breakpoint::parse("crate", game).is_err());
+        let crate_gone = Breakpoint::parse("-CRATE", game).unwrap();
+
+        let mut session = Session::default();
+        session.toggle_breakpoint(crate_gone);
+        session.load(Some(recording));
+        // The crate leaves its cell when it is pushed and then the late rule removes it
+        assert_eq!(session.current().unwrap().phase, Phase::Movement);
+        assert!(session.hits()[0].contains("disappeared @(1,0)"));
+        session.resume();
+        assert_eq!(session.current().unwrap().line, Some(late_rule_line));
+        assert!(session.status.as_ref().unwrap().contains("@(2,0)"));
+
+        // No more breakpoints so it runs to the end of the tick
+        session.resume();
+        assert_eq!(session.step, 7);
+        assert!(!session.next_step());
+
+        session.prev_phase();
+        assert_eq!(session.step, 5);
+        session.prev_phase();
+        assert_eq!(session.step, 4);
+        assert!(session.next_phase());
+        assert_eq!(session.current().unwrap().phase, Phase::LateRules);
+
+        session.toggle_breakpoint(crate_gone);
+        assert!(session.breakpoints.is_empty());
+    }
+}
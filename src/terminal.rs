@@ -1,9 +1,12 @@
 use std::cmp;
 use std::format;
+use std::io;
 use std::time;
 
 use log::trace;
+use tui::backend::Backend;
 use tui::buffer::Buffer;
+use tui::buffer::Cell;
 use tui::layout::Rect;
 use tui::style::Color;
 use tui::style::Modifier;
@@ -12,12 +15,18 @@ use tui::widgets::Block;
 use tui::widgets::Borders;
 use tui::widgets::Widget;
 
+use crate::browse::GameEntry;
+use crate::browse::SortBy;
 use crate::color::ColorSpace;
 use crate::color::Rgb;
-use crate::debugger::ScreenDumper;
 use crate::engine::BoardOrMessage;
 use crate::engine::Engine;
+use crate::model::board::Board;
+use crate::model::game::GameData;
+use crate::model::game::Level;
 use crate::model::util::Position;
+use crate::stepper::Session;
+use crate::stepper::Step;
 
 // Temporary grid of pixels. This is used to render the
 // level using '  ' or '▄' depending on the size of the terminal
@@ -66,6 +75,16 @@ impl Grid {
         }
     }
 
+    // Where the pixels from (x, y) to (x + width, y + height) end up on the screen
+    fn pixels_rect(&self, area: &Rect, x: u16, y: u16, width: u16, height: u16) -> Rect {
+        if self.width * 2 >= area.width || self.height >= area.height {
+            let height = cmp::max(1, height / 2);
+            Rect::new(area.x + x, area.y + y / 2, width, height)
+        } else {
+            Rect::new(area.x + x * 2, area.y + y, width * 2, height)
+        }
+    }
+
     fn render(&self, area: &Rect, buf: &mut Buffer) {
         if self.width * 2 >= area.width || self.height >= area.height {
             self.render_small(area, buf);
@@ -146,100 +165,22 @@ impl Widget for Engine {
         match &self.current_level {
             BoardOrMessage::Message(msg) => MessageWindow::new(msg.clone()).draw(area, buf),
             BoardOrMessage::Board(board) => {
-                let (sprite_width, sprite_height) = self.game_data.sprite_size();
                 let board_size = board.size();
-                let is_flickscreen = self.game_data.metadata.flickscreen.is_some();
-                let screen_size = self
-                    .game_data
-                    .metadata
-                    .flickscreen
-                    .or(self.game_data.metadata.zoomscreen);
-                let game_window = match screen_size {
-                    None => Rect::new(0, 0, board_size.width, board_size.height),
-                    Some(flick) => {
-                        let width = cmp::min(flick.width, board_size.width); // see atlas-shrank
-                        let height = cmp::min(flick.height, board_size.height);
-                        let player = self.player_position().unwrap_or(Position::default());
-                        if is_flickscreen {
-                            Rect::new(
-                                player.x / width * width,
-                                player.y / height * height,
-                                width,
-                                height,
-                            )
-                        } else {
-                            // zoomscreen
-                            Rect::new(
-                                if player.x >= width / 2 {
-                                    player.x - width / 2
-                                } else {
-                                    0
-                                },
-                                if player.y >= height / 2 {
-                                    player.y - height / 2
-                                } else {
-                                    0
-                                },
-                                width,
-                                height,
-                            )
-                        }
-                    }
-                };
+                let (top_left, window_size) = self.screen_window(board_size);
+                let game_window = Rect::new(
+                    top_left.x,
+                    top_left.y,
+                    window_size.width,
+                    window_size.height,
+                );
 
                 trace!(
                     "Board: {:?}, Is flickscreen? {:?}",
                     board_size,
                     self.game_data.metadata.flickscreen
                 );
-                ScreenDumper::set_window(game_window);
-
-                let mut grid = Grid::new(
-                    game_window.width * sprite_width,
-                    game_window.height * sprite_height,
-                    self.game_data
-                        .metadata
-                        .background_color
-                        .unwrap_or(Rgb::black()),
-                );
-
-                let is_visible = |pos: &Position| {
-                    pos.x >= game_window.left()
-                        && pos.y >= game_window.top()
-                        && pos.x < game_window.right()
-                        && pos.y < game_window.bottom()
-                };
-
-                for cell_pos in board.positions_iter() {
-                    if !is_visible(&cell_pos) {
-                        continue;
-                    }
-
-                    let mut sprites = board.get_sprite_states(&cell_pos);
-                    sprites.sort();
-
-                    for sprite in sprites {
-                        let sprite = self.game_data.lookup_sprite(sprite);
-
-                        for sprite_y in 0..sprite_height {
-                            for sprite_x in 0..sprite_width {
-                                if let Some(rgb) =
-                                    sprite.pixels[sprite_y as usize][sprite_x as usize]
-                                {
-                                    let x = sprite_x as u16 + cell_pos.x * sprite_width;
-                                    let y = sprite_y as u16 + cell_pos.y * sprite_height;
-
-                                    // shift for flickscreen games
-                                    let x = x - game_window.left() * sprite_width;
-                                    let y = y - game_window.top() * sprite_height;
-
-                                    grid.set(x, y, rgb);
-                                }
-                            }
-                        }
-                    }
-                }
 
+                let grid = board_to_grid(&self.game_data, board, game_window);
                 grid.render(&area, buf);
 
                 // add ellipses if the window is to short or narrow to show the whole level
@@ -274,6 +215,523 @@ impl Widget for Engine {
     }
 }
 
+// Draws the part of the board inside `window` (in cells) using every pixel of the sprites
+fn board_to_grid(game_data: &GameData, board: &Board, window: Rect) -> Grid {
+    let (sprite_width, sprite_height) = game_data.sprite_size();
+    let mut grid = Grid::new(
+        window.width * sprite_width,
+        window.height * sprite_height,
+        game_data.metadata.background_color.unwrap_or(Rgb::black()),
+    );
+
+    let is_visible = |pos: &Position| {
+        pos.x >= window.left()
+            && pos.y >= window.top()
+            && pos.x < window.right()
+            && pos.y < window.bottom()
+    };
+
+    for cell_pos in board.positions_iter() {
+        if !is_visible(&cell_pos) {
+            continue;
+        }
+
+        let mut sprites = board.get_sprite_states(&cell_pos);
+        sprites.sort();
+
+        for sprite in sprites {
+            let sprite = game_data.lookup_sprite(sprite);
+
+            for sprite_y in 0..sprite_height {
+                for sprite_x in 0..sprite_width {
+                    if let Some(rgb) = sprite.pixels[sprite_y as usize][sprite_x as usize] {
+                        let x = sprite_x as u16 + cell_pos.x * sprite_width;
+                        let y = sprite_y as u16 + cell_pos.y * sprite_height;
+
+                        // shift for flickscreen games
+                        let x = x - window.left() * sprite_width;
+                        let y = y - window.top() * sprite_height;
+
+                        grid.set(x, y, rgb);
+                    }
+                }
+            }
+        }
+    }
+    grid
+}
+
+// One pixel per cell (the middle pixel of the top-most sprite) for boards that are too big to draw
+fn board_to_thumbnail(game_data: &GameData, board: &Board) -> Grid {
+    let (sprite_width, sprite_height) = game_data.sprite_size();
+    let mut grid = Grid::new(
+        board.width,
+        board.height,
+        game_data.metadata.background_color.unwrap_or(Rgb::black()),
+    );
+    for pos in board.positions_iter() {
+        let mut sprites = board.get_sprite_states(&pos);
+        sprites.sort();
+        for sprite in sprites {
+            let sprite = game_data.lookup_sprite(sprite);
+            let middle = sprite.pixels[sprite_height as usize / 2][sprite_width as usize / 2];
+            if let Some(rgb) = middle {
+                grid.set(pos.x, pos.y, rgb);
+            }
+        }
+    }
+    grid
+}
+
+/// Lists every level of the game (maps and messages) so the player can jump to one.
+/// Levels that are completed get a check mark and the selected map is previewed next to the list
+pub struct LevelSelect<'a> {
+    game_data: &'a GameData,
+    completed: &'a [bool],
+    selected: usize,
+}
+
+impl<'a> LevelSelect<'a> {
+    pub fn new(game_data: &'a GameData, completed: &'a [bool], selected: usize) -> Self {
+        Self {
+            game_data,
+            completed,
+            selected,
+        }
+    }
+
+    /// "Level 3" for the third map. Messages show their text instead
+    pub fn label(game_data: &GameData, index: usize) -> String {
+        match &game_data.levels[index] {
+            Level::Message(message) => format!("\"{}\"", message),
+            Level::Map(_) => {
+                let maps = game_data.levels[..=index]
+                    .iter()
+                    .filter(|l| matches!(l, Level::Map(_)))
+                    .count();
+                format!("Level {}", maps)
+            }
+        }
+    }
+}
+
+impl<'a> Widget for LevelSelect<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                buf.get_mut(x, y).reset();
+            }
+        }
+
+        let list_width = cmp::min(40, area.width / 2);
+        let list_area = Rect::new(area.x, area.y, list_width, area.height);
+        let mut block = Block::default()
+            .title("Levels (Enter: play, Esc: back)")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::LightYellow));
+        let inner = block.inner(list_area);
+        block.draw(list_area, buf);
+
+        // Scroll so the selected level is always visible
+        let rows = inner.height as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+        let count = self.game_data.levels.len();
+        for (row, index) in (first..count).take(rows).enumerate() {
+            let check = if self.completed.get(index).cloned().unwrap_or(false) {
+                '✔'
+            } else {
+                ' '
+            };
+            let line = format!("{} {}", check, LevelSelect::label(self.game_data, index));
+            let style = if index == self.selected {
+                Style::default().modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let y = inner.y + row as u16;
+            buf.set_stringn(inner.x, y, line, inner.width as usize, style);
+        }
+
+        let preview = Rect::new(
+            list_area.right() + 1,
+            area.y,
+            area.width.saturating_sub(list_width + 1),
+            area.height,
+        );
+        if preview.width == 0 || preview.height == 0 {
+            return;
+        }
+        match &self.game_data.levels[self.selected] {
+            Level::Message(message) => {
+                buf.set_stringn(
+                    preview.x,
+                    preview.y,
+                    message,
+                    preview.width as usize,
+                    Style::default().fg(Color::White),
+                );
+            }
+            level @ Level::Map(_) => {
+                let board = self.game_data.to_board(level);
+                let window = Rect::new(0, 0, board.width, board.height);
+                let grid = board_to_grid(self.game_data, &board, window);
+                let rendered = grid.rendered_rect(&preview);
+                if rendered.width <= preview.width && rendered.height <= preview.height {
+                    grid.render(&preview, buf);
+                } else {
+                    board_to_thumbnail(self.game_data, &board).render(&preview, buf);
+                }
+            }
+        }
+    }
+}
+
+/// The game browser (`puzzlescript browse`): a search box, the games that match and the
+/// title, author and homepage of the selected game
+pub struct GameBrowser<'a> {
+    games: &'a [GameEntry],
+    visible: &'a [usize],
+    selected: usize,
+    query: &'a str,
+    sort: SortBy,
+    status: Option<&'a str>,
+}
+
+impl<'a> GameBrowser<'a> {
+    /// `visible` are the indexes into `games` that match the query and `selected` is an index into `visible`
+    pub fn new(
+        games: &'a [GameEntry],
+        visible: &'a [usize],
+        selected: usize,
+        query: &'a str,
+        sort: SortBy,
+        status: Option<&'a str>,
+    ) -> Self {
+        Self {
+            games,
+            visible,
+            selected,
+            query,
+            sort,
+            status,
+        }
+    }
+}
+
+impl<'a> Widget for GameBrowser<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        if area.height < 6 {
+            return;
+        }
+        let gray = Style::default().fg(Color::DarkGray);
+        buf.set_stringn(
+            area.x,
+            area.y,
+            format!("Search: {}_", self.query),
+            area.width as usize,
+            Style::default().fg(Color::LightYellow),
+        );
+        let sort = format!("Sort: {} [Tab]", self.sort);
+        if area.width as usize > sort.len() + 20 {
+            let x = area.right() - sort.len() as u16;
+            buf.set_string(x, area.y, sort, gray);
+        }
+
+        let list_area = Rect::new(area.x, area.y + 1, area.width, area.height - 4);
+        let title = format!(
+            "{} of {} games (Enter: play, Esc: quit)",
+            self.visible.len(),
+            self.games.len()
+        );
+        let mut block = Block::default().title(&title).borders(Borders::ALL);
+        let inner = block.inner(list_area);
+        block.draw(list_area, buf);
+
+        // Scroll so the selected game is always visible
+        let rows = inner.height as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+        let progress_width = 10;
+        let title_width = (inner.width as usize).saturating_sub(progress_width + 1);
+        for (row, index) in self.visible.iter().enumerate().skip(first).take(rows) {
+            let game = &self.games[*index];
+            let done = game.level_count() > 0 && game.completed_count() == game.level_count();
+            let check = if done { '✔' } else { ' ' };
+            let mut line = format!("{} {}", check, game.title);
+            if let Some(author) = &game.author {
+                line = format!("{} by {}", line, author);
+            }
+            let progress = format!("{}/{}", game.completed_count(), game.level_count());
+            let style = if row == self.selected {
+                Style::default().modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let y = inner.y + (row - first) as u16;
+            buf.set_stringn(
+                inner.x,
+                y,
+                format!("{:width$}", line, width = title_width),
+                title_width,
+                style,
+            );
+            buf.set_stringn(
+                inner.x + title_width as u16 + 1,
+                y,
+                format!("{:>width$}", progress, width = progress_width),
+                progress_width,
+                style,
+            );
+        }
+
+        let details = list_area.bottom();
+        match self
+            .visible
+            .get(self.selected)
+            .map(|index| &self.games[*index])
+        {
+            None => {
+                buf.set_string(area.x, details, "No games match", gray);
+            }
+            Some(game) => {
+                let author = game.author.as_deref().unwrap_or("unknown");
+                let line = format!(
+                    "{} by {} ({} levels)",
+                    game.title,
+                    author,
+                    game.level_count()
+                );
+                buf.set_stringn(area.x, details, line, area.width as usize, Style::default());
+                let homepage = game.homepage.as_deref().unwrap_or("");
+                buf.set_stringn(area.x, details + 1, homepage, area.width as usize, gray);
+                let file = game.path.to_string_lossy();
+                buf.set_stringn(area.x, details + 2, file, area.width as usize, gray);
+            }
+        }
+        if let Some(status) = self.status {
+            let style = Style::default().fg(Color::LightRed);
+            buf.set_stringn(area.x, details + 2, status, area.width as usize, style);
+        }
+    }
+}
+
+/// The step debugger: the board after the current step of the last tick with the cells that
+/// the rule matched, and next to it the step, the sprites in the inspected cell and the breakpoints
+pub struct StepView<'a> {
+    engine: &'a Engine,
+    session: &'a Session,
+}
+
+impl<'a> StepView<'a> {
+    pub fn new(engine: &'a Engine, session: &'a Session) -> Self {
+        Self { engine, session }
+    }
+
+    fn draw_board(&self, board: &Board, step: Option<&Step>, area: Rect, buf: &mut Buffer) {
+        let game_data = &self.engine.game_data;
+        let window = Rect::new(0, 0, board.width, board.height);
+        let mut grid = board_to_grid(game_data, board, window);
+        let (mut cell_width, mut cell_height) = game_data.sprite_size();
+        let rendered = grid.rendered_rect(&area);
+        if rendered.width > area.width || rendered.height > area.height {
+            grid = board_to_thumbnail(game_data, board);
+            cell_width = 1;
+            cell_height = 1;
+        }
+        grid.render(&area, buf);
+
+        let cell_rect = |pos: &Position| {
+            let (x, y) = (pos.x * cell_width, pos.y * cell_height);
+            grid.pixels_rect(&area, x, y, cell_width, cell_height)
+                .intersection(area)
+        };
+        // Number the cells with the bracket that matched them
+        let matches = step.map(|s| s.matches.as_slice()).unwrap_or(&[]);
+        for (i, cells) in matches.iter().enumerate() {
+            let number = std::char::from_digit((i as u32 + 1) % 10, 10).unwrap_or('?');
+            for rect in cells.iter().map(cell_rect).filter(|r| r.area() > 0) {
+                buf.get_mut(rect.x, rect.y)
+                    .set_char(number)
+                    .set_fg(Color::Black)
+                    .set_bg(Color::LightYellow);
+            }
+        }
+        let cursor = cell_rect(&self.session.cursor);
+        for y in cursor.top()..cursor.bottom() {
+            for x in cursor.left()..cursor.right() {
+                buf.get_mut(x, y).set_modifier(Modifier::REVERSED);
+            }
+        }
+    }
+
+    // The lines above the list of steps
+    fn details(&self, board: Option<&Board>, step: Option<&Step>) -> Vec<(String, Style)> {
+        let white = Style::default().fg(Color::White);
+        let gray = Style::default().fg(Color::DarkGray);
+        let mut lines = vec![];
+        let recording = self.session.recording.as_ref();
+        match (recording, step) {
+            (Some(recording), Some(step)) => {
+                let mut title = format!(
+                    "Step {} of {} ({})",
+                    self.session.step + 1,
+                    recording.steps.len(),
+                    step.phase
+                );
+                if recording.truncated {
+                    title.push_str(" (the rest was not recorded)");
+                }
+                lines.push((title, Style::default().fg(Color::LightYellow)));
+                lines.push((step.description.clone(), white));
+                for hit in self.session.hits() {
+                    let style = Style::default().fg(Color::LightRed);
+                    lines.push((format!("Breakpoint: {}", hit), style));
+                }
+                for (i, cells) in step.matches.iter().enumerate() {
+                    let cells: Vec<String> = cells.iter().map(|p| p.to_string()).collect();
+                    lines.push((format!("Bracket {}: {}", i + 1, cells.join(" ")), white));
+                }
+                let commands = step.triggered.to_string();
+                if !commands.is_empty() {
+                    lines.push((format!("Commands:{}", commands), white));
+                }
+            }
+            _ => lines.push((String::from("Move to record the steps of a tick"), white)),
+        }
+        lines.push((String::new(), white));
+
+        let cursor = self.session.cursor;
+        let mut title = format!("Cell {}", cursor);
+        if self.session.inspecting {
+            title.push_str(" (arrows move, i: done)");
+        }
+        lines.push((title, Style::default().fg(Color::LightYellow)));
+        if let Some(board) = board.filter(|b| cursor.x < b.width && cursor.y < b.height) {
+            for (sprite, dir) in board.get_sprites_and_dir(&cursor) {
+                let name = &self.engine.game_data.lookup_sprite(sprite).name;
+                lines.push((format!("  {} {}", name, dir), white));
+            }
+        }
+        lines.push((String::new(), white));
+        lines.push((String::from("Steps:"), gray));
+        lines
+    }
+
+    // The lines below the list of steps
+    fn footer(&self) -> Vec<(String, Style)> {
+        let gray = Style::default().fg(Color::DarkGray);
+        let yellow = Style::default().fg(Color::LightYellow);
+        let mut lines = vec![];
+        match (&self.session.prompt, &self.session.status) {
+            (Some(prompt), _) => {
+                let line = format!("Break on (line, +sprite or -sprite): {}_", prompt);
+                lines.push((line, yellow));
+            }
+            (None, Some(status)) => lines.push((status.clone(), yellow)),
+            (None, None) => lines.push((String::new(), gray)),
+        }
+        let breakpoints: Vec<String> = self
+            .session
+            .breakpoints
+            .iter()
+            .map(|b| b.to_string())
+            .collect();
+        let breakpoints = if breakpoints.is_empty() {
+            String::from("none")
+        } else {
+            breakpoints.join(", ")
+        };
+        lines.push((format!("Breakpoints: {}", breakpoints), Style::default()));
+        lines.push((String::from("n/b: step  ]/[: phase  g: continue"), gray));
+        lines.push((
+            String::from("B: break on rule  :: breakpoint  i: inspect"),
+            gray,
+        ));
+        lines
+    }
+}
+
+// Splits `text` into lines of at most `width` characters
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() || width == 0 {
+        return vec![String::new()];
+    }
+    chars.chunks(width).map(|c| c.iter().collect()).collect()
+}
+
+impl<'a> Widget for StepView<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                buf.get_mut(x, y).reset();
+            }
+        }
+        let panel_width = cmp::min(50, area.width / 2);
+        let board_area = Rect::new(area.x, area.y, area.width - panel_width - 1, area.height);
+        let panel = Rect::new(area.right() - panel_width, area.y, panel_width, area.height);
+
+        let step = self.session.current();
+        let board = match (step, &self.engine.current_level) {
+            (Some(step), _) => Some(&step.board),
+            (None, BoardOrMessage::Board(board)) => Some(board),
+            (None, BoardOrMessage::Message(_)) => None,
+        };
+        if let Some(board) = board {
+            if board_area.area() > 0 {
+                self.draw_board(board, step, board_area, buf);
+            }
+        }
+
+        let mut block = Block::default()
+            .title("Step debugger (~: close)")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::LightYellow));
+        let inner = block.inner(panel);
+        block.draw(panel, buf);
+        let width = inner.width as usize;
+
+        let details: Vec<(String, Style)> = self
+            .details(board, step)
+            .into_iter()
+            .flat_map(|(line, style)| wrap(&line, width).into_iter().map(move |l| (l, style)))
+            .collect();
+        let footer = self.footer();
+        let list_height = (inner.height as usize).saturating_sub(details.len() + footer.len());
+
+        // The steps around the current one
+        let mut steps = vec![];
+        if let Some(recording) = &self.session.recording {
+            let first = self.session.step.saturating_sub(list_height / 2);
+            for (index, step) in recording.steps.iter().enumerate().skip(first) {
+                let phase = step.phase.to_string();
+                let line = format!("{:>4} {:<10} {}", index + 1, phase, step.description);
+                let style = if index == self.session.step {
+                    Style::default().modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                steps.push((line, style));
+            }
+        }
+        steps.truncate(list_height);
+        steps.resize(list_height, (String::new(), Style::default()));
+
+        let lines = details.into_iter().chain(steps).chain(footer);
+        for (row, (line, style)) in lines.take(inner.height as usize).enumerate() {
+            let y = inner.y + row as u16;
+            buf.set_stringn(inner.x, y, line, width, style);
+        }
+    }
+}
+
 pub struct MessageWindow {
     message: String,
 }
@@ -326,6 +784,12 @@ pub struct Spinner {
     last_tick: time::Instant,
 }
 
+impl Default for Spinner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Spinner {
     pub fn new() -> Self {
         Self {
@@ -361,6 +825,12 @@ pub struct Help {
     pub expanded: bool,
 }
 
+impl Default for Help {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Help {
     pub fn new() -> Self {
         Self { expanded: false }
@@ -373,7 +843,7 @@ impl Help {
 impl Widget for Help {
     fn draw(&mut self, area: Rect, buf: &mut Buffer) {
         if self.expanded {
-            buf.set_string(area.x, area.y, "Move: Arrows/WSAD | Action: X/Space | Undo: Z/U | Restart: R | Quit: Q/Esc | Pause: P | Debugger: ` or ~ or \\ | Fast/Slow: - or +", Style::default())
+            buf.set_string(area.x, area.y, "Move: Arrows/WSAD | Action: X/Space | Undo: Z/U | Redo: Y | Restart: R | Levels: L | Quit: Q/Esc | Pause: P | Step debugger: ` or ~ or \\ | Fast/Slow: - or +", Style::default())
         } else {
             buf.set_string(
                 area.x,
@@ -422,6 +892,12 @@ pub struct PlayPause {
     pub paused: bool,
 }
 
+impl Default for PlayPause {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PlayPause {
     pub fn new() -> Self {
         Self { paused: false }
@@ -524,3 +1000,46 @@ impl Widget for RecordingInfo {
         buf.set_string(area.x, area.y, s, Style::default());
     }
 }
+
+// A backend that always reports the same size instead of asking the terminal.
+// Used when recording without a terminal (e.g. turning a replay into a screencast)
+pub struct FixedSizeBackend<B: Backend> {
+    inner: B,
+    size: Rect,
+}
+
+impl<B: Backend> FixedSizeBackend<B> {
+    pub fn new(inner: B, size: Rect) -> Self {
+        Self { inner, size }
+    }
+}
+
+impl<B: Backend> Backend for FixedSizeBackend<B> {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        self.inner.draw(content)
+    }
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.inner.hide_cursor()
+    }
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.inner.show_cursor()
+    }
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        self.inner.get_cursor()
+    }
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.inner.set_cursor(x, y)
+    }
+    fn clear(&mut self) -> io::Result<()> {
+        self.inner.clear()
+    }
+    fn size(&self) -> io::Result<Rect> {
+        Ok(self.size)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
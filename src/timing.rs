@@ -0,0 +1,241 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::engine::EngineInput;
+use crate::model::game::Metadata;
+
+// PuzzleScript's defaults (in seconds)
+const DEFAULT_AGAIN_INTERVAL: f32 = 0.15;
+const DEFAULT_KEY_REPEAT_INTERVAL: f32 = 0.15;
+
+fn seconds(sec: f32) -> Duration {
+    Duration::from_millis((sec * 1000.0) as u64)
+}
+
+/// How long the play loop waits between ticks and which key presses it lets through.
+///
+/// A terminal cannot tell a held key from a key that is pressed again. One press of the same
+/// key within `key_repeat_interval` of the previous one can be a double-tap, so it counts as a
+/// held-key repeat only once the presses keep arriving that fast. Repeats are let through at
+/// most once per `key_repeat_interval`.
+#[derive(Debug)]
+pub struct Timing {
+    // Replaces the realtime and again intervals and slows down input ticks (`--speed`)
+    speed: Option<Duration>,
+    // Replaces the realtime and again intervals but not the delay after input (the +/- keys)
+    adjusted_speed: Option<Duration>,
+    realtime: Option<Duration>,
+    again: Duration,
+    key_repeat: Duration,
+    throttle_movement: bool,
+    no_repeat_action: bool,
+    last_received: Option<(EngineInput, Instant)>,
+    // How many presses in a row arrived within `key_repeat` of the press before them
+    quick_presses: u32,
+    last_accepted: Option<(EngineInput, Instant)>,
+    last_move: Option<(EngineInput, Instant)>,
+}
+
+impl Timing {
+    pub fn new(metadata: &Metadata, speed: Option<Duration>) -> Self {
+        Self {
+            speed,
+            adjusted_speed: None,
+            realtime: metadata.realtime_interval.map(seconds),
+            again: seconds(metadata.again_interval.unwrap_or(DEFAULT_AGAIN_INTERVAL)),
+            key_repeat: seconds(
+                metadata
+                    .key_repeat_interval
+                    .unwrap_or(DEFAULT_KEY_REPEAT_INTERVAL),
+            ),
+            throttle_movement: metadata.throttle_movement,
+            no_repeat_action: metadata.no_repeat_action,
+            last_received: None,
+            quick_presses: 0,
+            last_accepted: None,
+            last_move: None,
+        }
+    }
+
+    /// Every key is let through and every tick happens after `speed` (used for `--scripted` runs)
+    pub fn fixed(speed: Duration) -> Self {
+        Self {
+            speed: Some(speed),
+            adjusted_speed: None,
+            realtime: None,
+            again: speed,
+            key_repeat: Duration::from_secs(0),
+            throttle_movement: false,
+            no_repeat_action: false,
+            last_received: None,
+            quick_presses: 0,
+            last_accepted: None,
+            last_move: None,
+        }
+    }
+
+    /// Time between the ticks of an AGAIN chain
+    pub fn again_interval(&self) -> Duration {
+        self.adjusted_speed.or(self.speed).unwrap_or(self.again)
+    }
+
+    /// Time between the ticks of a realtime game (None when the game only ticks on input)
+    pub fn realtime_interval(&self) -> Option<Duration> {
+        self.realtime
+            .map(|realtime| self.adjusted_speed.or(self.speed).unwrap_or(realtime))
+    }
+
+    /// Time to wait after a tick that ended the AGAIN chain (only `--speed` slows these down)
+    pub fn after_input(&self) -> Duration {
+        self.speed.unwrap_or_default()
+    }
+
+    pub fn faster(&mut self) -> bool {
+        let current = self.current_speed();
+        if current >= Duration::from_millis(50) {
+            self.adjusted_speed = Some(current - Duration::from_millis(50));
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn slower(&mut self) -> bool {
+        let current = self.current_speed();
+        if current < Duration::from_secs(1) {
+            self.adjusted_speed = Some(current + Duration::from_millis(50));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn current_speed(&self) -> Duration {
+        self.realtime_interval()
+            .unwrap_or_else(|| self.again_interval())
+    }
+
+    /// Returns false when the key press should be dropped because it is a held-key repeat that
+    /// came too soon or (with `throttle_movement`) the same move was just made
+    pub fn accept(&mut self, input: EngineInput, now: Instant) -> bool {
+        let key_repeat = self.key_repeat;
+        let within = |last: Option<(EngineInput, Instant)>| match last {
+            Some((last, at)) => last == input && now.duration_since(at) < key_repeat,
+            None => false,
+        };
+        self.quick_presses = if within(self.last_received) {
+            self.quick_presses + 1
+        } else {
+            0
+        };
+        self.last_received = Some((input, now));
+        // The first quick press can be a double-tap. A key that keeps arriving that fast is held
+        let is_repeat = self.quick_presses > 1;
+
+        let drop = if is_repeat {
+            (input == EngineInput::Action && self.no_repeat_action) || within(self.last_accepted)
+        } else {
+            self.throttle_movement && input.is_direction() && within(self.last_move)
+        };
+        if drop {
+            return false;
+        }
+        self.last_accepted = Some((input, now));
+        if input.is_direction() {
+            self.last_move = Some((input, now));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            again_interval: Some(0.3),
+            key_repeat_interval: Some(0.1),
+            ..Metadata::default()
+        }
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn uses_the_game_intervals() {
+        let mut timing = Timing::new(&metadata(), None);
+        assert_eq!(timing.again_interval(), ms(300));
+        assert_eq!(timing.realtime_interval(), None);
+        assert!(timing.faster());
+        assert_eq!(timing.again_interval(), ms(250));
+        // Only --speed slows down the ticks that follow an input
+        assert_eq!(timing.after_input(), ms(0));
+
+        let mut timing = Timing::new(&Metadata::default(), Some(ms(20)));
+        assert_eq!(timing.again_interval(), ms(20));
+        assert!(timing.slower());
+        assert_eq!(timing.again_interval(), ms(70));
+        assert_eq!(timing.after_input(), ms(20));
+        let timing = Timing::new(
+            &Metadata {
+                realtime_interval: Some(0.5),
+                ..Metadata::default()
+            },
+            None,
+        );
+        assert_eq!(timing.again_interval(), ms(150));
+        assert_eq!(timing.realtime_interval(), Some(ms(500)));
+    }
+
+    #[test]
+    fn throttles_held_keys() {
+        let mut timing = Timing::new(&metadata(), None);
+        let start = Instant::now();
+        // A held key repeats every 30ms but only moves every 100ms once it is clearly held
+        let accepted: Vec<u64> = (0..9)
+            .map(|i| i * 30)
+            .filter(|t| timing.accept(EngineInput::Right, start + ms(*t)))
+            .collect();
+        assert_eq!(accepted, vec![0, 30, 150]);
+        // Other keys and keys pressed again later are not repeats
+        assert!(timing.accept(EngineInput::Left, start + ms(250)));
+        assert!(timing.accept(EngineInput::Right, start + ms(260)));
+        assert!(timing.accept(EngineInput::Right, start + ms(500)));
+    }
+
+    #[test]
+    fn accepts_double_taps() {
+        let mut timing = Timing::new(&Metadata::default(), None);
+        let start = Instant::now();
+        assert!(timing.accept(EngineInput::Right, start));
+        assert!(timing.accept(EngineInput::Right, start + ms(100)));
+    }
+
+    #[test]
+    fn throttles_movement() {
+        let mut timing = Timing::new(
+            &Metadata {
+                throttle_movement: true,
+                no_repeat_action: true,
+                ..metadata()
+            },
+            None,
+        );
+        let start = Instant::now();
+        assert!(timing.accept(EngineInput::Right, start));
+        assert!(timing.accept(EngineInput::Action, start + ms(10)));
+        assert!(timing.accept(EngineInput::Action, start + ms(40)));
+        // Holding the action key does not repeat it
+        assert!(!timing.accept(EngineInput::Action, start + ms(70)));
+        // Pressing the same direction again is too soon
+        assert!(!timing.accept(EngineInput::Right, start + ms(80)));
+        assert!(timing.accept(EngineInput::Right, start + ms(150)));
+
+        let mut timing = Timing::fixed(ms(0));
+        assert!(timing.accept(EngineInput::Right, start));
+        assert!(timing.accept(EngineInput::Right, start));
+    }
+}
@@ -0,0 +1,329 @@
+use std::hash::Hasher;
+use std::io;
+use std::io::Write;
+
+use fnv::FnvHasher;
+use serde::Serialize;
+
+use crate::engine::EngineInput;
+use crate::engine::TickResult;
+use crate::model::board::Board;
+use crate::model::bracket::BracketMatch;
+use crate::model::game::GameData;
+use crate::model::rule::Rule;
+use crate::model::util::Position;
+use crate::model::util::TriggeredCommands;
+use crate::observer::EngineObserver;
+
+/// One line of the trace (see `--trace`). Positions are `[x, y]` starting at the top left
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    TickStart {
+        tick: u64,
+        /// None when the tick had no input (e.g. `AGAIN` or a realtime tick)
+        input: Option<String>,
+    },
+    RuleApplied {
+        tick: u64,
+        /// The line of the rule in the game source (starting at 1). None for the rules that
+        /// the engine adds to move the player
+        source_line_num: Option<usize>,
+        late: bool,
+        /// The cells that each bracket of the rule matched
+        positions: Vec<Vec<[u16; 2]>>,
+    },
+    Commands {
+        tick: u64,
+        source_line_num: Option<usize>,
+        commands: Vec<String>,
+    },
+    Moved {
+        tick: u64,
+        sprite: String,
+        from: [u16; 2],
+        to: [u16; 2],
+    },
+    Blocked {
+        tick: u64,
+        sprite: String,
+        at: [u16; 2],
+    },
+    TickEnd {
+        tick: u64,
+        changed: bool,
+        won: bool,
+        /// See `board_hash()`
+        board_hash: String,
+    },
+}
+
+/// A hash of the sprites on the board that another engine can compute too: FNV-1a (64 bit)
+/// of one line per cell (row by row) with the names of the cell's sprites, sorted and
+/// separated by a space
+pub fn board_hash(game: &GameData, board: &Board) -> u64 {
+    hash_board(&sprite_names(game), board)
+}
+
+fn hash_board(names: &[String], board: &Board) -> u64 {
+    let mut hasher = FnvHasher::default();
+    for pos in board.positions_iter() {
+        let mut cell: Vec<&str> = board
+            .as_sprites(&pos)
+            .into_vec()
+            .iter()
+            .map(|index| name_of(names, *index))
+            .collect();
+        cell.sort_unstable();
+        hasher.write(cell.join(" ").as_bytes());
+        hasher.write(b"\n");
+    }
+    hasher.finish()
+}
+
+// Sprite names by sprite index
+fn sprite_names(game: &GameData) -> Vec<String> {
+    let mut names = vec![];
+    for (state, sprite) in &game.sprites {
+        let index = state.index as usize;
+        if names.len() <= index {
+            names.resize(index + 1, String::new());
+        }
+        names[index] = sprite.name.clone();
+    }
+    names
+}
+
+fn name_of(names: &[String], index: u16) -> &str {
+    names.get(index as usize).map(|n| n.as_str()).unwrap_or("?")
+}
+
+fn xy(pos: Position) -> [u16; 2] {
+    [pos.x, pos.y]
+}
+
+fn command_names(t: &TriggeredCommands) -> Vec<String> {
+    let flags = [
+        (t.again, "again"),
+        (t.cancel, "cancel"),
+        (t.checkpoint, "checkpoint"),
+        (t.restart, "restart"),
+        (t.win, "win"),
+    ];
+    let mut names: Vec<String> = flags
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    names.extend(t.sfx.iter().map(|seed| format!("sfx {}", seed)));
+    if let Some(message) = &t.message {
+        names.push(format!("message {}", message));
+    }
+    names
+}
+
+/// Writes what the engine does as JSON lines, one `Event` per line, so two runs (or this
+/// engine and another one) can be compared to find the first tick where they differ.
+/// The output is flushed at the end of every tick
+pub struct Tracer<W: Write> {
+    out: W,
+    tick: u64,
+    names: Vec<String>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            tick: 0,
+            names: vec![],
+            error: None,
+        }
+    }
+
+    /// Flushes the trace. Returns the first error that writing it ran into (the events after
+    /// an error are dropped)
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.out.flush(),
+        }
+    }
+
+    fn write(&mut self, event: &Event) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = serde_json::to_writer(&mut self.out, event)
+            .map_err(io::Error::from)
+            .and_then(|_| self.out.write_all(b"\n"));
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+
+    fn sprite(&self, index: u16) -> String {
+        name_of(&self.names, index).to_string()
+    }
+}
+
+impl<W: Write> EngineObserver for Tracer<W> {
+    fn tick_started(&mut self, game: &GameData, input: Option<EngineInput>, _board: &Board) {
+        if self.names.is_empty() {
+            self.names = sprite_names(game);
+        }
+        self.tick += 1;
+        self.write(&Event::TickStart {
+            tick: self.tick,
+            input: input.map(|i| i.to_string()),
+        });
+    }
+
+    fn rule_applied(
+        &mut self,
+        rule: &Rule,
+        _board: &Board,
+        _triggered: &TriggeredCommands,
+        matches: &[(usize, BracketMatch)],
+    ) {
+        let positions = rule
+            .matched_cells(matches)
+            .into_iter()
+            .map(|cells| cells.into_iter().map(xy).collect())
+            .collect();
+        self.write(&Event::RuleApplied {
+            tick: self.tick,
+            source_line_num: rule.source_line_num.map(|line| line + 1),
+            late: rule.late,
+            positions,
+        });
+    }
+
+    fn command_triggered(&mut self, rule: &Rule, commands: &TriggeredCommands) {
+        self.write(&Event::Commands {
+            tick: self.tick,
+            source_line_num: rule.source_line_num.map(|line| line + 1),
+            commands: command_names(commands),
+        });
+    }
+
+    fn sprite_moved(&mut self, sprite_index: u16, from: Position, to: Position) {
+        self.write(&Event::Moved {
+            tick: self.tick,
+            sprite: self.sprite(sprite_index),
+            from: xy(from),
+            to: xy(to),
+        });
+    }
+
+    fn movement_blocked(&mut self, sprite_index: u16, pos: Position) {
+        self.write(&Event::Blocked {
+            tick: self.tick,
+            sprite: self.sprite(sprite_index),
+            at: xy(pos),
+        });
+    }
+
+    fn tick_finished(&mut self, board: &Board, result: &TickResult) {
+        self.write(&Event::TickEnd {
+            tick: self.tick,
+            changed: result.changed,
+            won: result.completed_level.is_some(),
+            board_hash: format!("{:016x}", hash_board(&self.names, board)),
+        });
+        if self.error.is_none() {
+            if let Err(e) = self.out.flush() {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::Value;
+
+    use crate::engine::BoardOrMessage;
+    use crate::engine::Engine;
+    use crate::fixture;
+
+    fn board(engine: &Engine) -> &Board {
+        match &engine.current_level {
+            BoardOrMessage::Board(board) => board,
+            BoardOrMessage::Message(_) => panic!("The level is a message"),
+        }
+    }
+
+    fn trace(inputs: &[EngineInput]) -> (Engine, Vec<Value>) {
+        let rules = "[ > Player | Crate ] -> [ > Player | > Crate ] again";
+        let mut engine = fixture::engine(rules, "P.C.#");
+        let mut tracer = Tracer::new(vec![]);
+        for input in inputs {
+            engine.tick_with_observer(Some(*input), &mut tracer);
+        }
+        let lines = String::from_utf8(tracer.out).unwrap();
+        let events = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        (engine, events)
+    }
+
+    #[test]
+    fn writes_one_line_per_event() {
+        let (engine, events) = trace(&[EngineInput::Right, EngineInput::Right]);
+        let names: Vec<_> = events
+            .iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "tick_start",
+                "rule_applied",
+                "moved",
+                "tick_end",
+                "tick_start",
+                "rule_applied",
+                "commands",
+                "rule_applied",
+                // The push rule keeps matching the moving crate until the board stops changing
+                "commands",
+                "commands",
+                "moved",
+                "moved",
+                "tick_end",
+            ]
+        );
+        assert_eq!(events[0]["input"], "RIGHT");
+        // The engine's rule that moves the player has no source line
+        assert_eq!(events[1]["source_line_num"], Value::Null);
+        assert_eq!(events[2]["sprite"], "Player");
+        assert_eq!(events[2]["to"], serde_json::json!([1, 0]));
+        assert_eq!(events[6]["source_line_num"], fixture::FIRST_RULE_LINE);
+        assert_eq!(events[6]["commands"], serde_json::json!(["again"]));
+        assert_eq!(
+            events[7]["positions"],
+            serde_json::json!([[[1, 0], [2, 0]]])
+        );
+
+        let board = board(&engine);
+        let hash = format!("{:016x}", board_hash(&engine.game_data, board));
+        assert_eq!(events[12]["board_hash"], Value::String(hash));
+        assert_ne!(events[3]["board_hash"], events[12]["board_hash"]);
+    }
+
+    #[test]
+    fn hashes_the_sprite_names() {
+        let (engine, _) = trace(&[]);
+        let mut expected = FnvHasher::default();
+        expected.write(
+            b"Background Player\nBackground\nBackground Crate\nBackground\nBackground Wall\n",
+        );
+        let board = board(&engine);
+        assert_eq!(board_hash(&engine.game_data, board), expected.finish());
+    }
+}
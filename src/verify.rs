@@ -0,0 +1,365 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::panic;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time;
+
+use log::debug;
+
+use crate::engine::Engine;
+use crate::engine::EngineInput;
+use crate::engine::TickResult;
+use crate::model::game::GameData;
+use crate::parser;
+use crate::save::SaveState;
+use crate::snapshot;
+use crate::snapshot::Snapshot;
+use crate::snapshot::Snapshots;
+
+// Solutions that only dismiss a message. test_solutions.bash skips these too
+const TRIVIAL_SOLUTIONS: [&str; 9] = ["X", "!", "?", ",!", ".", ".,,,,,", ".!", "null", ""];
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Solved,
+    Failed(String),
+    Skipped(String),
+}
+
+/// The result of replaying one level (or a whole game when `index` is `None`)
+#[derive(Debug)]
+pub struct Report {
+    pub game: String,
+    pub index: Option<usize>,
+    pub outcome: Outcome,
+    pub elapsed: time::Duration,
+}
+
+// Same lines as test_solutions.bash writes to stats.txt. The reason a level failed is not part of
+// the line (the script only had the exit status of the game)
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.outcome, self.index) {
+            (Outcome::Solved, Some(index)) => write!(f, "Solved {} index={}", self.game, index),
+            (Outcome::Failed(_), Some(index)) => {
+                write!(f, "FAILED {} index={} status=101", self.game, index)
+            }
+            (Outcome::Failed(reason), None) => write!(f, "FAILED {} reason={}", self.game, reason),
+            (Outcome::Skipped(reason), Some(index)) => {
+                write!(
+                    f,
+                    "Skipping {} level={} because {}",
+                    self.game, index, reason
+                )
+            }
+            (Outcome::Skipped(reason), None) => {
+                write!(f, "Skipping {} because {}", self.game, reason)
+            }
+            (Outcome::Solved, None) => write!(f, "Solved {}", self.game),
+        }
+    }
+}
+
+impl Report {
+    /// The stats.txt line plus how long the level took (for `verify --timing`)
+    pub fn with_time(&self) -> String {
+        match (&self.outcome, self.index) {
+            (Outcome::Skipped(_), _) | (_, None) => self.to_string(),
+            _ => format!("{} time={}ms", self, self.elapsed.as_millis()),
+        }
+    }
+}
+
+/// Feeds the keys of a solution into the engine one tick at a time, the same way
+/// `--scripted` reads them from stdin. Returns true if a level was completed
+pub fn replay(engine: &mut Engine, keys: &str) -> bool {
+    replay_with(engine, keys, |_, _| {})
+}
+
+/// Same as `replay()` but calls `on_tick` after every tick (before moving on to the next level)
+pub fn replay_with<F: FnMut(&mut Engine, &TickResult)>(
+    engine: &mut Engine,
+    keys: &str,
+    mut on_tick: F,
+) -> bool {
+    let mut did_win = false;
+    let mut tick_without_input = engine
+        .game_data
+        .metadata
+        .run_rules_on_level_start
+        .unwrap_or(false);
+
+    for key in keys.chars() {
+        let input = match key {
+            'w' | 'W' => Some(EngineInput::Up),
+            's' | 'S' => Some(EngineInput::Down),
+            'a' | 'A' => Some(EngineInput::Left),
+            'd' | 'D' => Some(EngineInput::Right),
+            ' ' | 'x' | 'X' | '!' => Some(EngineInput::Action),
+            'z' | 'Z' | 'u' => Some(EngineInput::Undo),
+            'y' | 'Y' => Some(EngineInput::Redo),
+            'R' | 'r' => Some(EngineInput::Restart),
+            _ => None,
+        };
+        let mut should_tick = match key {
+            'q' | '$' => break,
+            // Keys that only change the terminal (and checkpoint markers) do not tick
+            '?' | 'h' | 'p' | 'c' | 'n' | '#' | '\n' | '~' | '`' | '\\' | '-' | '_' | '=' | '+' => {
+                false
+            }
+            _ => true,
+        };
+        if tick_without_input {
+            should_tick = true;
+        }
+        if !should_tick {
+            continue;
+        }
+
+        let tr = engine.tick(input);
+        tick_without_input = !tr.accepting_input;
+        on_tick(engine, &tr);
+
+        if tr.completed_level.is_some() {
+            did_win = true;
+            if !engine.next_level() {
+                break;
+            }
+        }
+    }
+    did_win
+}
+
+// A solution with `#` markers may have been saved at a checkpoint before the level was
+// completed. It passes when the replay reaches every checkpoint that was recorded
+fn replay_level(game_data: GameData, index: usize, keys: &str) -> (Outcome, Vec<Snapshot>) {
+    let recorded_checkpoints = keys.matches('#').count();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let mut engine = Engine::new(game_data, index as u8);
+        snapshot::record(&mut engine, keys)
+    }));
+    match result {
+        Ok((true, snapshots)) => (Outcome::Solved, snapshots),
+        Ok((false, snapshots)) if recorded_checkpoints > 0 => {
+            let reached = snapshots.iter().filter(|s| s.label != "end").count();
+            if reached >= recorded_checkpoints {
+                (Outcome::Solved, snapshots)
+            } else {
+                (
+                    Outcome::Failed(format!(
+                        "reached {} of {} checkpoints",
+                        reached, recorded_checkpoints
+                    )),
+                    vec![],
+                )
+            }
+        }
+        Ok((false, _)) => (
+            Outcome::Failed(String::from("level did not complete")),
+            vec![],
+        ),
+        Err(_) => (Outcome::Failed(String::from("panicked")), vec![]),
+    }
+}
+
+fn game_name(path: &Path) -> String {
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    file_name.trim_end_matches(".parsed.json").to_string()
+}
+
+/// Replays every level of a `*.parsed.json` game using the solutions in its `*.test-replay.json` file.
+/// The boards are compared with the golden snapshots in its `*.test-snapshots.txt` file (when
+/// there is one), or written into that file when `update_snapshots` is true
+pub fn verify_game(path: &Path, update_snapshots: bool) -> Vec<Report> {
+    let game = game_name(path);
+    let replay_path = format!("{}.test-replay.json", path.display());
+    let game_report = |outcome| {
+        vec![Report {
+            game: game.clone(),
+            index: None,
+            outcome,
+            elapsed: time::Duration::from_secs(0),
+        }]
+    };
+
+    if !Path::new(&replay_path).exists() {
+        return game_report(Outcome::Skipped(String::from("no solutions were found")));
+    }
+    let solutions = match SaveState::read_from_file(&replay_path) {
+        Ok(save) => save.inputs,
+        Err(e) => return game_report(Outcome::Failed(format!("{}", e))),
+    };
+
+    let snapshots_path = Snapshots::path_for(&path.display().to_string());
+    let mut golden = if Path::new(&snapshots_path).exists() {
+        match Snapshots::read_from_file(&snapshots_path) {
+            Ok(golden) => golden,
+            Err(e) if update_snapshots => {
+                debug!("Replacing {}", e);
+                Snapshots::default()
+            }
+            Err(e) => return game_report(Outcome::Failed(format!("{}", e))),
+        }
+    } else {
+        Snapshots::default()
+    };
+
+    let mut reports = vec![];
+    for (index, keys) in solutions.iter().enumerate() {
+        if TRIVIAL_SOLUTIONS.contains(&keys.as_str()) {
+            continue;
+        }
+
+        debug!("Replaying {} level {}", game, index);
+        let start = time::Instant::now();
+        // Parse the game for every level so a level that panics cannot affect the next one
+        let outcome = match File::open(path)
+            .map_err(|e| e.into())
+            .and_then(|f| parser::parse(BufReader::new(f)))
+        {
+            Ok(game_data) => match replay_level(game_data, index, keys) {
+                (Outcome::Solved, snapshots) if update_snapshots => {
+                    golden.levels.insert(index, snapshots);
+                    Outcome::Solved
+                }
+                (Outcome::Solved, snapshots) => match golden.levels.get(&index) {
+                    Some(expected) => match snapshot::compare(expected, &snapshots) {
+                        Some(reason) => Outcome::Failed(reason),
+                        None => Outcome::Solved,
+                    },
+                    None => Outcome::Solved,
+                },
+                (outcome, _) => outcome,
+            },
+            Err(e) => Outcome::Failed(format!("{}", e)),
+        };
+        reports.push(Report {
+            game: game.clone(),
+            index: Some(index),
+            outcome,
+            elapsed: start.elapsed(),
+        });
+    }
+
+    if update_snapshots && !golden.levels.is_empty() {
+        if let Err(e) = golden.write_to_file(&snapshots_path) {
+            reports.extend(game_report(Outcome::Failed(format!("{}", e))));
+        }
+    }
+    reports
+}
+
+/// Runs `verify_game` on each path using `jobs` threads. The reports are sorted by game and level
+pub fn verify_games(paths: Vec<PathBuf>, jobs: usize, update_snapshots: bool) -> Vec<Report> {
+    let queue = Arc::new(Mutex::new(paths.into_iter().collect::<VecDeque<_>>()));
+    let (tx, rx) = mpsc::channel();
+
+    let handles: Vec<_> = (0..jobs.max(1))
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            thread::spawn(move || loop {
+                let path = queue.lock().unwrap().pop_front();
+                match path {
+                    None => break,
+                    Some(path) => tx.send(verify_game(&path, update_snapshots)).unwrap(),
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let mut reports: Vec<Report> = rx.iter().flatten().collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    reports.sort_by(|a, b| (&a.game, a.index).cmp(&(&b.game, b.index)));
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture;
+
+    const RULES: &str = "[ > Player | Target ] -> [ > Player | Target ] again";
+
+    fn build_engine(level: u8) -> Engine {
+        Engine::new(fixture::game(RULES, "message hello\n\nP..T"), level)
+    }
+
+    #[test]
+    fn replays_a_solution() {
+        assert!(replay(&mut build_engine(1), "DDD"));
+        assert!(replay(&mut build_engine(1), "d.d.d."));
+        assert!(!replay(&mut build_engine(1), "DD"));
+        assert!(replay(&mut build_engine(0), "X"));
+    }
+
+    #[test]
+    fn ignores_terminal_keys() {
+        assert!(!replay(&mut build_engine(1), "D?D#"));
+        assert!(replay(&mut build_engine(1), "D?D#D"));
+    }
+
+    #[test]
+    fn accepts_solutions_saved_at_a_checkpoint() {
+        let rules = format!("{}\nlate [ Player Flag ] -> [ Player ] checkpoint", RULES);
+        let game = || fixture::game(&rules, "message hello\n\nPF.T");
+        let labels = |(outcome, snapshots): (Outcome, Vec<Snapshot>)| {
+            let labels: Vec<String> = snapshots.into_iter().map(|s| s.label).collect();
+            (outcome, labels)
+        };
+
+        assert_eq!(
+            labels(replay_level(game(), 1, "D#")),
+            (Outcome::Solved, vec![String::from("checkpoint 1")])
+        );
+        assert_eq!(
+            labels(replay_level(game(), 1, "D#DD")),
+            (
+                Outcome::Solved,
+                vec![String::from("checkpoint 1"), String::from("end")]
+            )
+        );
+        assert_eq!(
+            labels(replay_level(game(), 1, "#")),
+            (
+                Outcome::Failed(String::from("reached 0 of 1 checkpoints")),
+                vec![]
+            )
+        );
+    }
+
+    #[test]
+    fn formats_like_stats_txt() {
+        let report = Report {
+            game: String::from("pot-wash-panic"),
+            index: Some(3),
+            outcome: Outcome::Solved,
+            elapsed: time::Duration::from_millis(12),
+        };
+        assert_eq!(format!("{}", report), "Solved pot-wash-panic index=3");
+        assert_eq!(
+            report.with_time(),
+            "Solved pot-wash-panic index=3 time=12ms"
+        );
+
+        let report = Report {
+            outcome: Outcome::Failed(String::from("level did not complete")),
+            ..report
+        };
+        assert_eq!(
+            format!("{}", report),
+            "FAILED pot-wash-panic index=3 status=101"
+        );
+    }
+}
@@ -31,4 +31,4 @@ echo "..........q" | try bench 4 cargo run --release ./games/skipping-stones.par
 
 try cargo test
 
-try bench 300 ./test_solutions.bash
+try bench 300 cargo run --release -- verify